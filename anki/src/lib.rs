@@ -0,0 +1,571 @@
+// Reading and writing of Anki packages (.apkg), which are zip files with the collection
+// in a SQLite database. Each note becomes a QA, scheduled like its first card.
+
+mod write;
+
+pub use write::{card_state, export_notes, text_to_html, write, CardState};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use rusqlite::{Connection, OpenFlags};
+use serde::Deserialize;
+use zip::ZipArchive;
+
+use message::Schedule;
+
+// Collections in the order they're looked for. Packages that Anki exports for newer
+// versions also contain a collection.anki2 that only says to update Anki.
+const COLLECTIONS: [&str; 2] = ["collection.anki21", "collection.anki2"];
+
+// Newer Anki versions compress the collection, which isn't supported.
+const COMPRESSED_COLLECTION: &str = "collection.anki21b";
+
+// Intervals in days from which a card counts as shown correctly twice and as mature,
+// to map intervals to correct_count.
+const YOUNG_IVL: i64 = 7;
+const MATURE_IVL: i64 = 21;
+
+// correct_count of mature QAs, which is the default max of a QA.
+const MATURE_CORRECT_COUNT: u8 = 3;
+
+// Which note field the question and answer come from, by name or by number counting
+// from 1.
+#[derive(Debug, Clone)]
+pub struct FieldMap {
+    pub q: String,
+    pub a: String,
+}
+
+impl Default for FieldMap {
+    fn default() -> Self {
+        Self {
+            q: "1".to_string(),
+            a: "2".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Note {
+    pub id: i64,
+    pub q: String,
+    pub a: String,
+    pub deck: Option<String>,
+    pub tags: Vec<String>,
+    pub schedule: Schedule,
+    pub reviews: Vec<Review>,
+}
+
+// A review from the review log of Anki, in the terms of PastReview.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Review {
+    pub reviewed_at: i64,
+    pub correct: bool,
+    pub kind: u8,
+    pub response_ms: Option<u32>,
+}
+
+// A note that couldn't be read, and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    pub id: i64,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Package {
+    pub notes: Vec<Note>,
+    pub skipped: Vec<Skipped>,
+}
+
+// A card as stored in the cards table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Card {
+    pub id: i64,
+    // 0 (new), 1 (learning), 2 (review) or 3 (relearning).
+    pub kind: i64,
+    // -1 when suspended.
+    pub queue: i64,
+    // Days, or seconds if negative.
+    pub ivl: i64,
+    pub lapses: i64,
+    // Seconds since the epoch.
+    pub modified: i64,
+}
+
+// A review as stored in the revlog table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Revlog {
+    // Milliseconds since the epoch, when the review happened.
+    pub id: i64,
+    // The button pressed, from 1 (again) to 4 (easy). 0 for rescheduling.
+    pub ease: i64,
+    // Milliseconds taken to answer.
+    pub time: i64,
+    // 0 (learning), 1 (review), 2 (relearning), 3 (filtered) or 4 (rescheduled).
+    pub kind: i64,
+}
+
+#[derive(Deserialize)]
+struct LegacyModel {
+    flds: Vec<LegacyField>,
+}
+
+#[derive(Deserialize)]
+struct LegacyField {
+    name: String,
+    ord: usize,
+}
+
+#[derive(Deserialize)]
+struct LegacyDeck {
+    name: String,
+}
+
+// Reads the notes of the package in bytes.
+pub fn read(bytes: &[u8], fields: &FieldMap) -> anyhow::Result<Package> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).context("Not an Anki package")?;
+    let Some(name) = COLLECTIONS
+        .into_iter()
+        .find(|name| archive.index_for_name(name).is_some())
+    else {
+        bail!("The package has no collection");
+    };
+    if name == "collection.anki2" && archive.index_for_name(COMPRESSED_COLLECTION).is_some() {
+        bail!("The package is for newer Anki versions, export it with support for older ones");
+    }
+
+    let mut collection = Vec::new();
+    archive.by_name(name)?.read_to_end(&mut collection)?;
+
+    // SQLite can only open files, so the collection is written to a temporary one.
+    let file = TempFile::new()?;
+    fs::write(&file.0, collection)?;
+    let conn = Connection::open_with_flags(&file.0, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    read_collection(&conn, fields)
+}
+
+fn read_collection(conn: &Connection, fields: &FieldMap) -> anyhow::Result<Package> {
+    let field_names = field_names(conn)?;
+    let decks = deck_names(conn)?;
+    let cards = first_cards(conn)?;
+    let mut reviews = reviews(conn)?;
+
+    let mut package = Package::default();
+    let mut stmt = conn.prepare("SELECT id, mid, tags, flds FROM notes ORDER BY id")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let model: i64 = row.get(1)?;
+        let tags: String = row.get(2)?;
+        let flds: String = row.get(3)?;
+
+        let Some(&(deck, card)) = cards.get(&id) else {
+            package.skipped.push(Skipped {
+                id,
+                reason: "The note has no cards".to_string(),
+            });
+            continue;
+        };
+
+        let names = field_names.get(&model).map(Vec::as_slice).unwrap_or(&[]);
+        let values: Vec<&str> = flds.split('\u{1f}').collect();
+        let field = |spec: &str| {
+            resolve_field(spec, names)
+                .and_then(|i| values.get(i).copied())
+                .map(html_to_text)
+                .ok_or_else(|| format!("The note has no field {spec:?}"))
+        };
+        let (q, a) = match (field(&fields.q), field(&fields.a)) {
+            (Ok(q), _) if has_cloze(&q) => cloze(&q),
+            (Ok(q), Ok(a)) => (q, a),
+            (Err(reason), _) | (_, Err(reason)) => {
+                package.skipped.push(Skipped { id, reason });
+                continue;
+            }
+        };
+        if q.is_empty() || a.is_empty() {
+            package.skipped.push(Skipped {
+                id,
+                reason: "The question or answer is empty".to_string(),
+            });
+            continue;
+        }
+
+        let card_reviews = review_kinds(reviews.remove(&card.id).unwrap_or_default());
+        let last_review = card_reviews.last().map(|r| r.reviewed_at / 1000);
+        package.notes.push(Note {
+            id,
+            q,
+            a,
+            deck: decks.get(&deck).cloned(),
+            tags: tags.split_whitespace().map(str::to_string).collect(),
+            schedule: schedule(&card, id / 1000, last_review),
+            reviews: card_reviews,
+        });
+    }
+
+    Ok(package)
+}
+
+// Returns the field names of each note type. Older collections keep the note types as
+// JSON in the col table, newer ones in tables of their own.
+fn field_names(conn: &Connection) -> anyhow::Result<HashMap<i64, Vec<String>>> {
+    let mut names: HashMap<i64, Vec<String>> = HashMap::new();
+    if table_exists(conn, "fields")? {
+        let mut stmt = conn.prepare("SELECT ntid, name FROM fields ORDER BY ntid, ord")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            names.entry(row.get(0)?).or_default().push(row.get(1)?);
+        }
+        return Ok(names);
+    }
+
+    let models: String = conn.query_row("SELECT models FROM col", [], |row| row.get(0))?;
+    let models: HashMap<String, LegacyModel> = serde_json::from_str(&models)?;
+    for (id, mut model) in models {
+        model.flds.sort_by_key(|f| f.ord);
+        let fields = model.flds.into_iter().map(|f| f.name).collect();
+        names.insert(id.parse()?, fields);
+    }
+    Ok(names)
+}
+
+// Returns the names of the decks, with subdecks separated by "::" like Anki shows them.
+fn deck_names(conn: &Connection) -> anyhow::Result<HashMap<i64, String>> {
+    let mut names = HashMap::new();
+    if table_exists(conn, "decks")? {
+        let mut stmt = conn.prepare("SELECT id, name FROM decks")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(1)?;
+            names.insert(row.get(0)?, name.replace('\u{1f}', "::"));
+        }
+        return Ok(names);
+    }
+
+    let decks: String = conn.query_row("SELECT decks FROM col", [], |row| row.get(0))?;
+    let decks: HashMap<String, LegacyDeck> = serde_json::from_str(&decks)?;
+    for (id, deck) in decks {
+        names.insert(id.parse()?, deck.name);
+    }
+    Ok(names)
+}
+
+// Returns the deck and first card of each note. Notes with several cards, like ones
+// with a reverse card, are scheduled like their first one.
+fn first_cards(conn: &Connection) -> anyhow::Result<HashMap<i64, (i64, Card)>> {
+    let mut cards = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT nid, did, id, type, queue, ivl, lapses, mod FROM cards ORDER BY nid, ord DESC",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let card = Card {
+            id: row.get(2)?,
+            kind: row.get(3)?,
+            queue: row.get(4)?,
+            ivl: row.get(5)?,
+            lapses: row.get(6)?,
+            modified: row.get(7)?,
+        };
+        cards.insert(row.get(0)?, (row.get(1)?, card));
+    }
+    Ok(cards)
+}
+
+// Returns the reviews of each card in the order they happened.
+fn reviews(conn: &Connection) -> anyhow::Result<HashMap<i64, Vec<Revlog>>> {
+    let mut reviews: HashMap<i64, Vec<_>> = HashMap::new();
+    let mut stmt = conn.prepare("SELECT cid, id, ease, time, type FROM revlog ORDER BY id")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let review = Revlog {
+            id: row.get(1)?,
+            ease: row.get(2)?,
+            time: row.get(3)?,
+            kind: row.get(4)?,
+        };
+        reviews.entry(row.get(0)?).or_default().push(review);
+    }
+    Ok(reviews)
+}
+
+fn table_exists(conn: &Connection, name: &str) -> anyhow::Result<bool> {
+    let n: i64 = conn.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |row| row.get(0),
+    )?;
+    Ok(n > 0)
+}
+
+// Maps the reviews of a card to the kinds of the review log. The first learning review
+// is when the card was new. Rescheduling entries, which aren't reviews, are dropped.
+pub fn review_kinds(reviews: Vec<Revlog>) -> Vec<Review> {
+    let mut seen_new = false;
+    reviews
+        .into_iter()
+        .filter_map(|r| {
+            let kind = match r.kind {
+                _ if r.ease == 0 => return None,
+                0 if !seen_new => 0,
+                0 => 1,
+                1 => 2,
+                2 => 3,
+                3 => 4,
+                _ => return None,
+            };
+            seen_new = true;
+            Some(Review {
+                reviewed_at: r.id,
+                correct: r.ease > 1,
+                kind,
+                response_ms: u32::try_from(r.time).ok().filter(|&ms| ms > 0),
+            })
+        })
+        .collect()
+}
+
+// Maps the state of a card to a schedule. Review cards count as shown correctly more
+// times the longer their interval is.
+pub fn schedule(card: &Card, created_at: i64, last_review: Option<i64>) -> Schedule {
+    let correct_count = match card.kind {
+        2 | 3 if card.ivl >= MATURE_IVL => MATURE_CORRECT_COUNT,
+        2 | 3 if card.ivl >= YOUNG_IVL => 2,
+        2 | 3 => 1,
+        _ => 0,
+    };
+    let last_shown_at = match card.kind {
+        0 => None,
+        _ => Some(last_review.unwrap_or(card.modified)),
+    };
+
+    Schedule {
+        created_at,
+        last_shown_at,
+        correct_count,
+        lapses: card.lapses.clamp(0, u16::MAX as i64) as u16,
+        learning: matches!(card.kind, 1 | 3),
+        suspended: card.queue == -1,
+    }
+}
+
+fn resolve_field(spec: &str, names: &[String]) -> Option<usize> {
+    let spec = spec.trim();
+    match spec.parse::<usize>() {
+        Ok(n) => n.checked_sub(1),
+        Err(_) => names.iter().position(|n| n.eq_ignore_ascii_case(spec)),
+    }
+}
+
+// Converts a field to plain text: line breaks are kept, other markup and sounds are
+// dropped and entities decoded.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            let Some(end) = rest.find('>') else {
+                text.push_str(rest);
+                break;
+            };
+            let tag = rest[1..end].trim().to_ascii_lowercase();
+            let name = tag.trim_start_matches('/').split([' ', '/']).next();
+            if matches!(name, Some("br" | "div" | "p" | "li")) && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            rest = &rest[end + 1..];
+        } else if rest.starts_with("[sound:") {
+            match rest.find(']') {
+                Some(end) => rest = &rest[end + 1..],
+                None => break,
+            }
+        } else if c == '&' {
+            let entity = rest
+                .find(';')
+                .filter(|&end| end <= 8)
+                .map(|end| &rest[..=end]);
+            match entity.and_then(decode_entity) {
+                Some(decoded) => {
+                    text.push(decoded);
+                    rest = &rest[entity.unwrap().len()..];
+                }
+                None => {
+                    text.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        } else {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    text.trim().to_string()
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    let name = &entity[1..entity.len() - 1];
+    match name {
+        "nbsp" => Some(' '),
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+fn has_cloze(text: &str) -> bool {
+    text.contains("{{c") && text.contains("::")
+}
+
+// Turns a cloze like "{{c1::Helsinki::city}} is the capital" into a question with the
+// deletions hidden, showing their hints if they have one, and an answer with them
+// revealed.
+pub fn cloze(text: &str) -> (String, String) {
+    let (mut q, mut a) = (String::new(), String::new());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{c") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let inner = &rest[start + 2..start + len];
+        let Some((_, deletion)) = inner.split_once("::") else {
+            break;
+        };
+        let (answer, hint) = deletion.split_once("::").unwrap_or((deletion, "..."));
+
+        q.push_str(&rest[..start]);
+        a.push_str(&rest[..start]);
+        q.push_str(&format!("[{hint}]"));
+        a.push_str(answer);
+        rest = &rest[start + len + 2..];
+    }
+    q.push_str(rest);
+    a.push_str(rest);
+    (q, a)
+}
+
+// A file in the temporary directory that's removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new() -> anyhow::Result<Self> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.subsec_nanos();
+        let name = format!("memryze-{}-{nanos}.anki2", process::id());
+        Ok(Self(std::env::temp_dir().join(name)))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(
+            html_to_text("<div>Hyvää&nbsp;päivää</div><div>good&amp;day<br/></div>"),
+            "Hyvää päivää\ngood&day"
+        );
+        assert_eq!(html_to_text("kissa [sound:kissa.mp3]"), "kissa");
+        assert_eq!(html_to_text("a &lt; b &#228; &#xe4; & c"), "a < b ä ä & c");
+        assert_eq!(html_to_text("<b>bold</b> <i>text"), "bold text");
+    }
+
+    #[test]
+    fn test_cloze() {
+        assert_eq!(
+            cloze("{{c1::Helsinki::city}} is the capital of {{c2::Finland}}"),
+            (
+                "[city] is the capital of [...]".to_string(),
+                "Helsinki is the capital of Finland".to_string()
+            )
+        );
+        assert!(has_cloze("{{c1::x}}"));
+        assert!(!has_cloze("plain"));
+    }
+
+    #[test]
+    fn test_schedule() {
+        let card = Card {
+            kind: 2,
+            ivl: 30,
+            lapses: 2,
+            queue: -1,
+            modified: 50,
+            ..Default::default()
+        };
+        let schedule = schedule(&card, 10, Some(40));
+        assert_eq!(
+            schedule,
+            Schedule {
+                created_at: 10,
+                last_shown_at: Some(40),
+                correct_count: 3,
+                lapses: 2,
+                learning: false,
+                suspended: true,
+            }
+        );
+
+        let card = Card {
+            kind: 3,
+            ivl: 8,
+            modified: 50,
+            ..Default::default()
+        };
+        let schedule = super::schedule(&card, 10, None);
+        assert_eq!(
+            (
+                schedule.correct_count,
+                schedule.learning,
+                schedule.last_shown_at
+            ),
+            (2, true, Some(50))
+        );
+
+        let schedule = super::schedule(&Card::default(), 10, None);
+        assert_eq!((schedule.correct_count, schedule.last_shown_at), (0, None));
+    }
+
+    #[test]
+    fn test_review_kinds() {
+        let revlog = |id, ease, time, kind| Revlog {
+            id,
+            ease,
+            time,
+            kind,
+        };
+        let reviews = review_kinds(vec![
+            revlog(1000, 1, 5000, 0),
+            revlog(2000, 3, 0, 0),
+            revlog(3000, 0, 0, 4),
+            revlog(4000, 2, 70000, 1),
+            revlog(5000, 1, 3000, 2),
+        ]);
+        let kinds: Vec<_> = reviews.iter().map(|r| (r.kind, r.correct)).collect();
+        assert_eq!(kinds, vec![(0, false), (1, true), (2, true), (3, false)]);
+        assert_eq!(reviews[0].response_ms, Some(5000));
+        assert_eq!(reviews[1].response_ms, None);
+    }
+}
@@ -0,0 +1,506 @@
+// Writing of Anki packages with the legacy collection schema, which all Anki versions
+// can import. Each QA becomes a note of a basic note type with one card, scheduled like
+// the QA.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{Cursor, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use message::{ExportedQA, PastReview, Schedule};
+
+use crate::{Note, Review, TempFile, MATURE_CORRECT_COUNT, MATURE_IVL, YOUNG_IVL};
+
+// Ids are fixed so that importing a later export again updates the same note type.
+const MODEL_ID: i64 = 1_731_000_000_000;
+const DEFAULT_DECK_ID: i64 = 1;
+const DEFAULT_DECK: &str = "Default";
+
+const SCHEMA: &str = "
+CREATE TABLE col (id integer PRIMARY KEY, crt integer NOT NULL, mod integer NOT NULL,
+    scm integer NOT NULL, ver integer NOT NULL, dty integer NOT NULL, usn integer NOT NULL,
+    ls integer NOT NULL, conf text NOT NULL, models text NOT NULL, decks text NOT NULL,
+    dconf text NOT NULL, tags text NOT NULL);
+CREATE TABLE notes (id integer PRIMARY KEY, guid text NOT NULL, mid integer NOT NULL,
+    mod integer NOT NULL, usn integer NOT NULL, tags text NOT NULL, flds text NOT NULL,
+    sfld text NOT NULL, csum integer NOT NULL, flags integer NOT NULL, data text NOT NULL);
+CREATE TABLE cards (id integer PRIMARY KEY, nid integer NOT NULL, did integer NOT NULL,
+    ord integer NOT NULL, mod integer NOT NULL, usn integer NOT NULL, type integer NOT NULL,
+    queue integer NOT NULL, due integer NOT NULL, ivl integer NOT NULL,
+    factor integer NOT NULL, reps integer NOT NULL, lapses integer NOT NULL,
+    left integer NOT NULL, odue integer NOT NULL, odid integer NOT NULL,
+    flags integer NOT NULL, data text NOT NULL);
+CREATE TABLE revlog (id integer PRIMARY KEY, cid integer NOT NULL, usn integer NOT NULL,
+    ease integer NOT NULL, ivl integer NOT NULL, lastIvl integer NOT NULL,
+    factor integer NOT NULL, time integer NOT NULL, type integer NOT NULL);
+CREATE TABLE graves (usn integer NOT NULL, oid integer NOT NULL, type integer NOT NULL);
+CREATE INDEX ix_notes_usn ON notes (usn);
+CREATE INDEX ix_cards_usn ON cards (usn);
+CREATE INDEX ix_revlog_usn ON revlog (usn);
+CREATE INDEX ix_cards_nid ON cards (nid);
+CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+CREATE INDEX ix_revlog_cid ON revlog (cid);
+CREATE INDEX ix_notes_csum ON notes (csum);
+";
+
+// The scheduling columns of a card.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CardState {
+    pub kind: i64,
+    pub queue: i64,
+    pub due: i64,
+    pub ivl: i64,
+    pub left: i64,
+}
+
+// Turns exported QAs into notes along with their reviews, which are in the order they
+// happened.
+pub fn export_notes(qas: Vec<ExportedQA>, reviews: &[PastReview]) -> Vec<Note> {
+    let mut by_qa: HashMap<i64, Vec<Review>> = HashMap::new();
+    for r in reviews {
+        by_qa.entry(r.qa_id).or_default().push(Review {
+            reviewed_at: r.reviewed_at,
+            correct: r.correct,
+            kind: r.kind,
+            response_ms: r.response_ms,
+        });
+    }
+    qas.into_iter()
+        .map(|qa| Note {
+            reviews: by_qa.remove(&qa.id).unwrap_or_default(),
+            id: qa.id,
+            q: qa.q,
+            a: qa.a,
+            deck: qa.deck,
+            tags: qa.tags,
+            schedule: qa.schedule,
+        })
+        .collect()
+}
+
+// Writes the notes to a package. Their ids are those of the QAs, which keep the notes
+// the same across exports.
+pub fn write(notes: &[Note]) -> anyhow::Result<Vec<u8>> {
+    let file = TempFile::new()?;
+    let conn = Connection::open(&file.0)?;
+    write_collection(&conn, notes)?;
+    conn.close().map_err(|(_, err)| err)?;
+    let collection = fs::read(&file.0)?;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("collection.anki2", SimpleFileOptions::default())?;
+    zip.write_all(&collection)?;
+    zip.start_file("media", SimpleFileOptions::default())?;
+    zip.write_all(b"{}")?;
+    Ok(zip.finish()?.into_inner())
+}
+
+fn write_collection(conn: &Connection, notes: &[Note]) -> anyhow::Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    // Due days of review cards count from the day the collection was created.
+    let crt = notes
+        .iter()
+        .map(|n| n.schedule.created_at)
+        .min()
+        .unwrap_or(now);
+    let crt = crt - crt.rem_euclid(86400);
+
+    let deck_ids = deck_ids(notes, now * 1000);
+    conn.execute_batch(SCHEMA)?;
+    conn.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            crt,
+            now * 1000,
+            json!({
+                "nextPos": notes.len() + 1,
+                "curDeck": DEFAULT_DECK_ID,
+                "activeDecks": [DEFAULT_DECK_ID],
+                "curModel": MODEL_ID,
+                "sortType": "noteFld",
+                "sortBackwards": false,
+                "addToCur": true,
+                "newSpread": 0,
+                "collapseTime": 1200,
+                "timeLim": 0,
+                "estTimes": true,
+                "dueCounts": true,
+            })
+            .to_string(),
+            models(now).to_string(),
+            decks(&deck_ids, now).to_string(),
+            deck_config(now).to_string(),
+        ],
+    )?;
+
+    // Anki ids are timestamps in milliseconds, so notes and reviews get the time they
+    // were created at or happened, moved forward until they're unique.
+    let mut used = HashSet::new();
+    let mut unique = |id: i64| {
+        let mut id = id;
+        while !used.insert(id) {
+            id += 1;
+        }
+        id
+    };
+
+    let mut note_stmt =
+        conn.prepare("INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')")?;
+    let mut card_stmt = conn.prepare(
+        "INSERT INTO cards VALUES (?1, ?1, ?2, 0, ?3, -1, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, \
+            0, 0, 0, '')",
+    )?;
+    let mut revlog_stmt =
+        conn.prepare("INSERT INTO revlog VALUES (?1, ?2, -1, ?3, ?4, 0, ?5, ?6, ?7)")?;
+    for (position, note) in notes.iter().enumerate() {
+        let s = &note.schedule;
+        let nid = unique(s.created_at * 1000);
+        let tags = match note.tags.is_empty() {
+            true => String::new(),
+            false => format!(" {} ", note.tags.join(" ")),
+        };
+        let flds = format!("{}\u{1f}{}", text_to_html(&note.q), text_to_html(&note.a));
+        note_stmt.execute(params![
+            nid,
+            format!("memryze-{}", note.id),
+            MODEL_ID,
+            now,
+            tags,
+            flds,
+            note.q,
+            checksum(&note.q),
+        ])?;
+
+        let state = card_state(s, crt, now, position as i64 + 1);
+        let did = note
+            .deck
+            .as_ref()
+            .and_then(|d| deck_ids.get(d))
+            .copied()
+            .unwrap_or(DEFAULT_DECK_ID);
+        let factor = if state.kind == 0 { 0 } else { 2500 };
+        card_stmt.execute(params![
+            nid,
+            did,
+            s.last_shown_at.unwrap_or(s.created_at),
+            state.kind,
+            state.queue,
+            state.due,
+            state.ivl,
+            factor,
+            note.reviews.len(),
+            s.lapses,
+            state.left,
+        ])?;
+
+        for review in &note.reviews {
+            revlog_stmt.execute(params![
+                unique(review.reviewed_at),
+                nid,
+                if review.correct { 3 } else { 1 },
+                state.ivl,
+                factor,
+                review.response_ms.unwrap_or(0),
+                revlog_kind(review.kind),
+            ])?;
+        }
+    }
+
+    Ok(())
+}
+
+// Maps a schedule to the state of a card, the other way around from schedule. New cards
+// are due in the order they're exported, learning ones right away and review ones an
+// interval after they were last shown.
+pub fn card_state(schedule: &Schedule, crt: i64, now: i64, position: i64) -> CardState {
+    let ivl = match schedule.correct_count {
+        c if c >= MATURE_CORRECT_COUNT => MATURE_IVL,
+        2 => YOUNG_IVL,
+        _ => 1,
+    };
+    let mut state = match schedule.last_shown_at {
+        None => CardState {
+            kind: 0,
+            queue: 0,
+            due: position,
+            ivl: 0,
+            left: 0,
+        },
+        Some(_) if schedule.learning => CardState {
+            kind: if schedule.lapses > 0 { 3 } else { 1 },
+            queue: 1,
+            due: now,
+            ivl: if schedule.lapses > 0 { ivl } else { 0 },
+            left: 1001,
+        },
+        Some(shown) => CardState {
+            kind: 2,
+            queue: 2,
+            due: (shown - crt).div_euclid(86400) + ivl,
+            ivl,
+            left: 0,
+        },
+    };
+    if schedule.suspended {
+        state.queue = -1;
+    }
+    state
+}
+
+// Maps a kind of the review log to the type of an Anki review. Practice reviews, which
+// don't change scheduling, are like reviews in filtered decks.
+fn revlog_kind(kind: u8) -> i64 {
+    match kind {
+        0 | 1 => 0,
+        2 => 1,
+        3 => 2,
+        _ => 3,
+    }
+}
+
+// Returns the ids of the decks and their parents, which Anki shows as one tree. A deck
+// named Default is Anki's own, which notes without a deck go to.
+fn deck_ids(notes: &[Note], first_id: i64) -> BTreeMap<String, i64> {
+    let mut names = BTreeMap::new();
+    let decks = notes.iter().filter_map(|n| n.deck.as_deref());
+    for deck in decks.filter(|&d| d != DEFAULT_DECK) {
+        let parts: Vec<&str> = deck.split("::").collect();
+        for i in 1..=parts.len() {
+            names.entry(parts[..i].join("::")).or_insert(0);
+        }
+    }
+    for (i, id) in names.values_mut().enumerate() {
+        *id = first_id + i as i64;
+    }
+    names
+}
+
+fn decks(deck_ids: &BTreeMap<String, i64>, now: i64) -> serde_json::Value {
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id,
+            "name": name,
+            "desc": "",
+            "mod": now,
+            "usn": -1,
+            "conf": 1,
+            "dyn": 0,
+            "collapsed": false,
+            "browserCollapsed": false,
+            "extendNew": 0,
+            "extendRev": 0,
+            "newToday": [0, 0],
+            "revToday": [0, 0],
+            "lrnToday": [0, 0],
+            "timeToday": [0, 0],
+        })
+    };
+    let mut decks = serde_json::Map::new();
+    decks.insert(
+        DEFAULT_DECK_ID.to_string(),
+        deck(DEFAULT_DECK_ID, "Default"),
+    );
+    for (name, &id) in deck_ids {
+        decks.insert(id.to_string(), deck(id, name));
+    }
+    decks.into()
+}
+
+fn models(now: i64) -> serde_json::Value {
+    let field = |name: &str, ord: i64| {
+        json!({
+            "name": name,
+            "ord": ord,
+            "sticky": false,
+            "rtl": false,
+            "font": "Arial",
+            "size": 20,
+            "media": [],
+        })
+    };
+    json!({
+        MODEL_ID.to_string(): {
+            "id": MODEL_ID,
+            "name": "Memryze",
+            "type": 0,
+            "mod": now,
+            "usn": -1,
+            "sortf": 0,
+            "did": DEFAULT_DECK_ID,
+            "flds": [field("Front", 0), field("Back", 1)],
+            "tmpls": [{
+                "name": "Card 1",
+                "ord": 0,
+                "qfmt": "{{Front}}",
+                "afmt": "{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}",
+                "bqfmt": "",
+                "bafmt": "",
+                "did": null,
+            }],
+            "css": ".card { font-family: arial; font-size: 20px; text-align: center; }",
+            "latexPre": "\\documentclass[12pt]{article}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "req": [[0, "any", [0]]],
+            "tags": [],
+            "vers": [],
+        }
+    })
+}
+
+fn deck_config(now: i64) -> serde_json::Value {
+    json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": now,
+            "usn": -1,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": {
+                "delays": [1.0, 10.0],
+                "ints": [1, 4, 7],
+                "initialFactor": 2500,
+                "order": 1,
+                "perDay": 20,
+                "bury": false,
+            },
+            "rev": {
+                "perDay": 200,
+                "ease4": 1.3,
+                "ivlFct": 1.0,
+                "maxIvl": 36500,
+                "hardFactor": 1.2,
+                "bury": false,
+            },
+            "lapse": {
+                "delays": [10.0],
+                "mult": 0.0,
+                "minInt": 1,
+                "leechFails": 8,
+                "leechAction": 1,
+            },
+        }
+    })
+}
+
+// Converts plain text to a field, the other way around from html_to_text.
+pub fn text_to_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>")
+}
+
+// Anki finds duplicates by the first 8 hex digits of the SHA-1 of the sort field.
+fn checksum(text: &str) -> i64 {
+    let hash = Sha1::digest(text.as_bytes());
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read, FieldMap, Review};
+
+    #[test]
+    fn test_card_state() {
+        let day = 86400;
+        let schedule = Schedule {
+            created_at: 0,
+            last_shown_at: Some(10 * day + 5),
+            correct_count: 3,
+            ..Default::default()
+        };
+        let state = card_state(&schedule, 0, 20 * day, 1);
+        assert_eq!(
+            (state.kind, state.queue, state.due),
+            (2, 2, 10 + MATURE_IVL)
+        );
+
+        let schedule = Schedule {
+            learning: true,
+            lapses: 1,
+            suspended: true,
+            ..schedule
+        };
+        let state = card_state(&schedule, 0, 20 * day, 1);
+        assert_eq!((state.kind, state.queue, state.due), (3, -1, 20 * day));
+
+        let state = card_state(&Schedule::default(), 0, 20 * day, 4);
+        assert_eq!((state.kind, state.queue, state.due), (0, 0, 4));
+    }
+
+    #[test]
+    fn test_write_read() {
+        let notes = vec![
+            Note {
+                id: 2,
+                q: "a < b & c".to_string(),
+                a: "two\nlines".to_string(),
+                deck: Some("Finnish::Animals".to_string()),
+                tags: vec!["a1".to_string(), "animals".to_string()],
+                schedule: Schedule {
+                    created_at: 1_700_000_000,
+                    last_shown_at: Some(1_700_100_000),
+                    correct_count: 2,
+                    lapses: 1,
+                    ..Default::default()
+                },
+                reviews: vec![
+                    Review {
+                        reviewed_at: 1_700_050_000_000,
+                        correct: false,
+                        kind: 0,
+                        response_ms: Some(4000),
+                    },
+                    Review {
+                        reviewed_at: 1_700_100_000_000,
+                        correct: true,
+                        kind: 2,
+                        response_ms: None,
+                    },
+                ],
+            },
+            Note {
+                id: 3,
+                q: "koira".to_string(),
+                deck: Some(DEFAULT_DECK.to_string()),
+                a: "dog".to_string(),
+                schedule: Schedule {
+                    created_at: 1_700_000_000,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ];
+
+        let package = read(&write(&notes).unwrap(), &FieldMap::default()).unwrap();
+        assert!(package.skipped.is_empty());
+        let mut read_notes = package.notes;
+        assert_eq!(read_notes.len(), 2);
+        // Notes created in the same second get consecutive ids.
+        assert_eq!(read_notes[1].id, read_notes[0].id + 1);
+        for note in read_notes.iter_mut() {
+            note.id = notes.iter().find(|n| n.q == note.q).unwrap().id;
+        }
+        assert_eq!(read_notes, notes);
+
+        let decks = deck_ids(&read_notes, 100);
+        assert_eq!(
+            decks.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("Finnish".to_string(), 100),
+                ("Finnish::Animals".to_string(), 101)
+            ]
+        );
+    }
+}
@@ -8,6 +8,14 @@ export async function updateApiKey(apiKey) {
     return await invoke("update_api_key", { apiKey })
 }
 
+export async function register(username, password) {
+    return await invoke("register", { username, password });
+}
+
+export async function login(username, password) {
+    return await invoke("login", { username, password });
+}
+
 export async function addQa(msg) {
     return await invoke("add_qa", { msg });
 }
@@ -19,3 +27,45 @@ export async function getQuiz() {
 export async function reviewQa(msg) {
     return await invoke("review_qa", { msg });
 }
+
+export async function getChoices(msg) {
+    return await invoke("get_choices", { msg });
+}
+
+export async function getStats() {
+    return await invoke("get_stats");
+}
+
+export async function startSession() {
+    return await invoke("start_session");
+}
+
+export async function endSession(msg) {
+    return await invoke("end_session", { msg });
+}
+
+export async function importQas(rows, duplicates, dryRun) {
+    return await invoke("import_qas", { rows, duplicates, dryRun });
+}
+
+export async function importAnki(bytes, qField, aField, deck, duplicates, dryRun, history) {
+    // Tauri expects an array of numbers for Vec<u8>.
+    bytes = Array.from(bytes);
+    return await invoke("import_anki", { bytes, qField, aField, deck, duplicates, dryRun, history });
+}
+
+export async function exportAll(fileName, history) {
+    return await invoke("export", { fileName, history });
+}
+
+export async function deleteAccount(password, exportFile) {
+    return await invoke("delete_account", { password, exportFile });
+}
+
+export async function getSettings() {
+    return await invoke("get_settings");
+}
+
+export async function updateSettings(msg) {
+    return await invoke("update_settings", { msg });
+}
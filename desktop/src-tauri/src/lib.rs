@@ -1,7 +1,7 @@
 use std::cmp::min;
 use std::fs::{self, File};
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str;
 
 use argon2::Argon2;
@@ -14,7 +14,12 @@ use tokio::sync::Mutex;
 use tokio::time::{self, Duration};
 use tracing::error;
 
-use message::{Message, QA};
+use message::export;
+use message::import::Row;
+use message::{
+    DuplicateMode, ExportedQA, ImportOutcome, Message, MultipleChoice, NewQA, PastReview, QuizOrder,
+    SessionSummary, Settings, Stats, IMPORT_BATCH_BYTES, QA,
+};
 
 const VAULT_CLIENT: &str = "ApiKeyClient";
 const VAULT_API_KEY: &str = "ApiKey";
@@ -129,9 +134,21 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             is_connected,
             update_api_key,
+            register,
+            login,
             add_qa,
             get_quiz,
-            review_qa
+            review_qa,
+            get_choices,
+            get_stats,
+            start_session,
+            end_session,
+            import_qas,
+            import_anki,
+            export,
+            get_settings,
+            update_settings,
+            delete_account
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -192,16 +209,13 @@ async fn retry_connect(
 async fn connect(in_buf: &mut [u8], out_buf: &mut [u8], token: &str) -> anyhow::Result<TcpStream> {
     let mut stream = TcpStream::connect(get_server_addr()).await?;
 
-    let handshake = Message::Handshake { version: 1, token };
-
-    prot::write_msg(&mut stream, out_buf, &handshake).await?;
-
-    let handshake_resp = prot::read_msg(&mut stream, in_buf).await?;
-
-    let Message::HandshakeResp = handshake_resp else {
-        error!(?handshake_resp, "Handshake reply has the wrong type");
-        anyhow::bail!("Handshake resp has the wrong type");
-    };
+    // Tokens the app gets by logging in already have a stored key.
+    if let Err(err) =
+        prot::handshake::authenticate(&mut stream, in_buf, out_buf, token, false).await
+    {
+        error!(%err, "Handshake failed");
+        return Err(err.into());
+    }
 
     Ok(stream)
 }
@@ -229,21 +243,73 @@ async fn update_api_key(state: State<'_, AppState>, api_key: String) -> Result<(
 
     let _ = stream.insert(new_stream);
 
-    // Store the API Key in vault and commit for later use.
-    state
-        .vault_cli
-        .store()
-        .insert(
-            VAULT_API_KEY.as_bytes().to_vec(),
-            api_key.as_bytes().to_vec(),
-            None,
-        )
+    store_api_key(&state, &api_key).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn register(state: State<'_, AppState>, username: String, password: String) -> Result<()> {
+    let msg = Message::Register {
+        username: &username,
+        password: &password,
+        device: &device_name(),
+    };
+    log_in(state, &msg).await
+}
+
+#[tauri::command]
+async fn login(state: State<'_, AppState>, username: String, password: String) -> Result<()> {
+    let msg = Message::Login {
+        username: &username,
+        password: &password,
+        device: &device_name(),
+    };
+    log_in(state, &msg).await
+}
+
+// Registers or logs in to get a token for this device, which the connection is then
+// authenticated with and which is stored in the vault like an API Key.
+async fn log_in(state: State<'_, AppState>, msg: &Message<'_>) -> Result<()> {
+    let mut state = state.lock().await;
+    let (in_buf, out_buf, stream, _) = state.split_borrow_mut();
+
+    let (new_stream, api_key) = connect_with_login(in_buf, out_buf, msg)
+        .await
         .map_err(|e| e.to_string())?;
 
+    let _ = stream.insert(new_stream);
+
+    store_api_key(&state, &api_key).map_err(|e| e.to_string())
+}
+
+async fn connect_with_login(
+    in_buf: &mut [u8],
+    out_buf: &mut [u8],
+    msg: &Message<'_>,
+) -> anyhow::Result<(TcpStream, String)> {
+    let mut stream = TcpStream::connect(get_server_addr()).await?;
+
+    let api_key = prot::handshake::request_token(&mut stream, in_buf, out_buf, msg).await?;
+    prot::handshake::authenticate(&mut stream, in_buf, out_buf, &api_key, false).await?;
+
+    Ok((stream, api_key))
+}
+
+// What the tokens issued to this app are labeled with.
+fn device_name() -> String {
+    format!("desktop ({})", std::env::consts::OS)
+}
+
+// Store the API Key in vault and commit for later use.
+fn store_api_key(state: &AppStateInner, api_key: &str) -> anyhow::Result<()> {
+    state.vault_cli.store().insert(
+        VAULT_API_KEY.as_bytes().to_vec(),
+        api_key.as_bytes().to_vec(),
+        None,
+    )?;
+
     state
         .vault_srv
-        .commit_with_keyprovider(&state.vault_snapshot_path, &state.vault_enc_key)
-        .map_err(|e| e.to_string())?;
+        .commit_with_keyprovider(&state.vault_snapshot_path, &state.vault_enc_key)?;
 
     Ok(())
 }
@@ -274,7 +340,9 @@ async fn add_qa(app: AppHandle, state: State<'_, AppState>, msg: Message<'_>) ->
 async fn get_quiz(state: State<'_, AppState>) -> Result<Vec<QA>> {
     let mut qas = Vec::new();
 
-    let msg = Message::GetQuiz;
+    let msg = Message::GetQuiz {
+        order: QuizOrder::default(),
+    };
 
     let mut state = state.lock().await;
 
@@ -294,8 +362,10 @@ async fn get_quiz(state: State<'_, AppState>) -> Result<Vec<QA>> {
     Ok(qas)
 }
 
+// Returns the number of seconds after which the QA should be shown again, if the
+// server wants it back within the same session.
 #[tauri::command]
-async fn review_qa(state: State<'_, AppState>, msg: Message<'_>) -> Result<()> {
+async fn review_qa(state: State<'_, AppState>, msg: Message<'_>) -> Result<Option<u32>> {
     let Message::ReviewQA { .. } = msg else {
         return Err(format!("expected ReviewQA, got {:?}", msg));
     };
@@ -304,17 +374,510 @@ async fn review_qa(state: State<'_, AppState>, msg: Message<'_>) -> Result<()> {
 
     let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
 
+    let mut due_in = None;
     let handle_resp = |resp: &Message| match resp {
-        Message::ReviewQAResp => Ok(()),
+        Message::ReviewQAResp { due_in: resp_due_in } => {
+            due_in = *resp_due_in;
+            Ok(())
+        }
         _ => anyhow::bail!("expected ReviewQAResp, got {:?}", resp),
     };
     request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp)
         .await
         .map_err(|e| e.to_string())?;
 
+    Ok(due_in)
+}
+
+#[tauri::command]
+async fn get_choices(state: State<'_, AppState>, msg: Message<'_>) -> Result<MultipleChoice> {
+    let Message::GetChoices { .. } = msg else {
+        return Err(format!("expected GetChoices, got {:?}", msg));
+    };
+
+    let mut choices = None;
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let handle_resp = |resp: &Message| match resp {
+        Message::Choices { choices: resp_choices } => {
+            choices = Some(resp_choices.clone());
+            Ok(())
+        }
+        _ => anyhow::bail!("expected Choices, got {:?}", resp),
+    };
+    request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    choices.ok_or_else(|| "missing choices".to_string())
+}
+
+#[tauri::command]
+async fn get_stats(state: State<'_, AppState>) -> Result<Stats> {
+    let mut stats = None;
+
+    let msg = Message::GetStats;
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let handle_resp = |resp: &Message| match resp {
+        Message::Stats { stats: resp_stats } => {
+            stats = Some(resp_stats.clone());
+            Ok(())
+        }
+        _ => anyhow::bail!("expected Stats, got {:?}", resp),
+    };
+    request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    stats.ok_or_else(|| "missing stats".to_string())
+}
+
+#[tauri::command]
+async fn start_session(state: State<'_, AppState>) -> Result<i64> {
+    let mut id = None;
+
+    let msg = Message::StartSession;
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let handle_resp = |resp: &Message| match resp {
+        Message::StartSessionResp { id: resp_id } => {
+            id = Some(*resp_id);
+            Ok(())
+        }
+        _ => anyhow::bail!("expected StartSessionResp, got {:?}", resp),
+    };
+    request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    id.ok_or_else(|| "missing session id".to_string())
+}
+
+#[tauri::command]
+async fn end_session(state: State<'_, AppState>, msg: Message<'_>) -> Result<SessionSummary> {
+    let Message::EndSession { .. } = msg else {
+        return Err(format!("expected EndSession, got {:?}", msg));
+    };
+
+    let mut summary = None;
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let handle_resp = |resp: &Message| match resp {
+        Message::EndSessionResp { summary: resp_summary } => {
+            summary = Some(*resp_summary);
+            Ok(())
+        }
+        Message::BadRequest { reason } => anyhow::bail!("{}", reason),
+        _ => anyhow::bail!("expected EndSessionResp, got {:?}", resp),
+    };
+    request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    summary.ok_or_else(|| "missing session summary".to_string())
+}
+
+// Imports the rows in batches that fit in the server's buffer. The outcome of a row is
+// None if it's too long to be sent.
+#[tauri::command]
+async fn import_qas(
+    state: State<'_, AppState>,
+    rows: Vec<Row>,
+    duplicates: DuplicateMode,
+    dry_run: bool,
+) -> Result<Vec<Option<ImportOutcome>>> {
+    let new_qas: Vec<NewQA> = rows.iter().map(Row::as_new_qa).collect();
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let (outcomes, _) = send_qas(
+        stream, in_buf, out_buf, vault_cli, &new_qas, duplicates, dry_run,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(outcomes)
+}
+
+// Imports the notes of an Anki package along with their scheduling, and the review
+// history of the ones that were added unless it's left out. Returns the question and
+// result of each note, including the ones that couldn't be read.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn import_anki(
+    state: State<'_, AppState>,
+    bytes: Vec<u8>,
+    q_field: String,
+    a_field: String,
+    deck: Option<String>,
+    duplicates: DuplicateMode,
+    dry_run: bool,
+    history: bool,
+) -> Result<Vec<(String, String)>> {
+    let fields = anki::FieldMap {
+        q: q_field,
+        a: a_field,
+    };
+    let package = anki::read(&bytes, &fields).map_err(|e| e.to_string())?;
+    let new_qas: Vec<NewQA> = package
+        .notes
+        .iter()
+        .map(|note| NewQA {
+            q: &note.q,
+            a: &note.a,
+            deck: deck.as_deref().or(note.deck.as_deref()),
+            tags: note.tags.iter().map(String::as_str).collect(),
+            schedule: Some(note.schedule),
+        })
+        .collect();
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let (outcomes, ids) = send_qas(
+        stream, in_buf, out_buf, vault_cli, &new_qas, duplicates, dry_run,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut report: Vec<(String, String)> = package
+        .skipped
+        .iter()
+        .map(|s| (format!("Note {}", s.id), s.reason.clone()))
+        .collect();
+    let mut ids = ids.into_iter();
+    let mut reviews = vec![];
+    for (note, outcome) in package.notes.iter().zip(outcomes) {
+        let result = match outcome {
+            Some(outcome) => format!("{outcome:?}"),
+            None => "Too long".to_string(),
+        };
+        report.push((note.q.clone(), result));
+
+        if outcome != Some(ImportOutcome::Added) || dry_run {
+            continue;
+        }
+        let Some(qa_id) = ids.next() else {
+            break;
+        };
+        if history {
+            reviews.extend(note.reviews.iter().map(|r| PastReview {
+                qa_id,
+                reviewed_at: r.reviewed_at,
+                correct: r.correct,
+                kind: r.kind,
+                response_ms: r.response_ms,
+            }));
+        }
+    }
+
+    let mut reviews_buf = vec![0u8; IMPORT_BATCH_BYTES];
+    let mut done = 0;
+    while done < reviews.len() {
+        let (reviews_bytes, count) =
+            prot::ser_prefix(&reviews[done..], &mut reviews_buf).map_err(|e| e.to_string())?;
+        let msg = Message::ImportReviews {
+            count: count as u16,
+            reviews_bytes,
+        };
+        let handle_resp = |resp: &Message| match resp {
+            Message::ImportReviewsResp => Ok(()),
+            Message::BadRequest { reason } => anyhow::bail!("{}", reason),
+            _ => anyhow::bail!("expected ImportReviewsResp, got {:?}", resp),
+        };
+        request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp)
+            .await
+            .map_err(|e| e.to_string())?;
+        done += count;
+    }
+
+    Ok(report)
+}
+
+// Exports all QAs, and the review history if asked, to a file in the downloads directory.
+// Files ending in .apkg are Anki packages, others are CSV with the history in a second
+// file. Returns the paths written to.
+#[tauri::command]
+async fn export(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    file_name: String,
+    history: bool,
+) -> Result<Vec<String>> {
+    let Some(file_name) = Path::new(&file_name).file_name() else {
+        return Err("Enter a file name".to_string());
+    };
+    let dir = app.path().download_dir().map_err(|e| e.to_string())?;
+    let path = dir.join(file_name);
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let fetch = async {
+        let qas = fetch_qas(stream, in_buf, out_buf, vault_cli).await?;
+        let reviews = match history {
+            true => fetch_reviews(stream, in_buf, out_buf, vault_cli).await?,
+            false => vec![],
+        };
+        anyhow::Ok((qas, reviews))
+    };
+    let (qas, reviews) = fetch.await.map_err(|e| e.to_string())?;
+
+    let paths = write_export(path, qas, &reviews, history).map_err(|e| e.to_string())?;
+
+    Ok(paths.iter().map(|p| p.display().to_string()).collect())
+}
+
+fn write_export(
+    path: PathBuf,
+    qas: Vec<ExportedQA>,
+    reviews: &[PastReview],
+    history: bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    if path.extension().is_some_and(|ext| ext == "apkg") {
+        fs::write(&path, anki::write(&anki::export_notes(qas, reviews))?)?;
+        return Ok(vec![path]);
+    }
+
+    fs::write(&path, export::qas_csv(&qas))?;
+    if !history {
+        return Ok(vec![path]);
+    }
+    let reviews_path = export::reviews_path(&path);
+    fs::write(&reviews_path, export::reviews_csv(reviews))?;
+    Ok(vec![path, reviews_path])
+}
+
+// Fetches all QAs, as many at a time as fit in a reply.
+async fn fetch_qas(
+    stream: &mut Option<TcpStream>,
+    in_buf: &mut [u8],
+    out_buf: &mut [u8],
+    vault_cli: &StrongholdClient,
+) -> anyhow::Result<Vec<ExportedQA>> {
+    let mut qas = vec![];
+    let mut after_id = Some(0);
+
+    while let Some(id) = after_id {
+        let msg = Message::ExportQAs { after_id: id };
+        let handle_resp = |resp: &Message| match resp {
+            Message::ExportedQAs {
+                count,
+                qas_bytes,
+                next,
+            } => {
+                prot::deser_from_bytes(qas_bytes, *count, &mut qas)?;
+                after_id = *next;
+                Ok(())
+            }
+            _ => anyhow::bail!("expected ExportedQAs, got {:?}", resp),
+        };
+        request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp).await?;
+    }
+
+    Ok(qas)
+}
+
+// Fetches the review log like fetch_qas.
+async fn fetch_reviews(
+    stream: &mut Option<TcpStream>,
+    in_buf: &mut [u8],
+    out_buf: &mut [u8],
+    vault_cli: &StrongholdClient,
+) -> anyhow::Result<Vec<PastReview>> {
+    let mut reviews = vec![];
+    let mut after_id = Some(0);
+
+    while let Some(id) = after_id {
+        let msg = Message::ExportReviews { after_id: id };
+        let handle_resp = |resp: &Message| match resp {
+            Message::ExportedReviews {
+                count,
+                reviews_bytes,
+                next,
+            } => {
+                prot::deser_from_bytes(reviews_bytes, *count, &mut reviews)?;
+                after_id = *next;
+                Ok(())
+            }
+            _ => anyhow::bail!("expected ExportedReviews, got {:?}", resp),
+        };
+        request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp).await?;
+    }
+
+    Ok(reviews)
+}
+
+// Sends the QAs in batches that fit in the server's buffer. Returns the outcome of each
+// of them, which is None if it's too long to be sent, and the ids of the added ones.
+async fn send_qas(
+    stream: &mut Option<TcpStream>,
+    in_buf: &mut [u8],
+    out_buf: &mut [u8],
+    vault_cli: &StrongholdClient,
+    new_qas: &[NewQA<'_>],
+    duplicates: DuplicateMode,
+    dry_run: bool,
+) -> anyhow::Result<(Vec<Option<ImportOutcome>>, Vec<i64>)> {
+    let mut qas_buf = vec![0u8; IMPORT_BATCH_BYTES];
+    let mut outcomes = Vec::with_capacity(new_qas.len());
+    let mut ids = vec![];
+
+    while outcomes.len() < new_qas.len() {
+        let (qas_bytes, count) = prot::ser_prefix(&new_qas[outcomes.len()..], &mut qas_buf)?;
+        if count == 0 {
+            outcomes.push(None);
+            continue;
+        }
+
+        let msg = Message::ImportQAs {
+            duplicates,
+            dry_run,
+            count: count as u16,
+            qas_bytes,
+        };
+        let handle_resp = |resp: &Message| match resp {
+            Message::ImportQAsResp {
+                outcomes: resp_outcomes,
+                ids: resp_ids,
+            } => {
+                outcomes.extend(resp_outcomes.iter().copied().map(Some));
+                ids.extend_from_slice(resp_ids);
+                Ok(())
+            }
+            Message::BadRequest { reason } => anyhow::bail!("{}", reason),
+            _ => anyhow::bail!("expected ImportQAsResp, got {:?}", resp),
+        };
+        request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp).await?;
+    }
+
+    Ok((outcomes, ids))
+}
+
+#[tauri::command]
+async fn get_settings(state: State<'_, AppState>) -> Result<Settings> {
+    let mut settings = None;
+
+    let msg = Message::GetSettings;
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let handle_resp = |resp: &Message| match resp {
+        Message::Settings { settings: resp_settings } => {
+            settings = Some(resp_settings.clone());
+            Ok(())
+        }
+        _ => anyhow::bail!("expected Settings, got {:?}", resp),
+    };
+    request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    settings.ok_or_else(|| "missing settings".to_string())
+}
+
+#[tauri::command]
+async fn update_settings(state: State<'_, AppState>, msg: Message<'_>) -> Result<()> {
+    let Message::UpdateSettings { .. } = msg else {
+        return Err(format!("expected UpdateSettings, got {:?}", msg));
+    };
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let handle_resp = |resp: &Message| match resp {
+        Message::UpdateSettingsResp => Ok(()),
+        Message::BadRequest { reason } => anyhow::bail!("{}", reason),
+        _ => anyhow::bail!("expected UpdateSettingsResp, got {:?}", resp),
+    };
+    request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp)
+        .await
+        .map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
+// Deletes the account, after an export with the review history if a file name is given,
+// and forgets the API Key. Nothing is deleted if the export fails. Accounts without a
+// password, whose password is left empty, are confirmed with a challenge answered with
+// the API Key.
+#[tauri::command]
+async fn delete_account(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    password: String,
+    export_file: Option<String>,
+) -> Result<Vec<String>> {
+    let paths = match export_file {
+        Some(file_name) => export(app, state.clone(), file_name, true).await?,
+        None => vec![],
+    };
+
+    let mut state = state.lock().await;
+
+    let (in_buf, out_buf, stream, vault_cli) = state.split_borrow_mut();
+
+    let nonce = prot::handshake::nonce();
+    let msg = Message::DeleteAccount {
+        password: Some(password.as_str()).filter(|p| !p.is_empty()),
+        nonce,
+    };
+    let handle_resp = |resp: &Message| match resp {
+        Message::DeleteAccountResp => Ok(()),
+        Message::BadRequest { reason } => anyhow::bail!("{}", reason),
+        _ => anyhow::bail!("expected DeleteAccountResp, got {:?}", resp),
+    };
+    if password.is_empty() {
+        let api_key = get_api_key(vault_cli).map_err(|e| e.to_string())?;
+        let stream = stream.as_mut().ok_or("Not connected to the server")?;
+        let challenged = async {
+            prot::write_msg(stream, out_buf, &msg).await?;
+            prot::handshake::answer_challenge(stream, in_buf, out_buf, &api_key, &nonce).await?;
+            handle_resp(&prot::read_msg(stream, in_buf).await?)
+        };
+        challenged.await.map_err(|e| e.to_string())?;
+    } else {
+        request_reconnect(stream, in_buf, out_buf, vault_cli, &msg, handle_resp)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    // The server closes the connection once the account is deleted.
+    let _ = stream.take();
+
+    state
+        .vault_cli
+        .store()
+        .delete(VAULT_API_KEY.as_bytes())
+        .map_err(|e| e.to_string())?;
+    state
+        .vault_srv
+        .commit_with_keyprovider(&state.vault_snapshot_path, &state.vault_enc_key)
+        .map_err(|e| e.to_string())?;
+
+    Ok(paths)
+}
+
 // request_reconnect will send a request to the server with the provided message,
 // and if it detects disconnection will attempt to re-establish the connection
 // using retry_connect and tries the request one more time afterwards.
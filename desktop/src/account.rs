@@ -0,0 +1,85 @@
+use serde_wasm_bindgen::from_value;
+use yew::platform::spawn_local;
+use yew::prelude::*;
+
+use crate::commands::delete_account;
+
+#[derive(Properties, PartialEq)]
+pub struct AccountProperties {
+    pub onerror: Callback<String>,
+    // Called once the account is deleted and the app is disconnected.
+    pub ondelete: Callback<()>,
+}
+
+#[function_component(AccountComponent)]
+pub fn account(props: &AccountProperties) -> Html {
+    let password_ref = use_node_ref();
+    let export_ref = use_node_ref();
+    let file_name_ref = use_node_ref();
+    let confirm_ref = use_node_ref();
+
+    let ondelete = {
+        let onerror = props.onerror.clone();
+        let ondelete = props.ondelete.clone();
+        let password_ref = password_ref.clone();
+        let export_ref = export_ref.clone();
+        let file_name_ref = file_name_ref.clone();
+        let confirm_ref = confirm_ref.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            onerror.emit("".to_string());
+            let input = |node_ref: &NodeRef| node_ref.cast::<web_sys::HtmlInputElement>().unwrap();
+            let password = input(&password_ref).value();
+            let export_file = input(&export_ref)
+                .checked()
+                .then(|| input(&file_name_ref).value().trim().to_string());
+            if !input(&confirm_ref).checked() {
+                onerror.emit("Confirm that the account is deleted for good".to_string());
+                return;
+            }
+            if export_file.as_ref().is_some_and(|f| f.is_empty()) {
+                onerror.emit("Enter a file name to export to".to_string());
+                return;
+            }
+
+            let onerror = onerror.clone();
+            let ondelete = ondelete.clone();
+            spawn_local(async move {
+                match delete_account(password, export_file).await {
+                    Ok(jsval) => {
+                        let paths: Vec<String> = from_value(jsval).unwrap_or_default();
+                        web_sys::console::log_1(
+                            &format!("Account deleted, exported to {paths:?}").into(),
+                        );
+                        ondelete.emit(());
+                    }
+                    Err(e) => onerror.emit(e.as_string().unwrap()),
+                }
+            });
+        })
+    };
+
+    html! {
+        <>
+            <h3>{"Delete account"}</h3>
+            <div class="settings">
+                <label>{"Password (empty if the account has none)"}</label>
+                <input ref={password_ref} type="password" autocomplete="current-password" />
+
+                <label>{"Export everything to Downloads first"}</label>
+                <input ref={export_ref} type="checkbox" checked=true />
+
+                <label>{"File name (.csv, or .apkg for Anki)"}</label>
+                <input ref={file_name_ref} type="text" value="memryze.csv" />
+
+                <label>{"Delete all my cards and reviews for good"}</label>
+                <input ref={confirm_ref} type="checkbox" />
+            </div>
+            <div class="actions actions-margined">
+                <button type="button" class="submit-button" onclick={ondelete}>
+                    {"Delete account"}
+                </button>
+            </div>
+        </>
+    }
+}
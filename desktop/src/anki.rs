@@ -0,0 +1,174 @@
+use js_sys::Uint8Array;
+use message::DuplicateMode;
+use serde_wasm_bindgen::{from_value, to_value};
+use wasm_bindgen_futures::JsFuture;
+use yew::platform::spawn_local;
+use yew::prelude::*;
+
+use crate::commands::import_anki;
+use crate::import::ImportProperties;
+
+#[function_component(AnkiImportComponent)]
+pub fn anki_import(props: &ImportProperties) -> Html {
+    let bytes = use_state(|| None::<Vec<u8>>);
+    let report = use_state(Vec::<(String, String)>::new);
+    let dry_run = use_state(|| true);
+
+    let q_field_ref = use_node_ref();
+    let a_field_ref = use_node_ref();
+    let deck_ref = use_node_ref();
+    let duplicates_ref = use_node_ref();
+    let history_ref = use_node_ref();
+
+    let onfile = {
+        let bytes = bytes.clone();
+        let report = report.clone();
+        let onerror = props.onerror.clone();
+
+        Callback::from(move |e: Event| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let Some(file) = input.files().and_then(|files| files.get(0)) else {
+                return;
+            };
+
+            let bytes = bytes.clone();
+            let report = report.clone();
+            let onerror = onerror.clone();
+            spawn_local(async move {
+                match JsFuture::from(file.array_buffer()).await {
+                    Ok(buf) => {
+                        bytes.set(Some(Uint8Array::new(&buf).to_vec()));
+                        report.set(vec![]);
+                    }
+                    Err(_) => onerror.emit("Couldn't read the file".to_string()),
+                }
+            });
+        })
+    };
+
+    let make_import_cb = |dry: bool| {
+        let bytes = bytes.clone();
+        let report = report.clone();
+        let dry_run = dry_run.clone();
+        let onerror = props.onerror.clone();
+        let q_field_ref = q_field_ref.clone();
+        let a_field_ref = a_field_ref.clone();
+        let deck_ref = deck_ref.clone();
+        let duplicates_ref = duplicates_ref.clone();
+        let history_ref = history_ref.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            onerror.emit("".to_string());
+            let Some(bytes) = (*bytes).clone() else {
+                onerror.emit("Choose a package to import".to_string());
+                return;
+            };
+
+            let input = |node_ref: &NodeRef| node_ref.cast::<web_sys::HtmlInputElement>().unwrap();
+            let q_field = input(&q_field_ref).value();
+            let a_field = input(&a_field_ref).value();
+            let deck = Some(input(&deck_ref).value().trim().to_string()).filter(|d| !d.is_empty());
+            let history = input(&history_ref).checked();
+            let duplicates = match duplicates_ref
+                .cast::<web_sys::HtmlSelectElement>()
+                .unwrap()
+                .value()
+                .as_str()
+            {
+                "update" => DuplicateMode::Update,
+                "keep-both" => DuplicateMode::KeepBoth,
+                _ => DuplicateMode::Skip,
+            };
+
+            dry_run.set(dry);
+
+            let report = report.clone();
+            let onerror = onerror.clone();
+            spawn_local(async move {
+                let res = import_anki(
+                    bytes,
+                    q_field,
+                    a_field,
+                    deck,
+                    to_value(&duplicates).unwrap(),
+                    dry,
+                    history,
+                )
+                .await;
+                match res {
+                    Ok(jsval) => match from_value(jsval) {
+                        Ok(lines) => report.set(lines),
+                        Err(e) => onerror.emit(e.to_string()),
+                    },
+                    Err(e) => onerror.emit(e.as_string().unwrap()),
+                }
+            });
+        })
+    };
+    let onpreview = make_import_cb(true);
+    let onimport = make_import_cb(false);
+
+    let report_rows = report.iter().map(|(q, result)| {
+        html! {
+            <tr>
+                <td>{q}</td>
+                <td>{result}</td>
+            </tr>
+        }
+    });
+    let added = report
+        .iter()
+        .filter(|(_, result)| result == "Added")
+        .count();
+    let report_title = if *dry_run {
+        format!("Preview: {added} of {} notes would be added", report.len())
+    } else {
+        format!("Imported: {added} of {} notes added", report.len())
+    };
+
+    html! {
+        <div class="import">
+            <div class="settings">
+                <label>{"Package"}</label>
+                <input type="file" accept=".apkg,.colpkg" onchange={onfile} />
+
+                <label>{"Question field (name or number)"}</label>
+                <input ref={q_field_ref} type="text" value="1" />
+
+                <label>{"Answer field (name or number)"}</label>
+                <input ref={a_field_ref} type="text" value="2" />
+
+                <label>{"Deck"}</label>
+                <input ref={deck_ref} type="text" placeholder="Keep the Anki decks" />
+
+                <label>{"Duplicate questions"}</label>
+                <select ref={duplicates_ref}>
+                    <option value="skip">{"Skip"}</option>
+                    <option value="update">{"Update the existing card"}</option>
+                    <option value="keep-both">{"Keep both"}</option>
+                </select>
+
+                <label>{"Import review history"}</label>
+                <input ref={history_ref} type="checkbox" checked=true />
+            </div>
+            <div class="actions">
+                <button type="button" class="submit-button neutral-button" onclick={onpreview}>
+                    {"Preview"}
+                </button>
+                <button type="button"
+                    class="submit-button"
+                    disabled={bytes.is_none()}
+                    onclick={onimport}
+                >{"Import"}</button>
+            </div>
+
+            if !report.is_empty() {
+                <h3>{report_title}</h3>
+                <table class="import-table">
+                    <tr><th>{"Question"}</th><th>{"Result"}</th></tr>
+                    { for report_rows }
+                </table>
+            }
+        </div>
+    }
+}
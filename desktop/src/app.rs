@@ -1,39 +1,82 @@
-use message::QA;
-use serde_wasm_bindgen::from_value;
+use gloo_timers::callback::Timeout;
+use message::{Message, SessionSummary, QA};
+use serde_wasm_bindgen::{from_value, to_value};
 use yew::platform::spawn_local;
 use yew::prelude::*;
 
-use crate::commands::get_quiz;
+use crate::commands::{end_session, get_quiz, start_session};
+use crate::import::ImportComponent;
+use crate::queue::{QueueAction, QuizQueue};
 use crate::quiz::QuizComponent;
+use crate::settings::SettingsComponent;
+use crate::stats::StatsComponent;
 use crate::submit::SubmitComponent;
+use crate::summary::SummaryComponent;
 
 #[derive(PartialEq, Copy, Clone)]
 enum NavbarSelected {
     Submit,
+    Import,
     Quiz,
+    Stats,
+    Settings,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct AppProperties {
+    // Called when the app can't be used anymore, because the account was deleted.
+    pub ondisconnect: Callback<()>,
 }
 
 #[function_component(App)]
-pub fn app() -> Html {
+pub fn app(props: &AppProperties) -> Html {
     let navbar_selected = use_state(|| NavbarSelected::Submit);
-    let fetched_qas = use_state(|| Vec::<QA>::new());
-    let current_qa_idx = use_state(|| 0);
+    let queue = use_reducer(QuizQueue::default);
     let status_message = use_state(|| String::from(""));
 
+    // Id of the session the reviews are counted towards.
+    let session = use_mut_ref(|| None::<i64>);
+    let summary = use_state(|| None::<SessionSummary>);
+
+    // Fetches a new quiz on startup and whenever all the fetched QAs have been reviewed.
+    // A session starts with the first non-empty quiz and ends when there's nothing
+    // left to review.
     {
-        let fetched_qas = fetched_qas.clone();
+        let queue = queue.clone();
         let status_message = status_message.clone();
+        let session = session.clone();
+        let summary = summary.clone();
 
-        use_effect_with((), move |_| {
-            let fetched_qas = fetched_qas.clone();
-            let status_message = status_message.clone();
-
-            spawn_local(async move {
-                match refresh_quiz().await {
-                    Ok(qas) => fetched_qas.set(qas),
-                    Err(e) => status_message.set(e),
-                }
-            });
+        use_effect_with(queue.is_exhausted(), move |exhausted| {
+            if *exhausted {
+                spawn_local(async move {
+                    let qas = match refresh_quiz().await {
+                        Ok(qas) => qas,
+                        Err(e) => {
+                            status_message.set(e);
+                            return;
+                        }
+                    };
+                    web_sys::console::log_1(&"qas refreshed after full consumption".into());
+
+                    let active = *session.borrow();
+                    match (active, qas.is_empty()) {
+                        (Some(id), true) => {
+                            session.borrow_mut().take();
+                            match finish_session(id).await {
+                                Ok(finished) => summary.set(Some(finished)),
+                                Err(e) => status_message.set(e),
+                            }
+                        }
+                        (None, false) => match begin_session().await {
+                            Ok(id) => *session.borrow_mut() = Some(id),
+                            Err(e) => status_message.set(e),
+                        },
+                        _ => {}
+                    }
+                    queue.dispatch(QueueAction::Refreshed(qas));
+                });
+            }
         });
     }
 
@@ -44,19 +87,24 @@ pub fn app() -> Html {
         })
     };
     let onselect_submit = make_onselect_cb(NavbarSelected::Submit);
+    let onselect_import = make_onselect_cb(NavbarSelected::Import);
     let onselect_quiz = make_onselect_cb(NavbarSelected::Quiz);
-
-    let (nav_submit_cls, nav_quiz_cls) = if *navbar_selected == NavbarSelected::Submit {
-        ("navbar-selected", "")
-    } else {
-        ("", "navbar-selected")
+    let onselect_stats = make_onselect_cb(NavbarSelected::Stats);
+    let onselect_settings = make_onselect_cb(NavbarSelected::Settings);
+
+    let nav_cls = |ns: NavbarSelected| {
+        if *navbar_selected == ns {
+            "navbar-selected"
+        } else {
+            ""
+        }
     };
 
     // TODO: Also when new questions are submitted, submit component calls a callback
     // to signal quiz should be refreshed. If the current qas is empty, then
     // a refresh should be attempted upon switching from submit to quiz component.
 
-    let current_qa = fetched_qas.get(*current_qa_idx).cloned();
+    let current_qa = queue.current().cloned();
     let onerror = {
         let status_message = status_message.clone();
         Callback::from(move |msg| {
@@ -64,51 +112,60 @@ pub fn app() -> Html {
         })
     };
     let onreview = {
-        let fetched_qas = fetched_qas.clone();
-        let current_qa_idx = current_qa_idx.clone();
-        let status_message = status_message.clone();
-
-        Callback::from(move |_| {
-            let fetched_qas = fetched_qas.clone();
-            let status_message = status_message.clone();
-
-            let new_idx = *current_qa_idx + 1;
-            if new_idx != fetched_qas.len() {
-                current_qa_idx.set(new_idx);
-            } else {
-                current_qa_idx.set(0);
-                spawn_local(async move {
-                    match refresh_quiz().await {
-                        Ok(qas) => {
-                            web_sys::console::log_1(&"qas refreshed after full consumption".into());
-                            fetched_qas.set(qas)
-                        }
-                        Err(e) => status_message.set(e),
-                    }
-                });
+        let queue = queue.clone();
+
+        Callback::from(move |(qa, due_in): (QA, Option<u32>)| {
+            // QAs that are still being (re)learned come back once they're due, without
+            // waiting for the whole quiz to be refetched.
+            if let Some(due_in) = due_in {
+                let queue = queue.clone();
+                Timeout::new(due_in.saturating_mul(1000), move || {
+                    queue.dispatch(QueueAction::Reinsert(qa));
+                })
+                .forget();
             }
+            queue.dispatch(QueueAction::Next);
         })
     };
 
+    let onclose_summary = {
+        let summary = summary.clone();
+        Callback::from(move |_| summary.set(None))
+    };
+
     html! {
         <main class="container">
             <nav>
                 <ul class="navbar">
-                    <li class={nav_submit_cls} onclick={onselect_submit}>{"Submit"}</li>
-                    <li class={nav_quiz_cls} onclick={onselect_quiz}>{"Quiz"}</li>
+                    <li class={nav_cls(NavbarSelected::Submit)} onclick={onselect_submit}>{"Submit"}</li>
+                    <li class={nav_cls(NavbarSelected::Import)} onclick={onselect_import}>{"Import/Export"}</li>
+                    <li class={nav_cls(NavbarSelected::Quiz)} onclick={onselect_quiz}>{"Quiz"}</li>
+                    <li class={nav_cls(NavbarSelected::Stats)} onclick={onselect_stats}>{"Statistics"}</li>
+                    <li class={nav_cls(NavbarSelected::Settings)} onclick={onselect_settings}>{"Settings"}</li>
                 </ul>
              </nav>
 
             <p><span>{if status_message.is_empty() { "" } else { "* "}}</span>{&*status_message}</p>
 
-            if *navbar_selected == NavbarSelected::Submit {
-                <SubmitComponent {onerror} />
-            } else {
-                <QuizComponent qa={current_qa}
-                    {onreview}
-                    {onerror}
-                />
-            }
+            {match *navbar_selected {
+                NavbarSelected::Submit => html! { <SubmitComponent {onerror} /> },
+                NavbarSelected::Import => html! { <ImportComponent {onerror} /> },
+                NavbarSelected::Quiz => match *summary {
+                    Some(summary) => html! {
+                        <SummaryComponent {summary} onclose={onclose_summary} />
+                    },
+                    None => html! {
+                        <QuizComponent qa={current_qa}
+                            {onreview}
+                            {onerror}
+                        />
+                    },
+                },
+                NavbarSelected::Stats => html! { <StatsComponent {onerror} /> },
+                NavbarSelected::Settings => html! {
+                    <SettingsComponent {onerror} ondelete={props.ondisconnect.clone()} />
+                },
+            }}
         </main>
     }
 }
@@ -123,3 +180,18 @@ async fn refresh_quiz() -> Result<Vec<QA>, String> {
         Err(e) => Err(e.as_string().unwrap()),
     }
 }
+
+async fn begin_session() -> Result<i64, String> {
+    match start_session().await {
+        Ok(jsval) => from_value(jsval).map_err(|e| e.to_string()),
+        Err(e) => Err(e.as_string().unwrap()),
+    }
+}
+
+async fn finish_session(id: i64) -> Result<SessionSummary, String> {
+    let args = to_value(&Message::EndSession { id }).unwrap();
+    match end_session(args).await {
+        Ok(jsval) => from_value(jsval).map_err(|e| e.to_string()),
+        Err(e) => Err(e.as_string().unwrap()),
+    }
+}
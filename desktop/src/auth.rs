@@ -2,7 +2,7 @@ use serde_wasm_bindgen::{from_value, to_value};
 use yew::platform::spawn_local;
 use yew::prelude::*;
 
-use crate::commands::{is_connected, update_api_key};
+use crate::commands::{is_connected, login, register, update_api_key};
 
 #[derive(Properties, PartialEq)]
 pub struct AuthProperties {
@@ -12,6 +12,8 @@ pub struct AuthProperties {
 #[function_component(Auth)]
 pub fn auth(props: &AuthProperties) -> Html {
     let api_key_ref = use_node_ref();
+    let username_ref = use_node_ref();
+    let password_ref = use_node_ref();
     let status_message = use_state(|| String::from(""));
     let submit_disabled = use_state(|| false);
 
@@ -80,12 +82,68 @@ pub fn auth(props: &AuthProperties) -> Html {
         })
     };
 
+    // Logs in, or registers, and the token the server issues for this device is stored
+    // like an API Key would be.
+    let make_login_cb = |new_account: bool| {
+        let username_ref = username_ref.clone();
+        let password_ref = password_ref.clone();
+        let status_message = status_message.clone();
+        let onconnect = props.onconnect.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            status_message.set("".to_string());
+
+            let input = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<web_sys::HtmlInputElement>()
+                    .unwrap()
+                    .value()
+            };
+            let username = input(&username_ref).trim().to_string();
+            let password = input(&password_ref);
+            if username.is_empty() || password.is_empty() {
+                status_message.set("Enter your username and password".to_string());
+                return;
+            }
+
+            let status_message = status_message.clone();
+            let onconnect = onconnect.clone();
+            spawn_local(async move {
+                let res = if new_account {
+                    register(username, password).await
+                } else {
+                    login(username, password).await
+                };
+                match res {
+                    Ok(_) => onconnect.emit(()),
+                    Err(e) => status_message.set(e.as_string().unwrap()),
+                }
+            });
+        })
+    };
+    let onlogin = make_login_cb(false);
+    let onregister = make_login_cb(true);
+
     html! {
         <main class="container">
             <p><span>{if status_message.is_empty() { "" } else { "* "}}</span>{&*status_message}</p>
             <div class="row">
                 <div class="input-group">
-                    <label>{"Insert your API Key"}</label>
+                    <label>{"Username"}</label>
+                    <input ref={username_ref} type="text" autocomplete="username" />
+                    <label>{"Password"}</label>
+                    <input ref={password_ref} type="password" autocomplete="current-password" />
+                </div>
+            </div>
+            <div class="actions">
+                <button type="button" class="submit-button" onclick={onlogin}>{"Log in"}</button>
+                <button type="button" class="submit-button neutral-button" onclick={onregister}>
+                    {"Register"}
+                </button>
+            </div>
+            <div class="row">
+                <div class="input-group">
+                    <label>{"Or insert your API Key"}</label>
                     <textarea
                         name="api-key"
                         placeholder={"API KEY"}
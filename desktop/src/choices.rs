@@ -0,0 +1,118 @@
+use gloo_timers::callback::Timeout;
+use message::{Message, MultipleChoice, QA};
+use serde_wasm_bindgen::{from_value, to_value};
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+
+use crate::commands::get_choices;
+use crate::quiz::{elapsed_ms, submit_review_qa};
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct ChoicesProperties {
+    pub qa: QA,
+    pub onreview: Callback<(QA, Option<u32>)>,
+    pub onerror: Callback<String>,
+}
+
+// ChoicesComponent shows the QA as a multiple-choice question. Picking an option
+// reviews the QA as correct or wrong.
+#[function_component(ChoicesComponent)]
+pub fn choices(props: &ChoicesProperties) -> Html {
+    let choices = use_state(|| None::<MultipleChoice>);
+    let picked = use_state(|| None::<usize>);
+    // When the options were shown, to measure how long picking one took.
+    let shown_at = use_mut_ref(|| None::<f64>);
+
+    {
+        let choices = choices.clone();
+        let picked = picked.clone();
+        let shown_at = shown_at.clone();
+        let onerror = props.onerror.clone();
+
+        use_effect_with(props.qa.id, move |&id| {
+            choices.set(None);
+            picked.set(None);
+            spawn_local(async move {
+                let msg = Message::GetChoices { id };
+                let args = to_value(&msg).unwrap();
+                match get_choices(args).await {
+                    Ok(jsval) => match from_value(jsval) {
+                        Ok(fetched) => {
+                            *shown_at.borrow_mut() = Some(js_sys::Date::now());
+                            choices.set(Some(fetched));
+                        }
+                        Err(e) => onerror.emit(e.to_string()),
+                    },
+                    Err(e) => onerror.emit(e.as_string().unwrap()),
+                }
+            });
+        });
+    }
+
+    let Some(mc) = choices.as_ref() else {
+        return html! {
+            <p class="cond-render">{ "Loading options..." }</p>
+        };
+    };
+
+    let options = mc.options.iter().enumerate().map(|(i, option)| {
+        let class = match *picked {
+            Some(_) if i == mc.answer as usize => "choice-correct",
+            Some(p) if p == i => "choice-wrong",
+            _ => "",
+        };
+
+        let onclick = {
+            let qa = props.qa.clone();
+            let onreview = props.onreview.clone();
+            let onerror = props.onerror.clone();
+            let picked = picked.clone();
+            let shown_at = shown_at.clone();
+            let correct = i == mc.answer as usize;
+
+            Callback::from(move |_: MouseEvent| {
+                if picked.is_some() {
+                    return;
+                }
+                picked.set(Some(i));
+                let response_ms = elapsed_ms(shown_at.borrow_mut().take());
+
+                let qa = qa.clone();
+                let onreview = onreview.clone();
+                let onerror = onerror.clone();
+                spawn_local(async move {
+                    let due_in = submit_review_qa(onerror, qa.id, correct, response_ms).await;
+                    // Leave the right answer on screen for a moment before moving on.
+                    Timeout::new(1000, move || {
+                        onreview.emit((qa, due_in));
+                    })
+                    .forget();
+                });
+            })
+        };
+
+        html! {
+            <button type="button"
+                class={classes!("choice", class)}
+                disabled={picked.is_some()}
+                {onclick}
+            >{option}</button>
+        }
+    });
+
+    html! {
+        <>
+            <div class="input-group">
+                <label>{"Question"}</label>
+                <textarea
+                    name="question"
+                    rows=6
+                    value={mc.q.clone()}
+                />
+            </div>
+            <div class="choices">
+                { for options }
+            </div>
+        </>
+    }
+}
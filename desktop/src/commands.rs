@@ -8,6 +8,12 @@ extern "C" {
     #[wasm_bindgen(js_name = updateApiKey, catch)]
     pub async fn update_api_key(api_key: JsValue) -> Result<JsValue, JsValue>;
 
+    #[wasm_bindgen(js_name = register, catch)]
+    pub async fn register(username: String, password: String) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = login, catch)]
+    pub async fn login(username: String, password: String) -> Result<JsValue, JsValue>;
+
     #[wasm_bindgen(js_name = addQa, catch)]
     pub async fn add_qa(msg: JsValue) -> Result<JsValue, JsValue>;
 
@@ -16,4 +22,49 @@ extern "C" {
 
     #[wasm_bindgen(js_name = reviewQa, catch)]
     pub async fn review_qa(msg: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = getChoices, catch)]
+    pub async fn get_choices(msg: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = getStats, catch)]
+    pub async fn get_stats() -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = startSession, catch)]
+    pub async fn start_session() -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = endSession, catch)]
+    pub async fn end_session(msg: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = importQas, catch)]
+    pub async fn import_qas(
+        rows: JsValue,
+        duplicates: JsValue,
+        dry_run: bool,
+    ) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = importAnki, catch)]
+    pub async fn import_anki(
+        bytes: Vec<u8>,
+        q_field: String,
+        a_field: String,
+        deck: Option<String>,
+        duplicates: JsValue,
+        dry_run: bool,
+        history: bool,
+    ) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = exportAll, catch)]
+    pub async fn export_all(file_name: String, history: bool) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = deleteAccount, catch)]
+    pub async fn delete_account(
+        password: String,
+        export_file: Option<String>,
+    ) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = getSettings, catch)]
+    pub async fn get_settings() -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_name = updateSettings, catch)]
+    pub async fn update_settings(msg: JsValue) -> Result<JsValue, JsValue>;
 }
@@ -0,0 +1,65 @@
+use serde_wasm_bindgen::from_value;
+use yew::platform::spawn_local;
+use yew::prelude::*;
+
+use crate::commands::export_all;
+use crate::import::ImportProperties;
+
+#[function_component(ExportComponent)]
+pub fn export(props: &ImportProperties) -> Html {
+    let written = use_state(Vec::<String>::new);
+
+    let file_name_ref = use_node_ref();
+    let history_ref = use_node_ref();
+
+    let onexport = {
+        let written = written.clone();
+        let onerror = props.onerror.clone();
+        let file_name_ref = file_name_ref.clone();
+        let history_ref = history_ref.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            onerror.emit("".to_string());
+            let input = |node_ref: &NodeRef| node_ref.cast::<web_sys::HtmlInputElement>().unwrap();
+            let file_name = input(&file_name_ref).value().trim().to_string();
+            let history = input(&history_ref).checked();
+            if file_name.is_empty() {
+                onerror.emit("Enter a file name".to_string());
+                return;
+            }
+
+            let written = written.clone();
+            let onerror = onerror.clone();
+            spawn_local(async move {
+                match export_all(file_name, history).await {
+                    Ok(jsval) => match from_value(jsval) {
+                        Ok(paths) => written.set(paths),
+                        Err(e) => onerror.emit(e.to_string()),
+                    },
+                    Err(e) => onerror.emit(e.as_string().unwrap()),
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class="import">
+            <h3>{"Export"}</h3>
+            <div class="settings">
+                <label>{"File name (.csv, or .apkg for Anki)"}</label>
+                <input ref={file_name_ref} type="text" value="memryze.csv" />
+
+                <label>{"Include review history"}</label>
+                <input ref={history_ref} type="checkbox" />
+            </div>
+            <div class="actions">
+                <button type="button" class="submit-button" onclick={onexport}>
+                    {"Export to Downloads"}
+                </button>
+            </div>
+            if !written.is_empty() {
+                <p>{"Exported to "}{written.join(" and ")}</p>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,350 @@
+use message::import::{self, ColumnMap, Row};
+use message::{DuplicateMode, ImportOutcome};
+use serde_wasm_bindgen::{from_value, to_value};
+use wasm_bindgen_futures::JsFuture;
+use yew::platform::spawn_local;
+use yew::prelude::*;
+
+use crate::anki::AnkiImportComponent;
+use crate::commands::import_qas;
+use crate::export::ExportComponent;
+use crate::subtitles::SubtitlesComponent;
+
+// Number of rows shown in the preview before importing.
+const PREVIEW_ROWS: usize = 10;
+
+#[derive(Properties, PartialEq)]
+pub struct ImportProperties {
+    pub onerror: Callback<String>,
+}
+
+// A line of the report, either a row the server handled or one that was rejected
+// before sending.
+#[derive(Clone, PartialEq)]
+struct ReportLine {
+    line: usize,
+    q: String,
+    result: String,
+}
+
+#[function_component(ImportComponent)]
+pub fn import(props: &ImportProperties) -> Html {
+    let format = use_state(|| "csv".to_string());
+
+    let onformat = {
+        let format = format.clone();
+        Callback::from(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            format.set(select.value());
+        })
+    };
+
+    let onerror = props.onerror.clone();
+    html! {
+        <>
+            <div class="settings">
+                <label>{"Format"}</label>
+                <select onchange={onformat}>
+                    <option value="csv" selected={*format == "csv"}>{"CSV or TSV"}</option>
+                    <option value="anki" selected={*format == "anki"}>{"Anki package (.apkg)"}</option>
+                    <option value="subtitles" selected={*format == "subtitles"}>
+                        {"Sentences from subtitles (.srt, .vtt)"}
+                    </option>
+                </select>
+            </div>
+            {
+                match format.as_str() {
+                    "anki" => html! { <AnkiImportComponent onerror={onerror.clone()} /> },
+                    "subtitles" => html! { <SubtitlesComponent onerror={onerror.clone()} /> },
+                    _ => html! { <CsvImportComponent onerror={onerror.clone()} /> },
+                }
+            }
+            <ExportComponent {onerror} />
+        </>
+    }
+}
+
+#[function_component(CsvImportComponent)]
+fn csv_import(props: &ImportProperties) -> Html {
+    let text = use_state(|| None::<String>);
+    let rows = use_state(Vec::<Row>::new);
+    let report = use_state(Vec::<ReportLine>::new);
+    let dry_run = use_state(|| true);
+
+    let delimiter_ref = use_node_ref();
+    let header_ref = use_node_ref();
+    let q_col_ref = use_node_ref();
+    let a_col_ref = use_node_ref();
+    let deck_col_ref = use_node_ref();
+    let tags_col_ref = use_node_ref();
+    let duplicates_ref = use_node_ref();
+
+    let onfile = {
+        let text = text.clone();
+        let rows = rows.clone();
+        let report = report.clone();
+        let onerror = props.onerror.clone();
+
+        Callback::from(move |e: Event| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let Some(file) = input.files().and_then(|files| files.get(0)) else {
+                return;
+            };
+
+            let text = text.clone();
+            let rows = rows.clone();
+            let report = report.clone();
+            let onerror = onerror.clone();
+            spawn_local(async move {
+                match JsFuture::from(file.text()).await {
+                    Ok(jsval) => {
+                        text.set(jsval.as_string());
+                        rows.set(vec![]);
+                        report.set(vec![]);
+                    }
+                    Err(_) => onerror.emit("Couldn't read the file".to_string()),
+                }
+            });
+        })
+    };
+
+    let make_import_cb = |dry: bool| {
+        let text = text.clone();
+        let rows = rows.clone();
+        let report = report.clone();
+        let dry_run = dry_run.clone();
+        let onerror = props.onerror.clone();
+        let delimiter_ref = delimiter_ref.clone();
+        let header_ref = header_ref.clone();
+        let q_col_ref = q_col_ref.clone();
+        let a_col_ref = a_col_ref.clone();
+        let deck_col_ref = deck_col_ref.clone();
+        let tags_col_ref = tags_col_ref.clone();
+        let duplicates_ref = duplicates_ref.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            onerror.emit("".to_string());
+            let Some(text) = text.as_ref() else {
+                onerror.emit("Choose a file to import".to_string());
+                return;
+            };
+
+            let input = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<web_sys::HtmlInputElement>()
+                    .unwrap()
+                    .value()
+            };
+            let select = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<web_sys::HtmlSelectElement>()
+                    .unwrap()
+                    .value()
+            };
+
+            let parsed = parse_rows(
+                text,
+                select(&delimiter_ref).chars().next(),
+                header_ref
+                    .cast::<web_sys::HtmlInputElement>()
+                    .unwrap()
+                    .checked(),
+                [
+                    input(&q_col_ref),
+                    input(&a_col_ref),
+                    input(&deck_col_ref),
+                    input(&tags_col_ref),
+                ],
+            );
+            let (parsed, rejected) = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    onerror.emit(e);
+                    return;
+                }
+            };
+            let duplicates = match select(&duplicates_ref).as_str() {
+                "update" => DuplicateMode::Update,
+                "keep-both" => DuplicateMode::KeepBoth,
+                _ => DuplicateMode::Skip,
+            };
+
+            rows.set(parsed.clone());
+            dry_run.set(dry);
+
+            let report = report.clone();
+            let onerror = onerror.clone();
+            spawn_local(async move {
+                let res = import_qas(
+                    to_value(&parsed).unwrap(),
+                    to_value(&duplicates).unwrap(),
+                    dry,
+                )
+                .await;
+                let outcomes: Vec<Option<ImportOutcome>> = match res {
+                    Ok(jsval) => match from_value(jsval) {
+                        Ok(outcomes) => outcomes,
+                        Err(e) => return onerror.emit(e.to_string()),
+                    },
+                    Err(e) => return onerror.emit(e.as_string().unwrap()),
+                };
+
+                let mut lines = rejected;
+                lines.extend(parsed.into_iter().zip(outcomes).map(|(row, outcome)| {
+                    let result = match outcome {
+                        Some(outcome) => format!("{outcome:?}"),
+                        None => "Too long".to_string(),
+                    };
+                    ReportLine {
+                        line: row.line,
+                        q: row.q,
+                        result,
+                    }
+                }));
+                lines.sort_by_key(|l| l.line);
+                report.set(lines);
+            });
+        })
+    };
+    let onpreview = make_import_cb(true);
+    let onimport = make_import_cb(false);
+
+    let preview = rows.iter().take(PREVIEW_ROWS).map(|row| {
+        html! {
+            <tr>
+                <td>{row.line}</td>
+                <td>{&row.q}</td>
+                <td>{&row.a}</td>
+                <td>{row.deck.clone().unwrap_or_default()}</td>
+                <td>{row.tags.join(" ")}</td>
+            </tr>
+        }
+    });
+    let report_rows = report.iter().map(|l| {
+        html! {
+            <tr>
+                <td>{l.line}</td>
+                <td>{&l.q}</td>
+                <td>{&l.result}</td>
+            </tr>
+        }
+    });
+    let added = report.iter().filter(|l| l.result == "Added").count();
+    let report_title = if *dry_run {
+        format!("Preview: {added} of {} rows would be added", report.len())
+    } else {
+        format!("Imported: {added} of {} rows added", report.len())
+    };
+
+    html! {
+        <div class="import">
+            <div class="settings">
+                <label>{"File (CSV or TSV)"}</label>
+                <input type="file" accept=".csv,.tsv,.txt" onchange={onfile} />
+
+                <label>{"Delimiter"}</label>
+                <select ref={delimiter_ref}>
+                    <option value="">{"Detect"}</option>
+                    <option value=",">{"Comma"}</option>
+                    <option value={"\t"}>{"Tab"}</option>
+                    <option value=";">{"Semicolon"}</option>
+                    <option value="|">{"Pipe"}</option>
+                </select>
+
+                <label>{"First row has column names"}</label>
+                <input ref={header_ref} type="checkbox" />
+
+                <label>{"Question column"}</label>
+                <input ref={q_col_ref} type="text" value="1" />
+
+                <label>{"Answer column"}</label>
+                <input ref={a_col_ref} type="text" value="2" />
+
+                <label>{"Deck column"}</label>
+                <input ref={deck_col_ref} type="text" placeholder="None" />
+
+                <label>{"Tags column"}</label>
+                <input ref={tags_col_ref} type="text" placeholder="None" />
+
+                <label>{"Duplicate questions"}</label>
+                <select ref={duplicates_ref}>
+                    <option value="skip">{"Skip"}</option>
+                    <option value="update">{"Update the existing card"}</option>
+                    <option value="keep-both">{"Keep both"}</option>
+                </select>
+            </div>
+            <div class="actions">
+                <button type="button" class="submit-button neutral-button" onclick={onpreview}>
+                    {"Preview"}
+                </button>
+                <button type="button"
+                    class="submit-button"
+                    disabled={rows.is_empty()}
+                    onclick={onimport}
+                >{"Import"}</button>
+            </div>
+
+            if !rows.is_empty() {
+                <h3>{"Rows"}</h3>
+                <table class="import-table">
+                    <tr><th>{"Line"}</th><th>{"Question"}</th><th>{"Answer"}</th><th>{"Deck"}</th><th>{"Tags"}</th></tr>
+                    { for preview }
+                </table>
+            }
+            if !report.is_empty() {
+                <h3>{report_title}</h3>
+                <table class="import-table">
+                    <tr><th>{"Line"}</th><th>{"Question"}</th><th>{"Result"}</th></tr>
+                    { for report_rows }
+                </table>
+            }
+        </div>
+    }
+}
+
+// Parses the file into rows using the given delimiter, or the detected one, and
+// columns given by number or header name. Records that can't be imported are returned
+// as report lines.
+fn parse_rows(
+    text: &str,
+    delimiter: Option<char>,
+    header: bool,
+    [q, a, deck, tags]: [String; 4],
+) -> Result<(Vec<Row>, Vec<ReportLine>), String> {
+    let quote = import::detect_quote(text);
+    let delim = delimiter.unwrap_or_else(|| import::detect_delimiter(text, quote));
+
+    let mut records = import::parse(text, delim, quote);
+    let header = if header && !records.is_empty() {
+        Some(records.remove(0))
+    } else {
+        None
+    };
+    let header = header.as_ref();
+    let optional = |spec: &str| {
+        Some(spec.trim())
+            .filter(|spec| !spec.is_empty())
+            .map(|spec| import::resolve_column(spec, header))
+            .transpose()
+    };
+    let columns = ColumnMap {
+        q: import::resolve_column(&q, header)?,
+        a: import::resolve_column(&a, header)?,
+        deck: optional(&deck)?,
+        tags: optional(&tags)?,
+    };
+
+    let mut rows = Vec::with_capacity(records.len());
+    let mut rejected = vec![];
+    for record in &records {
+        match import::to_row(record, &columns) {
+            Ok(row) => rows.push(row),
+            Err(reason) => rejected.push(ReportLine {
+                line: record.line,
+                q: record.fields.first().cloned().unwrap_or_default(),
+                result: reason,
+            }),
+        }
+    }
+    Ok((rows, rejected))
+}
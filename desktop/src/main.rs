@@ -1,10 +1,20 @@
 use yew::prelude::*;
 
+mod account;
+mod anki;
 mod app;
 mod auth;
+mod choices;
 mod commands;
+mod export;
+mod import;
+mod queue;
 mod quiz;
+mod settings;
+mod stats;
 mod submit;
+mod subtitles;
+mod summary;
 
 use app::App;
 use auth::Auth;
@@ -26,9 +36,17 @@ fn main() -> Html {
         }
     };
 
+    let ondisconnect = {
+        let connected = connected.clone();
+
+        move |_| {
+            connected.set(false);
+        }
+    };
+
     html! {
         if *connected {
-            <App />
+            <App {ondisconnect} />
         } else {
             <Auth {onconnect} />
         }
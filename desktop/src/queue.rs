@@ -0,0 +1,62 @@
+use std::rc::Rc;
+
+use message::QA;
+use yew::prelude::*;
+
+// QuizQueue holds the QAs fetched from the server and the one currently being
+// reviewed. It's a reducer rather than plain state because QAs that are still
+// being (re)learned are put back into it from timers, long after the render
+// that scheduled them.
+#[derive(Default, PartialEq)]
+pub struct QuizQueue {
+    qas: Vec<QA>,
+    idx: usize,
+}
+
+pub enum QueueAction {
+    // Replaces the queue with freshly fetched QAs.
+    Refreshed(Vec<QA>),
+    // Moves on to the next QA.
+    Next,
+    // Puts a QA that's due again at the end of the queue.
+    Reinsert(QA),
+}
+
+impl QuizQueue {
+    pub fn current(&self) -> Option<&QA> {
+        self.qas.get(self.idx)
+    }
+
+    // The queue is exhausted once every fetched or reinserted QA has been reviewed.
+    pub fn is_exhausted(&self) -> bool {
+        self.idx >= self.qas.len()
+    }
+}
+
+impl Reducible for QuizQueue {
+    type Action = QueueAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        match action {
+            QueueAction::Refreshed(qas) => Rc::new(Self { qas, idx: 0 }),
+            QueueAction::Next => Rc::new(Self {
+                qas: self.qas.clone(),
+                idx: self.idx + 1,
+            }),
+            QueueAction::Reinsert(qa) => {
+                // The QA might already be waiting in the queue if a refresh fetched
+                // it after it became due.
+                if self.qas[self.idx.min(self.qas.len())..]
+                    .iter()
+                    .any(|pending| pending.id == qa.id)
+                {
+                    return self;
+                }
+
+                let mut qas = self.qas.clone();
+                qas.push(qa);
+                Rc::new(Self { qas, idx: self.idx })
+            }
+        }
+    }
+}
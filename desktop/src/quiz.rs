@@ -1,20 +1,35 @@
 use message::{Message, QA};
-use serde_wasm_bindgen::to_value;
+use serde_wasm_bindgen::{from_value, to_value};
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 
+use crate::choices::ChoicesComponent;
 use crate::commands::review_qa;
 
 #[derive(Properties, PartialEq, Clone)]
 pub struct QuizProperties {
     pub qa: Option<QA>,
-    pub onreview: Callback<()>,
+    // Emitted with the reviewed QA and, if it has to be shown again in this session,
+    // the number of seconds after which it's due.
+    pub onreview: Callback<(QA, Option<u32>)>,
     pub onerror: Callback<String>,
 }
 
 #[function_component(QuizComponent)]
 pub fn quiz(props: &QuizProperties) -> Html {
     let revealed = use_state(|| false);
+    // When the question was shown and how long it took to reveal the answer.
+    let shown_at = use_mut_ref(|| None::<f64>);
+    let response_ms = use_mut_ref(|| None::<u32>);
+    let multiple_choice = use_state(|| false);
+
+    {
+        let shown_at = shown_at.clone();
+        use_effect_with(props.qa.as_ref().map(|qa| qa.id), move |_| {
+            *shown_at.borrow_mut() = Some(js_sys::Date::now());
+        });
+    }
+
     if props.qa.is_none() {
         return html! {
             <p class="cond-render">{ "There are no questions to review" }</p>
@@ -23,9 +38,39 @@ pub fn quiz(props: &QuizProperties) -> Html {
 
     let qa = props.qa.as_ref().unwrap();
 
+    let ontoggle_mode = {
+        let multiple_choice = multiple_choice.clone();
+        move |_| {
+            multiple_choice.set(!*multiple_choice);
+        }
+    };
+    let mode_toggle = html! {
+        <label class="quiz-mode">
+            <input type="checkbox" checked={*multiple_choice} onclick={ontoggle_mode} />
+            {"Multiple choice"}
+        </label>
+    };
+
+    if *multiple_choice {
+        return html! {
+            <>
+                {mode_toggle}
+                <ChoicesComponent qa={qa.clone()}
+                    onreview={props.onreview.clone()}
+                    onerror={props.onerror.clone()}
+                />
+            </>
+        };
+    }
+
     let onreveal = {
         let revealed = revealed.clone();
+        let shown_at = shown_at.clone();
+        let response_ms = response_ms.clone();
         move |_| {
+            if !*revealed {
+                *response_ms.borrow_mut() = elapsed_ms(shown_at.borrow_mut().take());
+            }
             revealed.set(!*revealed);
         }
     };
@@ -38,21 +83,28 @@ pub fn quiz(props: &QuizProperties) -> Html {
     };
 
     let make_review_cb = |correct: bool| /* -> Callback<()> */ {
-        let qa_id = qa.id;
+        let qa = qa.clone();
         let onerror = props.onerror.clone();
         let onreview = props.onreview.clone();
         let revealed = revealed.clone();
+        let shown_at = shown_at.clone();
+        let response_ms = response_ms.clone();
 
         Callback::from(move |_: MouseEvent| {
+            let qa = qa.clone();
             let onerror = onerror.clone();
             let onreview = onreview.clone();
             let revealed = revealed.clone();
+            let shown_at = shown_at.clone();
+            let response_ms = response_ms.borrow_mut().take();
 
             spawn_local(async move {
                 // TODO: what if the server fails? We'll skip this question and go to next one.
-                submit_review_qa(onerror, qa_id, correct).await;
-                onreview.emit(());
+                let due_in = submit_review_qa(onerror, qa.id, correct, response_ms).await;
+                onreview.emit((qa, due_in));
                 revealed.set(false);
+                // The same QA may be shown again, which doesn't rerun the effect above.
+                *shown_at.borrow_mut() = Some(js_sys::Date::now());
             });
         })
     };
@@ -62,6 +114,7 @@ pub fn quiz(props: &QuizProperties) -> Html {
 
     html! {
         <>
+            {mode_toggle}
             <div class="row">
                 <div class="input-group">
                     <label>{"Question"}</label>
@@ -104,13 +157,31 @@ pub fn quiz(props: &QuizProperties) -> Html {
     }
 }
 
-async fn submit_review_qa(onerror: Callback<String>, id: i64, correct: bool) {
-    let msg = Message::ReviewQA { id, correct };
+pub async fn submit_review_qa(
+    onerror: Callback<String>,
+    id: i64,
+    correct: bool,
+    response_ms: Option<u32>,
+) -> Option<u32> {
+    let msg = Message::ReviewQA {
+        id,
+        correct,
+        response_ms,
+    };
     let args = to_value(&msg).unwrap();
     match review_qa(args).await {
-        Ok(_) => {
+        Ok(jsval) => {
             onerror.emit("".to_string());
+            from_value(jsval).unwrap_or(None)
+        }
+        Err(e) => {
+            onerror.emit(e.as_string().unwrap());
+            None
         }
-        Err(e) => onerror.emit(e.as_string().unwrap()),
     }
 }
+
+// Returns the milliseconds passed since `since`, a timestamp from `Date::now`.
+pub fn elapsed_ms(since: Option<f64>) -> Option<u32> {
+    since.map(|since| (js_sys::Date::now() - since).max(0.0) as u32)
+}
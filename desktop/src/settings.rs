@@ -0,0 +1,261 @@
+use gloo_timers::callback::Timeout;
+use message::{Message, MixOrder, Settings};
+use serde_wasm_bindgen::{from_value, to_value};
+use yew::platform::spawn_local;
+use yew::prelude::*;
+
+use crate::account::AccountComponent;
+use crate::commands::{get_settings, update_settings};
+
+#[derive(Properties, PartialEq)]
+pub struct SettingsProperties {
+    pub onerror: Callback<String>,
+    pub ondelete: Callback<()>,
+}
+
+#[function_component(SettingsComponent)]
+pub fn settings(props: &SettingsProperties) -> Html {
+    let settings = use_state(|| None::<Settings>);
+    let save_success = use_state(|| false);
+
+    let new_per_day_ref = use_node_ref();
+    let reviews_per_day_ref = use_node_ref();
+    let mix_ref = use_node_ref();
+    let learning_steps_ref = use_node_ref();
+    let relearning_steps_ref = use_node_ref();
+    let timezone_ref = use_node_ref();
+    let rollover_hour_ref = use_node_ref();
+    let leech_threshold_ref = use_node_ref();
+    let leech_suspend_ref = use_node_ref();
+    let hard_after_secs_ref = use_node_ref();
+
+    {
+        let settings = settings.clone();
+        let onerror = props.onerror.clone();
+
+        use_effect_with((), move |_| {
+            spawn_local(async move {
+                match get_settings().await {
+                    Ok(jsval) => match from_value(jsval) {
+                        Ok(fetched) => settings.set(Some(fetched)),
+                        Err(e) => onerror.emit(e.to_string()),
+                    },
+                    Err(e) => onerror.emit(e.as_string().unwrap()),
+                }
+            });
+        });
+    }
+
+    let checkmark_class = if *save_success { "visible" } else { "hidden" };
+
+    let onsave = {
+        let save_success = save_success.clone();
+        let onerror = props.onerror.clone();
+        let new_per_day_ref = new_per_day_ref.clone();
+        let reviews_per_day_ref = reviews_per_day_ref.clone();
+        let mix_ref = mix_ref.clone();
+        let learning_steps_ref = learning_steps_ref.clone();
+        let relearning_steps_ref = relearning_steps_ref.clone();
+        let timezone_ref = timezone_ref.clone();
+        let rollover_hour_ref = rollover_hour_ref.clone();
+        let leech_threshold_ref = leech_threshold_ref.clone();
+        let leech_suspend_ref = leech_suspend_ref.clone();
+        let hard_after_secs_ref = hard_after_secs_ref.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            onerror.emit("".to_string());
+
+            let input = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<web_sys::HtmlInputElement>()
+                    .unwrap()
+                    .value()
+            };
+            let mix = match mix_ref
+                .cast::<web_sys::HtmlSelectElement>()
+                .unwrap()
+                .value()
+                .as_str()
+            {
+                "new-first" => MixOrder::NewFirst,
+                "reviews-first" => MixOrder::ReviewsFirst,
+                _ => MixOrder::Mixed,
+            };
+
+            let parsed = (|| -> Result<Settings, String> {
+                let number = |node_ref: &NodeRef, name: &str| {
+                    input(node_ref)
+                        .trim()
+                        .parse::<u16>()
+                        .map_err(|_| format!("{name} must be a positive number"))
+                };
+
+                let settings = Settings {
+                    new_per_day: number(&new_per_day_ref, "New cards per day")?,
+                    reviews_per_day: number(&reviews_per_day_ref, "Reviews per day")?,
+                    mix,
+                    learning_steps: message::parse_steps(&input(&learning_steps_ref))?,
+                    relearning_steps: message::parse_steps(&input(&relearning_steps_ref))?,
+                    timezone: input(&timezone_ref).trim().to_string(),
+                    rollover_hour: number(&rollover_hour_ref, "Rollover hour")?
+                        .try_into()
+                        .map_err(|_| "Rollover hour must be between 0 and 23".to_string())?,
+                    leech_threshold: number(&leech_threshold_ref, "Leech threshold")?,
+                    leech_suspend: leech_suspend_ref
+                        .cast::<web_sys::HtmlInputElement>()
+                        .unwrap()
+                        .checked(),
+                    hard_after_secs: number(&hard_after_secs_ref, "Hard after")?,
+                };
+                settings.validate()?;
+                Ok(settings)
+            })();
+
+            let settings = match parsed {
+                Ok(settings) => settings,
+                Err(e) => {
+                    onerror.emit(e);
+                    return;
+                }
+            };
+
+            let save_success = save_success.clone();
+            let onerror = onerror.clone();
+            spawn_local(async move {
+                let msg = Message::UpdateSettings { settings };
+                let args = to_value(&msg).unwrap();
+                match update_settings(args).await {
+                    Ok(_) => {
+                        save_success.set(true);
+
+                        let save_success = save_success.clone();
+                        Timeout::new(2000, move || {
+                            save_success.set(false);
+                        })
+                        .forget();
+                    }
+                    Err(e) => onerror.emit(e.as_string().unwrap()),
+                }
+            });
+        })
+    };
+
+    let ondetect_timezone = {
+        let timezone_ref = timezone_ref.clone();
+        Callback::from(move |_: MouseEvent| {
+            if let Some(timezone) = device_timezone() {
+                timezone_ref
+                    .cast::<web_sys::HtmlInputElement>()
+                    .unwrap()
+                    .set_value(&timezone);
+            }
+        })
+    };
+
+    let Some(settings) = settings.as_ref() else {
+        return html! {
+            <p class="cond-render">{ "Loading settings..." }</p>
+        };
+    };
+
+    html! {
+        <>
+            <div class="settings">
+                <label>{"New cards per day"}</label>
+                <input ref={new_per_day_ref}
+                    type="number"
+                    min="0"
+                    value={settings.new_per_day.to_string()}
+                />
+
+                <label>{"Reviews per day"}</label>
+                <input ref={reviews_per_day_ref}
+                    type="number"
+                    min="0"
+                    value={settings.reviews_per_day.to_string()}
+                />
+
+                <label>{"Order"}</label>
+                <select ref={mix_ref}>
+                    <option value="mixed" selected={settings.mix == MixOrder::Mixed}>
+                        {"Mix new cards and reviews"}
+                    </option>
+                    <option value="new-first" selected={settings.mix == MixOrder::NewFirst}>
+                        {"New cards first"}
+                    </option>
+                    <option value="reviews-first" selected={settings.mix == MixOrder::ReviewsFirst}>
+                        {"Reviews first"}
+                    </option>
+                </select>
+
+                <label>{"Learning steps"}</label>
+                <input ref={learning_steps_ref}
+                    type="text"
+                    placeholder="1m 10m"
+                    value={message::format_steps(&settings.learning_steps)}
+                />
+
+                <label>{"Relearning steps"}</label>
+                <input ref={relearning_steps_ref}
+                    type="text"
+                    placeholder="10m"
+                    value={message::format_steps(&settings.relearning_steps)}
+                />
+
+                <label>{"Time zone"}</label>
+                <div class="settings-timezone">
+                    <input ref={timezone_ref}
+                        type="text"
+                        placeholder="Europe/Helsinki"
+                        value={settings.timezone.clone()}
+                    />
+                    <button type="button"
+                        class="submit-button neutral-button"
+                        onclick={ondetect_timezone}
+                    >{"Use this device's"}</button>
+                </div>
+
+                <label>{"New day starts at (hour)"}</label>
+                <input ref={rollover_hour_ref}
+                    type="number"
+                    min="0"
+                    max="23"
+                    value={settings.rollover_hour.to_string()}
+                />
+
+                <label>{"Leech threshold (lapses)"}</label>
+                <input ref={leech_threshold_ref}
+                    type="number"
+                    min="1"
+                    value={settings.leech_threshold.to_string()}
+                />
+
+                <label>{"Suspend leeches"}</label>
+                <input ref={leech_suspend_ref}
+                    type="checkbox"
+                    checked={settings.leech_suspend}
+                />
+
+                <label>{"Slow answers are hard after (seconds, 0 for never)"}</label>
+                <input ref={hard_after_secs_ref}
+                    type="number"
+                    min="0"
+                    value={settings.hard_after_secs.to_string()}
+                />
+            </div>
+            <div class="actions actions-margined">
+                <button type="submit" class="submit-button" onclick={onsave}>{"Save"}</button>
+                <span class={classes!("checkmark", checkmark_class)}>{ "\u{2713}" }</span>
+            </div>
+            <AccountComponent onerror={props.onerror.clone()} ondelete={props.ondelete.clone()} />
+        </>
+    }
+}
+
+// Returns the IANA name of the time zone this device is set to.
+fn device_timezone() -> Option<String> {
+    let format = js_sys::Intl::DateTimeFormat::new(&js_sys::Array::new(), &js_sys::Object::new());
+    js_sys::Reflect::get(&format.resolved_options(), &"timeZone".into())
+        .ok()?
+        .as_string()
+}
@@ -0,0 +1,116 @@
+use message::Stats;
+use serde_wasm_bindgen::from_value;
+use yew::platform::spawn_local;
+use yew::prelude::*;
+
+use crate::commands::get_stats;
+
+#[derive(Properties, PartialEq)]
+pub struct StatsProperties {
+    pub onerror: Callback<String>,
+}
+
+#[function_component(StatsComponent)]
+pub fn stats(props: &StatsProperties) -> Html {
+    let stats = use_state(|| None::<Stats>);
+
+    {
+        let stats = stats.clone();
+        let onerror = props.onerror.clone();
+
+        use_effect_with((), move |_| {
+            spawn_local(async move {
+                match get_stats().await {
+                    Ok(jsval) => match from_value(jsval) {
+                        Ok(fetched) => stats.set(Some(fetched)),
+                        Err(e) => onerror.emit(e.to_string()),
+                    },
+                    Err(e) => onerror.emit(e.as_string().unwrap()),
+                }
+            });
+        });
+    }
+
+    let Some(stats) = stats.as_ref() else {
+        return html! {
+            <p class="cond-render">{ "Loading statistics..." }</p>
+        };
+    };
+
+    let reviews_today = stats.reviews_per_day.last().copied().unwrap_or(0);
+    let retention = match stats.retention {
+        Some(retention) => format!("{:.0}%", retention * 100.0),
+        None => "-".to_string(),
+    };
+    let cards = stats.cards;
+
+    html! {
+        <div class="stats">
+            <div class="stats-summary">
+                {summary_item("Reviews today", reviews_today.to_string())}
+                {summary_item("Retention", retention)}
+                {summary_item("Current streak", days(stats.current_streak))}
+                {summary_item("Longest streak", days(stats.longest_streak))}
+                {summary_item("Time spent", duration(stats.time_spent))}
+            </div>
+
+            <h3>{"Cards"}</h3>
+            <div class="stats-summary">
+                {summary_item("New", cards.new.to_string())}
+                {summary_item("Learning", cards.learning.to_string())}
+                {summary_item("Young", cards.young.to_string())}
+                {summary_item("Mature", cards.mature.to_string())}
+                {summary_item("Suspended", cards.suspended.to_string())}
+            </div>
+
+            <h3>{"Reviews per day"}</h3>
+            {bar_chart(&stats.reviews_per_day)}
+
+            <h3>{"Due in the next days"}</h3>
+            {bar_chart(&stats.forecast)}
+        </div>
+    }
+}
+
+pub fn summary_item(label: &str, value: String) -> Html {
+    html! {
+        <div class="stats-item">
+            <span class="stats-value">{value}</span>
+            <span class="stats-label">{label}</span>
+        </div>
+    }
+}
+
+// Draws one bar per day, scaled to the busiest day.
+fn bar_chart(counts: &[u32]) -> Html {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let bars = counts.iter().map(|&count| {
+        let style = format!("height: {}%", count * 100 / max);
+        html! {
+            <div class="stats-bar" title={count.to_string()}>
+                <div class="stats-bar-fill" {style}></div>
+            </div>
+        }
+    });
+
+    html! {
+        <div class="stats-chart">{ for bars }</div>
+    }
+}
+
+fn days(n: u32) -> String {
+    if n == 1 {
+        "1 day".to_string()
+    } else {
+        format!("{n} days")
+    }
+}
+
+fn duration(secs: u32) -> String {
+    let mins = secs / 60;
+    if mins < 60 {
+        format!("{mins}m")
+    } else {
+        format!("{}h {}m", mins / 60, mins % 60)
+    }
+}
@@ -15,6 +15,8 @@ pub struct SubmitProperties {
 pub fn submit(props: &SubmitProperties) -> Html {
     let q_ref = use_node_ref();
     let a_ref = use_node_ref();
+    let deck_ref = use_node_ref();
+    let tags_ref = use_node_ref();
 
     let submit_disabled = use_state(|| true);
     let submit_success = use_state(|| false);
@@ -25,6 +27,8 @@ pub fn submit(props: &SubmitProperties) -> Html {
         let submit_success = submit_success.clone();
         let q_ref = q_ref.clone();
         let a_ref = a_ref.clone();
+        let deck_ref = deck_ref.clone();
+        let tags_ref = tags_ref.clone();
         let onerror = props.onerror.clone();
 
         Callback::from(move |_: MouseEvent| {
@@ -45,12 +49,33 @@ pub fn submit(props: &SubmitProperties) -> Html {
                 return;
             }
 
+            // The deck and tags are kept as they are, so that several QAs can be
+            // added to the same deck in a row.
+            let deck = deck_ref
+                .cast::<web_sys::HtmlInputElement>()
+                .unwrap()
+                .value()
+                .trim()
+                .to_string();
+            let tags = tags_ref
+                .cast::<web_sys::HtmlInputElement>()
+                .unwrap()
+                .value();
+
             let submit_success = submit_success.clone();
             let onerror = onerror.clone();
             let q_ref = q_ref.clone();
             let a_ref = a_ref.clone();
             spawn_local(async move {
-                let msg = Message::AddQA { q: &q, a: &a };
+                let msg = Message::AddQA {
+                    q: &q,
+                    a: &a,
+                    deck: Some(deck.as_str()).filter(|deck| !deck.is_empty()),
+                    tags: tags
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|tag| !tag.is_empty())
+                        .collect(),
+                };
                 let args = to_value(&msg).unwrap();
                 let res = add_qa(args).await;
                 match res {
@@ -149,6 +174,16 @@ pub fn submit(props: &SubmitProperties) -> Html {
                     />
                 </div>
             </div>
+            <div class="row">
+                <div class="input-group">
+                    <label>{"Deck"}</label>
+                    <input ref={deck_ref} type="text" placeholder="Optional" />
+                </div>
+                <div class="input-group">
+                    <label>{"Tags"}</label>
+                    <input ref={tags_ref} type="text" placeholder="e.g. verbs, chapter-1" />
+                </div>
+            </div>
             <div class="actions actions-margined">
                 <button type="submit" disabled={*submit_disabled} class="submit-button" onclick={submit_qa}>{"Submit"}</button>
                 <span class={classes!("checkmark", checkmark_class)}>{ "\u{2713}" }</span>
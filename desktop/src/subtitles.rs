@@ -0,0 +1,247 @@
+use message::import::{self, Row};
+use message::subtitles::{self, Candidate};
+use message::{DuplicateMode, ImportOutcome};
+use serde_wasm_bindgen::{from_value, to_value};
+use wasm_bindgen_futures::JsFuture;
+use yew::platform::spawn_local;
+use yew::prelude::*;
+
+use crate::commands::import_qas;
+use crate::import::ImportProperties;
+
+// A sentence found in the subtitles, whether it's picked and the answer it gets, which
+// starts out as its translation.
+#[derive(Clone, PartialEq)]
+struct Sentence {
+    candidate: Candidate,
+    picked: bool,
+    answer: String,
+}
+
+#[function_component(SubtitlesComponent)]
+pub fn subtitles_import(props: &ImportProperties) -> Html {
+    let text = use_state(|| None::<String>);
+    let translation = use_state(|| None::<String>);
+    let sentences = use_state(Vec::<Sentence>::new);
+    let report = use_state(Vec::<(String, String)>::new);
+
+    let deck_ref = use_node_ref();
+    let tags_ref = use_node_ref();
+
+    let make_file_cb = |target: &UseStateHandle<Option<String>>| {
+        let target = target.clone();
+        let sentences = sentences.clone();
+        let report = report.clone();
+        let onerror = props.onerror.clone();
+
+        Callback::from(move |e: Event| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let Some(file) = input.files().and_then(|files| files.get(0)) else {
+                target.set(None);
+                return;
+            };
+
+            let target = target.clone();
+            let sentences = sentences.clone();
+            let report = report.clone();
+            let onerror = onerror.clone();
+            spawn_local(async move {
+                match JsFuture::from(file.text()).await {
+                    Ok(jsval) => {
+                        target.set(jsval.as_string());
+                        sentences.set(vec![]);
+                        report.set(vec![]);
+                    }
+                    Err(_) => onerror.emit("Couldn't read the file".to_string()),
+                }
+            });
+        })
+    };
+    let onfile = make_file_cb(&text);
+    let ontranslation = make_file_cb(&translation);
+
+    let onfind = {
+        let text = text.clone();
+        let translation = translation.clone();
+        let sentences = sentences.clone();
+        let report = report.clone();
+        let onerror = props.onerror.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            onerror.emit("".to_string());
+            let Some(text) = text.as_ref() else {
+                onerror.emit("Choose the subtitles to mine".to_string());
+                return;
+            };
+
+            let cues = subtitles::parse(text);
+            let translation = translation.as_deref().map(subtitles::parse);
+            let found: Vec<_> = subtitles::candidates(&cues, translation.as_deref())
+                .into_iter()
+                .map(|candidate| Sentence {
+                    answer: candidate.translation.clone().unwrap_or_default(),
+                    picked: false,
+                    candidate,
+                })
+                .collect();
+            if found.is_empty() {
+                onerror.emit("No sentences were found in the subtitles".to_string());
+            }
+            sentences.set(found);
+            report.set(vec![]);
+        })
+    };
+
+    let onadd = {
+        let sentences = sentences.clone();
+        let report = report.clone();
+        let onerror = props.onerror.clone();
+        let deck_ref = deck_ref.clone();
+        let tags_ref = tags_ref.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            onerror.emit("".to_string());
+            let input = |node_ref: &NodeRef| {
+                node_ref
+                    .cast::<web_sys::HtmlInputElement>()
+                    .unwrap()
+                    .value()
+            };
+            let deck = Some(input(&deck_ref).trim().to_string()).filter(|d| !d.is_empty());
+            let tags = import::split_tags(&input(&tags_ref));
+
+            let mut rows = vec![];
+            for (i, s) in sentences.iter().enumerate().filter(|(_, s)| s.picked) {
+                let row = s.candidate.to_row(i + 1, Some(&s.answer));
+                rows.push(Row {
+                    deck: deck.clone(),
+                    tags: tags.iter().cloned().chain(row.tags).collect(),
+                    ..row
+                });
+            }
+            if rows.is_empty() {
+                onerror.emit("Pick the sentences to add".to_string());
+                return;
+            }
+
+            let report = report.clone();
+            let onerror = onerror.clone();
+            spawn_local(async move {
+                let res = import_qas(
+                    to_value(&rows).unwrap(),
+                    to_value(&DuplicateMode::Skip).unwrap(),
+                    false,
+                )
+                .await;
+                let outcomes: Vec<Option<ImportOutcome>> = match res {
+                    Ok(jsval) => match from_value(jsval) {
+                        Ok(outcomes) => outcomes,
+                        Err(e) => return onerror.emit(e.to_string()),
+                    },
+                    Err(e) => return onerror.emit(e.as_string().unwrap()),
+                };
+
+                let lines = rows
+                    .into_iter()
+                    .zip(outcomes)
+                    .map(|(row, outcome)| {
+                        let result = match outcome {
+                            Some(outcome) => format!("{outcome:?}"),
+                            None => "Too long".to_string(),
+                        };
+                        (row.q, result)
+                    })
+                    .collect();
+                report.set(lines);
+            });
+        })
+    };
+
+    let rows = sentences.iter().enumerate().map(|(i, s)| {
+        let ontoggle = {
+            let sentences = sentences.clone();
+            Callback::from(move |e: Event| {
+                let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+                let mut updated = (*sentences).clone();
+                updated[i].picked = input.checked();
+                sentences.set(updated);
+            })
+        };
+        let onanswer = {
+            let sentences = sentences.clone();
+            Callback::from(move |e: Event| {
+                let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+                let mut updated = (*sentences).clone();
+                updated[i].answer = input.value();
+                sentences.set(updated);
+            })
+        };
+
+        html! {
+            <tr>
+                <td><input type="checkbox" checked={s.picked} onchange={ontoggle} /></td>
+                <td>{i + 1}</td>
+                <td>{subtitles::format_time(s.candidate.start_ms)}</td>
+                <td>{&s.candidate.text}</td>
+                <td><input type="text" value={s.answer.clone()} onchange={onanswer} /></td>
+            </tr>
+        }
+    });
+    let picked = sentences.iter().filter(|s| s.picked).count();
+
+    let report_rows = report.iter().map(|(q, result)| {
+        html! {
+            <tr>
+                <td>{q}</td>
+                <td>{result}</td>
+            </tr>
+        }
+    });
+    let added = report
+        .iter()
+        .filter(|(_, result)| result == "Added")
+        .count();
+
+    html! {
+        <div class="import">
+            <div class="settings">
+                <label>{"Subtitles"}</label>
+                <input type="file" accept=".srt,.vtt" onchange={onfile} />
+
+                <label>{"Translation (optional)"}</label>
+                <input type="file" accept=".srt,.vtt" onchange={ontranslation} />
+
+                <label>{"Deck"}</label>
+                <input ref={deck_ref} type="text" placeholder="None" />
+
+                <label>{"Tags"}</label>
+                <input ref={tags_ref} type="text" placeholder="None" />
+            </div>
+            <div class="actions">
+                <button type="button" class="submit-button neutral-button" onclick={onfind}>
+                    {"Find sentences"}
+                </button>
+                <button type="button"
+                    class="submit-button"
+                    disabled={picked == 0}
+                    onclick={onadd}
+                >{format!("Add {picked} selected")}</button>
+            </div>
+
+            if !sentences.is_empty() {
+                <h3>{format!("{} sentences", sentences.len())}</h3>
+                <table class="import-table">
+                    <tr><th></th><th>{"#"}</th><th>{"Time"}</th><th>{"Sentence"}</th><th>{"Answer"}</th></tr>
+                    { for rows }
+                </table>
+            }
+            if !report.is_empty() {
+                <h3>{format!("Added {added} of {} sentences", report.len())}</h3>
+                <table class="import-table">
+                    <tr><th>{"Question"}</th><th>{"Result"}</th></tr>
+                    { for report_rows }
+                </table>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,59 @@
+use message::SessionSummary;
+use yew::prelude::*;
+
+use crate::stats::summary_item;
+
+#[derive(Properties, PartialEq)]
+pub struct SummaryProperties {
+    pub summary: SessionSummary,
+    pub onclose: Callback<()>,
+}
+
+// SummaryComponent is shown once a session runs out of QAs to review.
+#[function_component(SummaryComponent)]
+pub fn summary(props: &SummaryProperties) -> Html {
+    let summary = &props.summary;
+
+    let accuracy = if summary.reviewed == 0 {
+        "-".to_string()
+    } else {
+        format!(
+            "{:.0}%",
+            summary.correct as f32 * 100.0 / summary.reviewed as f32
+        )
+    };
+    let next_due = match summary.next_due_in {
+        Some(0) => "Now".to_string(),
+        Some(secs) => format!("In {}", duration(secs)),
+        None => "-".to_string(),
+    };
+
+    let onclose = {
+        let onclose = props.onclose.clone();
+        move |_| onclose.emit(())
+    };
+
+    html! {
+        <div class="summary">
+            <h3>{"Session complete"}</h3>
+            <div class="stats-summary">
+                {summary_item("Cards reviewed", summary.reviewed.to_string())}
+                {summary_item("Accuracy", accuracy)}
+                {summary_item("Time", duration(summary.duration))}
+                {summary_item("Still failing", summary.failing.to_string())}
+                {summary_item("Next review", next_due)}
+            </div>
+            <div class="actions actions-margined">
+                <button type="button" class="submit-button" onclick={onclose}>{"Done"}</button>
+            </div>
+        </div>
+    }
+}
+
+fn duration(secs: u32) -> String {
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        _ => format!("{}h {}m", secs / 3600, secs / 60 % 60),
+    }
+}
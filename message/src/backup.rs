@@ -0,0 +1,98 @@
+// The file a backup is saved to, which holds the items of each part in the order they
+// are restored in.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{BackupDeck, BackupItem, BackupQA, BackupReview, BackupSession, Settings};
+
+// Version of the file format, increased when it changes in a way older restores can't
+// read.
+pub const BACKUP_VERSION: u32 = 1;
+
+// Name of the backup in a zip archive.
+pub const BACKUP_ENTRY: &str = "backup.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Backup {
+    pub version: u32,
+    pub settings: Option<Settings>,
+    pub decks: Vec<BackupDeck>,
+    pub qas: Vec<BackupQA>,
+    pub sessions: Vec<BackupSession>,
+    pub reviews: Vec<BackupReview>,
+}
+
+impl Default for Backup {
+    fn default() -> Self {
+        Self {
+            version: BACKUP_VERSION,
+            settings: None,
+            decks: vec![],
+            qas: vec![],
+            sessions: vec![],
+            reviews: vec![],
+        }
+    }
+}
+
+impl Backup {
+    pub fn push(&mut self, item: BackupItem) {
+        match item {
+            BackupItem::Settings(settings) => self.settings = Some(settings),
+            BackupItem::Deck(deck) => self.decks.push(deck),
+            BackupItem::QA(qa) => self.qas.push(qa),
+            BackupItem::Session(session) => self.sessions.push(session),
+            BackupItem::Review(review) => self.reviews.push(review),
+        }
+    }
+
+    // Returns the items in the order they're restored in.
+    pub fn into_items(self) -> Vec<BackupItem> {
+        self.settings
+            .map(BackupItem::Settings)
+            .into_iter()
+            .chain(self.decks.into_iter().map(BackupItem::Deck))
+            .chain(self.qas.into_iter().map(BackupItem::QA))
+            .chain(self.sessions.into_iter().map(BackupItem::Session))
+            .chain(self.reviews.into_iter().map(BackupItem::Review))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_items() {
+        let items = vec![
+            BackupItem::Settings(Settings::default()),
+            BackupItem::Deck(BackupDeck {
+                id: 3,
+                name: "Finnish".into(),
+            }),
+            BackupItem::QA(BackupQA {
+                id: 5,
+                deck_id: Some(3),
+                ..Default::default()
+            }),
+            BackupItem::Session(BackupSession {
+                id: 2,
+                ..Default::default()
+            }),
+            BackupItem::Review(BackupReview {
+                id: 9,
+                qa_id: 5,
+                session_id: Some(2),
+                ..Default::default()
+            }),
+        ];
+
+        let mut backup = Backup::default();
+        for item in items.iter().rev() {
+            backup.push(item.clone());
+        }
+        assert_eq!(backup.version, BACKUP_VERSION);
+        assert_eq!(backup.into_items(), items);
+    }
+}
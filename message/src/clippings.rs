@@ -0,0 +1,332 @@
+// Parsing of e-reader highlights into draft QAs, from Kindle's "My Clippings.txt" and
+// the JSON KOReader exports highlights to.
+
+use serde::Deserialize;
+
+use crate::import::Row;
+
+// Line that ends each clipping of My Clippings.txt.
+const KINDLE_SEPARATOR: &str = "==========";
+
+// Tag of every imported highlight, so that the drafts are easy to find and finish.
+pub const DRAFT_TAG: &str = "draft";
+
+// A highlighted word or passage, where it is in the book and the note the reader left
+// on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Highlight {
+    pub book: String,
+    pub location: String,
+    pub text: String,
+    pub note: Option<String>,
+}
+
+impl Highlight {
+    // Highlights don't come with an answer, so the draft's answer holds the note and the
+    // location until it's written. The book is given as a tag.
+    pub fn to_row(&self, line: usize) -> Row {
+        let a = [self.note.as_deref(), Some(self.location.as_str())]
+            .into_iter()
+            .flatten()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let tags = [book_tag(&self.book), DRAFT_TAG.to_string()]
+            .into_iter()
+            .filter(|tag| !tag.is_empty())
+            .collect();
+
+        Row {
+            line,
+            q: self.text.clone(),
+            a: if a.is_empty() { self.book.clone() } else { a },
+            deck: None,
+            tags,
+        }
+    }
+}
+
+// Parses My Clippings.txt. Each clipping is the title, a line describing it and its
+// text, like:
+//
+// The Hobbit (J.R.R. Tolkien)
+// - Your Highlight on page 12 | Location 170-172 | Added on Monday, 1 January 2024
+//
+// In a hole in the ground there lived a hobbit.
+// ==========
+//
+// Bookmarks are skipped and notes are attached to the highlight they were written on.
+pub fn parse_kindle(text: &str) -> Vec<Highlight> {
+    let text = text.replace("\r\n", "\n");
+
+    let mut highlights = Vec::new();
+    let mut notes = Vec::new();
+    for clipping in text.split(KINDLE_SEPARATOR) {
+        let mut lines = clipping
+            .lines()
+            .map(|l| l.trim_start_matches('\u{feff}').trim())
+            .skip_while(|l| l.is_empty());
+        let (Some(title), Some(meta)) = (lines.next(), lines.next()) else {
+            continue;
+        };
+        let body = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+        if body.is_empty() {
+            continue;
+        }
+
+        // The last part is when the clipping was added.
+        let mut parts: Vec<_> = meta
+            .trim_start_matches('-')
+            .split('|')
+            .map(str::trim)
+            .collect();
+        if parts.len() > 1 {
+            parts.pop();
+        }
+        let kind = parts[0].to_lowercase();
+        let start = kind
+            .find("page")
+            .or_else(|| kind.find("loc"))
+            .unwrap_or(kind.len());
+        parts[0] = parts[0].get(start..).unwrap_or(parts[0]);
+
+        let highlight = Highlight {
+            book: kindle_title(title).to_string(),
+            location: parts.join(", "),
+            text: body,
+            note: None,
+        };
+        if kind.contains("note") {
+            notes.push(highlight);
+        } else if !kind.contains("bookmark") {
+            highlights.push(highlight);
+        }
+    }
+
+    let mut highlights = dedupe(highlights);
+    for note in notes {
+        let Some((at, _)) = location_range(&note.location) else {
+            continue;
+        };
+        let highlight = highlights.iter_mut().find(|h| {
+            h.book == note.book
+                && location_range(&h.location).is_some_and(|(start, end)| start <= at && at <= end)
+        });
+        if let Some(highlight) = highlight {
+            highlight.note = Some(note.text);
+        }
+    }
+    highlights
+}
+
+// Drops the author Kindle appends to the title in parentheses.
+fn kindle_title(title: &str) -> &str {
+    match title.rfind(" (") {
+        Some(i) if title.ends_with(')') && i > 0 => &title[..i],
+        _ => title,
+    }
+}
+
+// Returns the first and last location of "Location 170-172" or "Loc. 170-72". Kindle
+// used to shorten the last one, which is then taken to be the first.
+fn location_range(location: &str) -> Option<(u32, u32)> {
+    let lower = location.to_lowercase();
+    let rest = &lower[lower.find("loc")?..];
+    let rest = rest.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let (start, end) = match rest.split_once('-') {
+        Some((start, end)) => (start, end),
+        None => (rest, rest),
+    };
+    let number = |s: &str| {
+        s.chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+            .parse::<u32>()
+            .ok()
+    };
+    let start = number(start)?;
+    let end = number(end).filter(|&end| end >= start).unwrap_or(start);
+    Some((start, end))
+}
+
+// What KOReader's JSON exporter writes, either a single book or all of them.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum KoreaderExport {
+    All { documents: Vec<KoreaderBook> },
+    Book(KoreaderBook),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KoreaderBook {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub entries: Vec<KoreaderEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KoreaderEntry {
+    #[serde(default)]
+    pub text: String,
+    pub note: Option<String>,
+    pub chapter: Option<String>,
+    pub page: Option<KoreaderPage>,
+}
+
+// Pages are numbers, unless the book is paged by the publisher's page labels.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum KoreaderPage {
+    Number(u32),
+    Label(String),
+}
+
+pub fn from_koreader(export: KoreaderExport) -> Vec<Highlight> {
+    let books = match export {
+        KoreaderExport::All { documents } => documents,
+        KoreaderExport::Book(book) => vec![book],
+    };
+
+    let mut highlights = Vec::new();
+    for book in books {
+        for entry in book.entries {
+            let text = entry.text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let page = entry.page.map(|page| match page {
+                KoreaderPage::Number(n) => format!("page {n}"),
+                KoreaderPage::Label(label) => format!("page {label}"),
+            });
+            let location = [entry.chapter, page]
+                .into_iter()
+                .flatten()
+                .filter(|s| !s.trim().is_empty())
+                .collect::<Vec<_>>()
+                .join(", ");
+            highlights.push(Highlight {
+                book: book.title.clone(),
+                location,
+                text: text.to_string(),
+                note: entry
+                    .note
+                    .map(|n| n.trim().to_string())
+                    .filter(|n| !n.is_empty()),
+            });
+        }
+    }
+    dedupe(highlights)
+}
+
+// Extending a highlight on a Kindle adds a new clipping and keeps the old one, so of
+// highlights in the same book where one contains the other only the longest is kept.
+fn dedupe(highlights: Vec<Highlight>) -> Vec<Highlight> {
+    let mut kept: Vec<Highlight> = Vec::with_capacity(highlights.len());
+    for highlight in highlights {
+        let same = kept.iter().position(|k| {
+            k.book == highlight.book
+                && (k.text.contains(&highlight.text) || highlight.text.contains(&k.text))
+        });
+        match same {
+            Some(i) if highlight.text.len() > kept[i].text.len() => kept[i] = highlight,
+            Some(_) => {}
+            None => kept.push(highlight),
+        }
+    }
+    kept
+}
+
+// Turns a title into a tag, which can't have spaces or commas in it.
+pub fn book_tag(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kindle() {
+        let text = "\u{feff}The Hobbit (J.R.R. Tolkien)\r\n\
+            - Your Highlight on page 1 | Location 10-11 | Added on Monday, 1 January 2024\r\n\
+            \r\n\
+            In a hole\r\n\
+            ==========\r\n\
+            The Hobbit (J.R.R. Tolkien)\r\n\
+            - Your Highlight on page 1 | Location 10-12 | Added on Monday, 1 January 2024\r\n\
+            \r\n\
+            In a hole in the ground\r\n\
+            ==========\r\n\
+            The Hobbit (J.R.R. Tolkien)\r\n\
+            - Your Note on page 1 | Location 12 | Added on Monday, 1 January 2024\r\n\
+            \r\n\
+            kolo\r\n\
+            ==========\r\n\
+            The Hobbit (J.R.R. Tolkien)\r\n\
+            - Your Bookmark on page 2 | Location 20 | Added on Monday, 1 January 2024\r\n\
+            \r\n\
+            \r\n\
+            ==========\r\n\
+            Sinuhe\r\n\
+            - Highlight Loc. 170-72 | Added on Tuesday, 2 January 2024\r\n\
+            \r\n\
+            Minä, Sinuhe\r\n\
+            ==========\r\n";
+
+        assert_eq!(
+            parse_kindle(text),
+            vec![
+                Highlight {
+                    book: "The Hobbit".into(),
+                    location: "page 1, Location 10-12".into(),
+                    text: "In a hole in the ground".into(),
+                    note: Some("kolo".into()),
+                },
+                Highlight {
+                    book: "Sinuhe".into(),
+                    location: "Loc. 170-72".into(),
+                    text: "Minä, Sinuhe".into(),
+                    note: None,
+                },
+            ]
+        );
+        assert_eq!(location_range("Loc. 170-72"), Some((170, 170)));
+        assert_eq!(location_range("page 3, Location 5-9"), Some((5, 9)));
+        assert_eq!(location_range("page 3"), None);
+    }
+
+    #[test]
+    fn test_from_koreader() {
+        let json = r#"{"documents": [{"title": "Seitsemän veljestä", "author": "Aleksis Kivi",
+            "entries": [
+                {"text": "Jukola", "chapter": "Ensimmäinen luku", "page": 5, "note": "talo"},
+                {"text": "  ", "page": 6},
+                {"text": "Impivaara", "page": "xii"}
+            ]}], "version": "en"}"#;
+        let export: KoreaderExport = serde_json::from_str(json).unwrap();
+        let highlights = from_koreader(export);
+
+        assert_eq!(
+            highlights[0].to_row(1),
+            Row {
+                line: 1,
+                q: "Jukola".into(),
+                a: "talo\nEnsimmäinen luku, page 5".into(),
+                deck: None,
+                tags: vec!["seitsemän-veljestä".into(), DRAFT_TAG.into()],
+            }
+        );
+        assert_eq!(highlights[1].location, "page xii");
+        assert_eq!(highlights.len(), 2);
+
+        let json = r#"{"title": "Kalevala", "entries": [{"text": "Vaka vanha"}]}"#;
+        let export: KoreaderExport = serde_json::from_str(json).unwrap();
+        assert_eq!(from_koreader(export)[0].to_row(1).a, "Kalevala");
+    }
+}
@@ -0,0 +1,143 @@
+// Writing of exported QAs and reviews as CSV, shared by the client and the desktop app.
+// The QAs can be imported again by naming the question, answer, deck and tags columns.
+
+use std::path::{Path, PathBuf};
+
+use crate::{ExportedQA, PastReview};
+
+// Timestamps are Unix timestamps like in Schedule and PastReview.
+pub const QA_HEADER: [&str; 11] = [
+    "id",
+    "question",
+    "answer",
+    "deck",
+    "tags",
+    "created_at",
+    "last_shown_at",
+    "correct_count",
+    "lapses",
+    "learning",
+    "suspended",
+];
+
+pub const REVIEW_HEADER: [&str; 5] = ["qa_id", "reviewed_at", "correct", "kind", "response_ms"];
+
+pub fn qas_csv(qas: &[ExportedQA]) -> String {
+    let mut out = String::new();
+    push_record(&mut out, QA_HEADER.map(str::to_string));
+    for qa in qas {
+        let s = &qa.schedule;
+        push_record(
+            &mut out,
+            [
+                qa.id.to_string(),
+                qa.q.clone(),
+                qa.a.clone(),
+                qa.deck.clone().unwrap_or_default(),
+                qa.tags.join(" "),
+                s.created_at.to_string(),
+                s.last_shown_at.map(|t| t.to_string()).unwrap_or_default(),
+                s.correct_count.to_string(),
+                s.lapses.to_string(),
+                s.learning.to_string(),
+                s.suspended.to_string(),
+            ],
+        );
+    }
+    out
+}
+
+pub fn reviews_csv(reviews: &[PastReview]) -> String {
+    let mut out = String::new();
+    push_record(&mut out, REVIEW_HEADER.map(str::to_string));
+    for r in reviews {
+        push_record(
+            &mut out,
+            [
+                r.qa_id.to_string(),
+                r.reviewed_at.to_string(),
+                r.correct.to_string(),
+                r.kind.to_string(),
+                r.response_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+            ],
+        );
+    }
+    out
+}
+
+// The reviews of an export to cards.csv are written to cards-reviews.csv.
+pub fn reviews_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}-reviews.csv"))
+}
+
+// Fields are quoted when they contain a delimiter, quote or line break, or have spaces
+// around them that would otherwise be trimmed on import.
+fn push_record<const N: usize>(out: &mut String, fields: [String; N]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let quote = field.contains([',', '"', '\n', '\r']) || field.trim() != field;
+        if quote {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::{self, ColumnMap};
+    use crate::Schedule;
+
+    #[test]
+    fn test_qas_csv() {
+        let qa = ExportedQA {
+            id: 7,
+            q: "hello, \"world\"".into(),
+            a: "two\nlines".into(),
+            deck: Some("Finnish".into()),
+            tags: vec!["a1".into(), "greetings".into()],
+            schedule: Schedule {
+                created_at: 1700000000,
+                correct_count: 2,
+                ..Default::default()
+            },
+        };
+        let csv = qas_csv(&[qa]);
+        assert!(csv.starts_with("id,question,answer,deck,tags,created_at,last_shown_at,"));
+
+        let mut records = import::parse(&csv, ',', '"');
+        let header = records.remove(0);
+        let column = |name| import::resolve_column(name, Some(&header)).unwrap();
+        let columns = ColumnMap {
+            q: column("question"),
+            a: column("answer"),
+            deck: Some(column("deck")),
+            tags: Some(column("tags")),
+        };
+        let row = import::to_row(&records[0], &columns).unwrap();
+        assert_eq!(row.q, "hello, \"world\"");
+        assert_eq!(row.a, "two\nlines");
+        assert_eq!(row.deck.as_deref(), Some("Finnish"));
+        assert_eq!(row.tags, vec!["a1", "greetings"]);
+        assert_eq!(
+            records[0].fields[5..],
+            ["1700000000", "", "2", "0", "false", "false"]
+        );
+    }
+
+    #[test]
+    fn test_reviews_path() {
+        assert_eq!(
+            reviews_path(Path::new("out/cards.csv")),
+            Path::new("out/cards-reviews.csv")
+        );
+    }
+}
@@ -0,0 +1,294 @@
+// Parsing of CSV and TSV files into QAs, shared by the client and the desktop app.
+
+use serde::{Deserialize, Serialize};
+
+use crate::NewQA;
+
+// Delimiters that are tried when detecting the one a file uses.
+const DELIMITERS: [char; 4] = [',', '\t', ';', '|'];
+
+// Number of records looked at when detecting the delimiter and quote.
+const SAMPLE: usize = 10;
+
+// A record of the file, along with the line it starts on so that rows can be reported
+// back to the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+// Which column of the file each part of a QA comes from, counting from 0.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ColumnMap {
+    pub q: usize,
+    pub a: usize,
+    pub deck: Option<usize>,
+    pub tags: Option<usize>,
+}
+
+impl Default for ColumnMap {
+    fn default() -> Self {
+        Self {
+            q: 0,
+            a: 1,
+            deck: None,
+            tags: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Row {
+    pub line: usize,
+    pub q: String,
+    pub a: String,
+    pub deck: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl Row {
+    pub fn as_new_qa(&self) -> NewQA<'_> {
+        NewQA {
+            q: &self.q,
+            a: &self.a,
+            deck: self.deck.as_deref(),
+            tags: self.tags.iter().map(String::as_str).collect(),
+            schedule: None,
+        }
+    }
+}
+
+// Picks the delimiter that splits the first records into the same number of fields,
+// preferring the one that gives the most fields. Falls back to a comma.
+pub fn detect_delimiter(text: &str, quote: char) -> char {
+    let mut best = (',', 1);
+    for delim in DELIMITERS {
+        let records = parse(text, delim, quote);
+        let sample = &records[..records.len().min(SAMPLE)];
+        let Some(first) = sample.first() else {
+            continue;
+        };
+        let n = first.fields.len();
+        if n > best.1 && sample.iter().all(|r| r.fields.len() == n) {
+            best = (delim, n);
+        }
+    }
+    best.0
+}
+
+// Returns the quote character fields are wrapped in: a single quote if fields start
+// with one more often than with a double quote, otherwise a double quote.
+pub fn detect_quote(text: &str) -> char {
+    let (mut double, mut single) = (0, 0);
+    for line in text.lines().take(SAMPLE) {
+        let starts = std::iter::once(line).chain(
+            line.match_indices(|c| DELIMITERS.contains(&c))
+                .map(|(i, d)| &line[i + d.len()..]),
+        );
+        for field in starts {
+            match field.trim_start().chars().next() {
+                Some('"') => double += 1,
+                Some('\'') => single += 1,
+                _ => {}
+            }
+        }
+    }
+    if single > double {
+        '\''
+    } else {
+        '"'
+    }
+}
+
+// Splits text into records. Fields wrapped in quotes may contain the delimiter, line
+// breaks and quotes, which are escaped by doubling them. Blank lines are skipped.
+pub fn parse(text: &str, delim: char, quote: char) -> Vec<Record> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line += 1;
+        }
+        if quoted {
+            if c == quote {
+                if chars.peek() == Some(&quote) {
+                    chars.next();
+                    field.push(quote);
+                } else {
+                    quoted = false;
+                }
+            } else {
+                field.push(c);
+            }
+            continue;
+        }
+
+        match c {
+            c if c == quote && field.is_empty() => quoted = true,
+            c if c == delim => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                push_record(&mut records, start, std::mem::take(&mut fields));
+                start = line;
+            }
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    push_record(&mut records, start, fields);
+
+    records
+}
+
+fn push_record(records: &mut Vec<Record>, line: usize, fields: Vec<String>) {
+    if fields.iter().all(|f| f.trim().is_empty()) {
+        return;
+    }
+    records.push(Record { line, fields });
+}
+
+// Resolves a column given by its header name or by its number, counting from 1 like
+// spreadsheets do.
+pub fn resolve_column(spec: &str, header: Option<&Record>) -> Result<usize, String> {
+    let spec = spec.trim();
+    if let Ok(n) = spec.parse::<usize>() {
+        return n
+            .checked_sub(1)
+            .ok_or_else(|| "Columns are numbered from 1".to_string());
+    }
+    header
+        .and_then(|h| {
+            h.fields
+                .iter()
+                .position(|f| f.trim().eq_ignore_ascii_case(spec))
+        })
+        .ok_or_else(|| format!("No column named {spec:?}"))
+}
+
+// Maps a record to a row, or explains why it can't be.
+pub fn to_row(record: &Record, columns: &ColumnMap) -> Result<Row, String> {
+    let field = |i: usize| {
+        record
+            .fields
+            .get(i)
+            .map(|f| f.trim())
+            .ok_or_else(|| format!("Missing column {}", i + 1))
+    };
+    let optional = |i: Option<usize>| {
+        i.and_then(|i| record.fields.get(i))
+            .map(|f| f.trim())
+            .filter(|f| !f.is_empty())
+    };
+
+    let q = field(columns.q)?;
+    let a = field(columns.a)?;
+    if q.is_empty() || a.is_empty() {
+        return Err("Question and answer can't be empty".to_string());
+    }
+
+    Ok(Row {
+        line: record.line,
+        q: q.to_string(),
+        a: a.to_string(),
+        deck: optional(columns.deck).map(str::to_string),
+        tags: optional(columns.tags).map(split_tags).unwrap_or_default(),
+    })
+}
+
+// Tags are separated by commas or whitespace.
+pub fn split_tags(s: &str) -> Vec<String> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(records: &[Record]) -> Vec<Vec<&str>> {
+        records
+            .iter()
+            .map(|r| r.fields.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let text =
+            "\u{feff}q,a\r\n\"hello, world\",\"moi \"\"maailma\"\"\"\n\n\"two\nlines\",x\nlast,";
+        let records = parse(text, ',', '"');
+        assert_eq!(
+            fields(&records),
+            vec![
+                vec!["q", "a"],
+                vec!["hello, world", "moi \"maailma\""],
+                vec!["two\nlines", "x"],
+                vec!["last", ""],
+            ]
+        );
+        let lines: Vec<_> = records.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![1, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect_delimiter("a\tb\nc\td\n", '"'), '\t');
+        assert_eq!(detect_delimiter("a;b,c\nd;e\n", '"'), ';');
+        assert_eq!(detect_delimiter("\"a,b\";c\n\"d\";e\n", '"'), ';');
+        assert_eq!(detect_delimiter("single\n", '"'), ',');
+
+        assert_eq!(detect_quote("'a,b','c'\n"), '\'');
+        assert_eq!(detect_quote("\"it's\",x\n"), '"');
+        assert_eq!(detect_quote("a,b\n"), '"');
+    }
+
+    #[test]
+    fn test_to_row() {
+        let header = Record {
+            line: 1,
+            fields: vec!["Front".into(), "Back".into(), "Tags".into()],
+        };
+        assert_eq!(resolve_column("back", Some(&header)), Ok(1));
+        assert_eq!(resolve_column("3", None), Ok(2));
+        assert!(resolve_column("0", None).is_err());
+        assert!(resolve_column("deck", Some(&header)).is_err());
+
+        let columns = ColumnMap {
+            tags: Some(2),
+            deck: Some(3),
+            ..Default::default()
+        };
+        let record = Record {
+            line: 2,
+            fields: vec![" kissa ".into(), "cat".into(), "animals, a1".into()],
+        };
+        assert_eq!(
+            to_row(&record, &columns),
+            Ok(Row {
+                line: 2,
+                q: "kissa".into(),
+                a: "cat".into(),
+                deck: None,
+                tags: vec!["animals".into(), "a1".into()],
+            })
+        );
+
+        let record = Record {
+            line: 3,
+            fields: vec!["koira".into()],
+        };
+        assert!(to_row(&record, &columns).is_err());
+    }
+}
@@ -1,19 +1,228 @@
 use serde::{Deserialize, Serialize};
 
+pub mod backup;
+pub mod clippings;
+pub mod export;
+pub mod import;
+pub mod subtitles;
+
 #[derive(Debug, Serialize, Deserialize)]
+#[rustfmt::skip]
 pub enum Message<'a> {
     Handshake { version: u8, token: &'a str },
     HandshakeResp,
 
-    AddQA { q: &'a str, a: &'a str },
+    // The deck is created if the customer doesn't have one by that name yet.
+    AddQA {
+        q: &'a str,
+        a: &'a str,
+        #[serde(borrow)]
+        deck: Option<&'a str>,
+        #[serde(borrow)]
+        tags: Vec<&'a str>,
+    },
     AddQAResp,
 
-    GetQuiz,
+    GetQuiz { order: QuizOrder },
     Quiz { count: u16, qas_bytes: &'a [u8] },
 
-    ReviewQA { id: i64, correct: bool },
-    ReviewQAResp,
+    // Replied to with a Quiz.
+    GetPracticeQuiz {
+        #[serde(borrow)]
+        practice: Practice<'a>,
+    },
+
+    // Builds a multiple-choice question out of the QA, using other answers of the
+    // customer as distractors. The answer is then reviewed with ReviewQA.
+    GetChoices { id: i64 },
+    Choices { choices: MultipleChoice },
+
+    // Logs the answer without changing when the QA is due.
+    PracticeReviewQA { id: i64, correct: bool, response_ms: Option<u32> },
+    PracticeReviewQAResp,
+
+    // response_ms is the time between showing the question and revealing the answer,
+    // if the client measured it.
+    ReviewQA { id: i64, correct: bool, response_ms: Option<u32> },
+    // due_in is set to the number of seconds after which the QA should be shown
+    // again when it's still being (re)learned.
+    ReviewQAResp { due_in: Option<u32> },
+
+    // Rewriting a QA also clears its leech flag and lapse count. Its deck and tags are
+    // kept when they're None.
+    UpdateQA {
+        id: i64,
+        q: &'a str,
+        a: &'a str,
+        #[serde(borrow)]
+        deck: Option<&'a str>,
+        #[serde(borrow)]
+        tags: Option<Vec<&'a str>>,
+    },
+    UpdateQAResp,
+
+    SuspendQA { id: i64 },
+    SuspendQAResp,
+
+    UnsuspendQA { id: i64 },
+    UnsuspendQAResp,
+
+    // Hides the QA from quizzes until the next day.
+    BuryQA { id: i64 },
+    BuryQAResp,
+
+    // Lists at most 20 QAs with id > after_id, ordered by id.
+    ListQAs { filter: QAFilter, after_id: i64 },
+    QAs { count: u16, qas_bytes: &'a [u8] },
+
+    GetSettings,
+    Settings { settings: Settings },
+
+    UpdateSettings { settings: Settings },
+    UpdateSettingsResp,
+
+    GetStats,
+    Stats { stats: Stats },
+
+    // Reviews are attributed to the customer's session until it ends. Starting a
+    // session ends the previous one if it's still going.
+    StartSession,
+    StartSessionResp { id: i64 },
+
+    EndSession { id: i64 },
+    EndSessionResp { summary: SessionSummary },
+
+    // Adds a batch of QAs, serialized like in Quiz, and reports what happened to each
+    // of them in order. With dry_run nothing is changed, only reported.
+    ImportQAs { duplicates: DuplicateMode, dry_run: bool, count: u16, qas_bytes: &'a [u8] },
+    // ids are those of the added QAs, in order. They're empty for a dry run.
+    ImportQAsResp { outcomes: Vec<ImportOutcome>, ids: Vec<i64> },
+
+    // Adds the past reviews of imported QAs, serialized like in Quiz.
+    ImportReviews { count: u16, reviews_bytes: &'a [u8] },
+    ImportReviewsResp,
+
+    // Exports the customer's QAs with id > after_id, ordered by id, as many as fit in
+    // the reply. next is the after_id of the following request, or None once all of
+    // them have been sent.
+    ExportQAs { after_id: i64 },
+    ExportedQAs { count: u16, qas_bytes: &'a [u8], next: Option<i64> },
+
+    // Exports the review log like ExportQAs, as PastReviews.
+    ExportReviews { after_id: i64 },
+    ExportedReviews { count: u16, reviews_bytes: &'a [u8], next: Option<i64> },
 
+    // Backs up everything the customer has, as many items at a time as fit in the reply,
+    // starting after the cursor. next is the cursor of the following request, or None
+    // once everything has been sent.
+    Backup { after: BackupCursor },
+    BackupItems { count: u16, items_bytes: &'a [u8], next: Option<BackupCursor> },
+
+    // Starts restoring a backup on this connection. Items sent afterwards refer to each
+    // other by their ids in the backup, which are mapped to the ids they're given here.
+    // Unless merging, the account must have no QAs, decks, sessions or reviews.
+    StartRestore { mode: RestoreMode },
+    StartRestoreResp,
+
+    // Restores a batch of backup items, serialized like in Quiz. Items must be sent in
+    // the order they were backed up, so that the ones they refer to come first.
+    Restore { count: u16, items_bytes: &'a [u8] },
+    RestoreResp {
+        restored: u16,
+        // Items that the account already had, or that refer to ones that weren't
+        // restored.
+        skipped: u16,
+    },
+
+    // Starts a handshake in which the token isn't sent, see prot::handshake. The prefix
+    // is that of the token.
+    StartHandshake { version: u8, prefix: &'a str, nonce: [u8; 16] },
+    Challenge { nonce: [u8; 16] },
+    // Answers the challenge, and is answered with HandshakeResp.
+    Proof { proof: [u8; 32] },
+    // Creates a customer who logs in with the username and password. Like Login, it's
+    // sent instead of the handshake and answered with a token for the device, which the
+    // connection can then be authenticated with.
+    Register { username: &'a str, password: &'a str, device: &'a str },
+    Login { username: &'a str, password: &'a str, device: &'a str },
+    IssuedToken { token: &'a str },
+
+    // Deletes the account with everything in it, after which the server closes the
+    // connection. It's confirmed with the password or, for accounts that don't have one,
+    // with a new challenge that's answered like in the handshake, with nonce as the
+    // client's nonce.
+    DeleteAccount { password: Option<&'a str>, nonce: [u8; 16] },
+    DeleteAccountResp,
+
+    // Shares one of the customer's decks for others to subscribe to, or stops sharing
+    // it, which turns the decks of its subscribers into their own. Replies with the id
+    // subscribers subscribe by.
+    ShareDeck { deck: &'a str, shared: bool },
+    ShareDeckResp { id: i64 },
+    // Lists the shared decks with id > after_id, ordered by id, as many as fit in the
+    // reply. next is like in ExportedQAs.
+    ListSharedDecks { after_id: i64 },
+    SharedDecks { count: u16, decks_bytes: &'a [u8], next: Option<i64> },
+    // Adds a deck that follows the shared one, with copies of its cards that are kept up
+    // to date with the owner's edits. The copies can't be edited, but are scheduled for
+    // the customer like their own cards. The deck is named like the shared one, unless
+    // another name is given.
+    SubscribeDeck { id: i64, name: Option<&'a str> },
+    SubscribeDeckResp { cards: u32 },
+    // Turns a subscribed deck into the customer's own, which can be edited and no longer
+    // follows the shared deck. The cards keep their schedule.
+    ForkDeck { deck: &'a str },
+    ForkDeckResp,
+
+    // Classes are run by the customer who creates them, their teacher, who needs the
+    // teacher role. Only the teacher can enroll or remove students, assign decks and see
+    // the progress of the students.
+    CreateClass { name: &'a str },
+    CreateClassResp { id: i64 },
+    // Students are enrolled by their username, which invites them to the class. They
+    // follow the decks assigned to it once they join it.
+    EnrollStudent { class_id: i64, username: &'a str },
+    EnrollStudentResp,
+    // Sent by a student who was enrolled in the class to join it.
+    JoinClass { class_id: i64 },
+    JoinClassResp,
+    RemoveStudent { class_id: i64, username: &'a str },
+    RemoveStudentResp,
+    // Sent by a student to leave the class, or to turn down joining it. The decks they
+    // followed for the class become their own.
+    LeaveClass { class_id: i64 },
+    LeaveClassResp,
+    // Has the students of the class follow one of the teacher's decks, due by due_at
+    // (seconds since the epoch) if it's given. The deck isn't listed with the shared
+    // decks unless the teacher shares it. Assigning a deck again changes its due date.
+    // Replies with the number of students who didn't follow the deck yet.
+    AssignDeck { class_id: i64, deck: &'a str, due_at: Option<i64> },
+    AssignDeckResp { subscribed: u32 },
+    // Lists the classes the customer teaches or is a student of, with id > after_id.
+    // next is like in ExportedQAs.
+    ListClasses { after_id: i64 },
+    Classes { count: u16, classes_bytes: &'a [u8], next: Option<i64> },
+    // Progress of the students of the class with id > after_id on the decks assigned to
+    // it. Their other decks aren't counted.
+    GetClassProgress { class_id: i64, after_id: i64 },
+    ClassProgress { count: u16, students_bytes: &'a [u8], next: Option<i64> },
+
+    // Only admins can send the messages below. Creates a customer with a token labeled
+    // label, and a username if one is given, which they can't log in with until they
+    // have a password.
+    CreateCustomer { username: Option<&'a str>, role: Role, label: &'a str },
+    CreatedCustomer { id: i64, token: &'a str },
+    // Disabling a customer closes their sessions and stops them from connecting.
+    SetDisabled { customer_id: i64, disabled: bool },
+    SetDisabledResp,
+    // Closes the customer's sessions, which get the new role when they reconnect.
+    SetRole { customer_id: i64, role: Role },
+    SetRoleResp,
+    GetServerStats,
+    ServerStats { stats: ServerStats },
+
+    // The request was understood but can't be fulfilled, e.g. because of invalid input.
+    BadRequest { reason: &'a str },
     InternalError,
 }
 
@@ -24,8 +233,580 @@ pub struct QA {
     pub a: String,
 }
 
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MultipleChoice {
+    pub id: i64,
+    pub q: String,
+    pub options: Vec<String>,
+    // Index of the correct option.
+    pub answer: u8,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum QAFilter {
+    All,
+    Leeches,
+    Suspended,
+}
+
+// Settings are the per-customer knobs used for scheduling quizzes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Settings {
+    // Max number of new QAs introduced per day.
+    pub new_per_day: u16,
+    // Max number of QAs reviewed per day, not counting (re)learning steps.
+    pub reviews_per_day: u16,
+    pub mix: MixOrder,
+    // Delays in seconds after which a new QA is shown again while it's being learned.
+    pub learning_steps: Vec<u32>,
+    // Delays in seconds after which a failed QA is shown again while it's being relearned.
+    pub relearning_steps: Vec<u32>,
+    // IANA name of the customer's time zone, e.g. "Europe/Helsinki".
+    pub timezone: String,
+    // Hour of the day, in the customer's time zone, at which a new day starts.
+    pub rollover_hour: u8,
+    // Number of lapses after which a QA becomes a leech.
+    pub leech_threshold: u16,
+    // Whether leeches are suspended as soon as they're flagged.
+    pub leech_suspend: bool,
+    // Correct answers that took longer than this many seconds count as hard, and
+    // aren't counted towards learning the QA. Zero turns it off.
+    pub hard_after_secs: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            new_per_day: 20,
+            reviews_per_day: 200,
+            mix: MixOrder::Mixed,
+            learning_steps: vec![60, 600],
+            relearning_steps: vec![600],
+            timezone: "UTC".to_owned(),
+            rollover_hour: 4,
+            leech_threshold: 8,
+            leech_suspend: false,
+            hard_after_secs: 0,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.timezone.is_empty() {
+            return Err("Time zone can't be empty".to_owned());
+        }
+        if self.rollover_hour > 23 {
+            return Err("Rollover hour must be between 0 and 23".to_owned());
+        }
+        if self.leech_threshold == 0 {
+            return Err("Leech threshold must be at least 1".to_owned());
+        }
+        let steps = self.learning_steps.iter().chain(&self.relearning_steps);
+        if steps.clone().any(|&step| step == 0 || step > MAX_STEP) {
+            return Err("Steps must be between 1 second and 1 day".to_owned());
+        }
+        Ok(())
+    }
+}
+
+// Steps are shown again within the same session, so they can't be longer than a day.
+const MAX_STEP: u32 = 24 * 60 * 60;
+
+// How new QAs and reviews are ordered in a quiz. QAs in the (re)learning steps always
+// come first since they're already due.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum MixOrder {
+    Mixed,
+    NewFirst,
+    ReviewsFirst,
+}
+
+// Stats summarize a customer's studying. Days start at the rollover hour in the
+// customer's time zone.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Stats {
+    // Number of reviews on each of the last STATS_DAYS days, ending with today.
+    pub reviews_per_day: Vec<u32>,
+    // Share of reviews answered correctly over the last STATS_DAYS days, not counting
+    // new QAs, (re)learning steps or practice. None when there were no reviews.
+    pub retention: Option<f32>,
+    pub cards: CardCounts,
+    // Number of consecutive days with reviews, up to today or yesterday.
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    // Estimated number of seconds spent reviewing over the last STATS_DAYS days.
+    pub time_spent: u32,
+    // Number of QAs due on each of the next STATS_DAYS days, starting with today.
+    pub forecast: Vec<u32>,
+}
+
+pub const STATS_DAYS: usize = 30;
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct SessionSummary {
+    // Number of reviews, including practice and repeated reviews of the same QA.
+    pub reviewed: u32,
+    pub correct: u32,
+    // Length of the session in seconds.
+    pub duration: u32,
+    // Number of QAs whose last review in the session was wrong.
+    pub failing: u32,
+    // Seconds until the next QA is due, zero if some are due already. None when no
+    // QA has been reviewed yet.
+    pub next_due_in: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CardCounts {
+    // Never shown.
+    pub new: u32,
+    // In the (re)learning steps.
+    pub learning: u32,
+    // Shown, but not yet answered correctly on enough days to be retired.
+    pub young: u32,
+    // Answered correctly on enough days to no longer be quizzed.
+    pub mature: u32,
+    // Suspended QAs, which aren't counted in any of the other states.
+    pub suspended: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SharedDeck {
+    pub id: i64,
+    pub name: String,
+    // Username of the owner, unless they were given a token instead.
+    pub owner: Option<String>,
+    pub cards: u32,
+    pub subscribers: u32,
+}
+
+// What a customer is allowed to do. Each role can do what the ones before it can.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    #[default]
+    User,
+    // Can create classes.
+    Teacher,
+    // Can manage customers and see the server's stats.
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Teacher => "teacher",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "teacher" => Ok(Role::Teacher),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!(
+                "Invalid role {s:?}, expected user, teacher or admin"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ServerStats {
+    pub customers: u32,
+    pub disabled: u32,
+    pub teachers: u32,
+    pub admins: u32,
+    // Customers with reviews in the last STATS_DAYS days.
+    pub active: u32,
+    // Sessions open on the server that replied.
+    pub sessions: u32,
+    pub cards: u64,
+    // Reviews in the last 24 hours.
+    pub reviews_today: u64,
+    pub shared_decks: u32,
+    pub classes: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Class {
+    pub id: i64,
+    pub name: String,
+    pub teacher: Option<String>,
+    // Whether the customer is the teacher rather than a student.
+    pub teaching: bool,
+    // Whether the customer has been enrolled in the class but hasn't joined it yet.
+    pub invited: bool,
+    // Students who joined the class.
+    pub students: u32,
+    pub assignments: Vec<Assignment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Assignment {
+    // Name of the teacher's deck, which the students' decks follow.
+    pub deck: String,
+    // Seconds since the epoch.
+    pub due_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StudentProgress {
+    // Id of the student's account.
+    pub id: i64,
+    pub username: Option<String>,
+    // Cards of the assigned decks.
+    pub cards: u32,
+    // Cards that have been shown and made it through the learning steps.
+    pub learned: u32,
+    // Like in Stats, over the cards of the assigned decks.
+    pub retention: Option<f32>,
+    // Cards that were due for a review more than a day ago.
+    pub overdue: u32,
+    // Cards of assignments past their due date that aren't learned yet.
+    pub late: u32,
+}
+
+// Which QAs are picked for a quiz when more are due than fit in it, and the order
+// they're shown in.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum QuizOrder {
+    // Most recently added QAs first.
+    #[default]
+    Newest,
+    // QAs that have waited the longest since they became due first.
+    MostOverdue,
+    // Shuffled, always the same way for the same seed.
+    Random {
+        seed: u64,
+    },
+    // Least recently added QAs first.
+    OldestFirst,
+    // New QAs and reviews alternate, regardless of the mix setting.
+    Interleaved,
+    // QAs most likely to have been forgotten first.
+    LowestRetrievability,
+}
+
+// A QA to be added in an import.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NewQA<'a> {
+    pub q: &'a str,
+    pub a: &'a str,
+    pub deck: Option<&'a str>,
+    #[serde(borrow)]
+    pub tags: Vec<&'a str>,
+    // Scheduling state carried over from another app. QAs without one are new.
+    pub schedule: Option<Schedule>,
+}
+
+// Scheduling state of an imported QA. It's only used when the QA is added, not when
+// an existing one is updated.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Schedule {
+    // Unix timestamps in seconds.
+    pub created_at: i64,
+    pub last_shown_at: Option<i64>,
+    pub correct_count: u8,
+    pub lapses: u16,
+    // QAs that are still being (re)learned start over from the first step.
+    pub learning: bool,
+    pub suspended: bool,
+}
+
+// A review of an imported QA that happened in another app.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct PastReview {
+    pub qa_id: i64,
+    // Unix timestamp in milliseconds.
+    pub reviewed_at: i64,
+    pub correct: bool,
+    // One of the kinds of the review log: 0 (new), 1 (learning), 2 (review),
+    // 3 (relearning) or 4 (practice).
+    pub kind: u8,
+    pub response_ms: Option<u32>,
+}
+
+// Max size of the QAs in one ImportQAs message, so that it fits in the server's
+// receive buffer.
+pub const IMPORT_BATCH_BYTES: usize = 480;
+
+// A QA with its scheduling state, as exported. It can be imported again as a NewQA.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExportedQA {
+    pub id: i64,
+    pub q: String,
+    pub a: String,
+    pub deck: Option<String>,
+    pub tags: Vec<String>,
+    pub schedule: Schedule,
+}
+
+impl ExportedQA {
+    pub fn as_new_qa(&self) -> NewQA<'_> {
+        NewQA {
+            q: &self.q,
+            a: &self.a,
+            deck: self.deck.as_deref(),
+            tags: self.tags.iter().map(String::as_str).collect(),
+            schedule: Some(self.schedule),
+        }
+    }
+}
+
+// Max size of the QAs or reviews in one export reply, so that it fits in the server's
+// send buffer.
+pub const EXPORT_BATCH_BYTES: usize = 1920;
+
+// Max size of the items in one Restore message. It's the size of a backup reply, so
+// that every item that can be backed up can be restored.
+pub const RESTORE_BATCH_BYTES: usize = EXPORT_BATCH_BYTES;
+
+// The parts of a backup, in the order they're sent.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BackupPart {
+    #[default]
+    Settings,
+    Decks,
+    QAs,
+    Sessions,
+    Reviews,
+}
+
+impl BackupPart {
+    pub fn next(self) -> Option<Self> {
+        match self {
+            BackupPart::Settings => Some(BackupPart::Decks),
+            BackupPart::Decks => Some(BackupPart::QAs),
+            BackupPart::QAs => Some(BackupPart::Sessions),
+            BackupPart::Sessions => Some(BackupPart::Reviews),
+            BackupPart::Reviews => None,
+        }
+    }
+}
+
+// Where a backup continues: after the item with after_id in part.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct BackupCursor {
+    pub part: BackupPart,
+    pub after_id: i64,
+}
+
+// Items of a backup hold all columns of the rows they come from. Timestamps are in
+// microseconds since the Unix epoch.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum BackupItem {
+    Settings(Settings),
+    Deck(BackupDeck),
+    QA(BackupQA),
+    Session(BackupSession),
+    Review(BackupReview),
+}
+
+impl BackupItem {
+    // Settings have no id since there's only one of them.
+    pub fn id(&self) -> i64 {
+        match self {
+            BackupItem::Settings(_) => 0,
+            BackupItem::Deck(deck) => deck.id,
+            BackupItem::QA(qa) => qa.id,
+            BackupItem::Session(session) => session.id,
+            BackupItem::Review(review) => review.id,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BackupDeck {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BackupQA {
+    pub id: i64,
+    pub q: String,
+    pub a: String,
+    pub deck_id: Option<i64>,
+    pub tags: Vec<String>,
+    pub max: i32,
+    pub correct_count: i32,
+    pub lapses: i32,
+    pub leech: bool,
+    pub suspended: bool,
+    pub buried_until: Option<i64>,
+    pub step: Option<i16>,
+    pub due_at: Option<i64>,
+    pub created_at: i64,
+    pub last_shown_at: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct BackupSession {
+    pub id: i64,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    pub reviewed: i32,
+    pub correct: i32,
+    pub failing: i32,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct BackupReview {
+    pub id: i64,
+    pub qa_id: i64,
+    pub session_id: Option<i64>,
+    pub correct: bool,
+    pub kind: i16,
+    pub reviewed_at: i64,
+    pub response_ms: Option<i32>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum RestoreMode {
+    // Restores into an account without any data, e.g. on another server.
+    #[default]
+    Empty,
+    // Adds to the account's data. Decks with the same name are merged, QAs whose
+    // question the account already has are skipped along with their reviews, and the
+    // account keeps its settings.
+    Merge,
+}
+
+// What's done with an imported QA whose question the customer already has.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DuplicateMode {
+    #[default]
+    Skip,
+    // The existing QA gets the imported answer, deck and tags.
+    Update,
+    KeepBoth,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ImportOutcome {
+    Added,
+    Updated,
+    // The question was a duplicate.
+    Skipped,
+    // The question or answer was empty.
+    Invalid,
+}
+
+// Practice quizzes drill QAs regardless of daily limits, and their reviews don't
+// change scheduling.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Practice<'a> {
+    // Only drill QAs in this deck.
+    pub deck: Option<&'a str>,
+    // Only drill QAs with this tag.
+    pub tag: Option<&'a str>,
+    // Without a deck or tag, QAs that will be due within this many days are drilled
+    // along with the ones due now.
+    pub ahead_days: u16,
+    pub order: QuizOrder,
+    // Number of QAs to skip, to get past the ones already drilled.
+    pub offset: u32,
+}
+
+// Parses steps like "1m 10m" or "30s,1h" into seconds. A bare number is treated as minutes.
+pub fn parse_steps(s: &str) -> Result<Vec<u32>, String> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|step| !step.is_empty())
+        .map(|step| {
+            let (num, unit) = match step.find(|c: char| !c.is_ascii_digit()) {
+                Some(i) => step.split_at(i),
+                None => (step, "m"),
+            };
+            let num: u32 = num.parse().map_err(|_| format!("Invalid step {step:?}"))?;
+            let mult = match unit {
+                "s" => 1,
+                "m" => 60,
+                "h" => 60 * 60,
+                "d" => 24 * 60 * 60,
+                _ => return Err(format!("Invalid unit in step {step:?}")),
+            };
+            num.checked_mul(mult)
+                .ok_or_else(|| format!("Step {step:?} is too long"))
+        })
+        .collect()
+}
+
+// Formats steps in the largest unit that divides them, the inverse of parse_steps.
+pub fn format_steps(steps: &[u32]) -> String {
+    steps
+        .iter()
+        .map(|&step| match step {
+            s if s % (24 * 60 * 60) == 0 => format!("{}d", s / (24 * 60 * 60)),
+            s if s % (60 * 60) == 0 => format!("{}h", s / (60 * 60)),
+            s if s % 60 == 0 => format!("{}m", s / 60),
+            s => format!("{s}s"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 #[derive(Clone, Serialize)]
 pub enum Connection {
     Connected,
     Reconnecting,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_steps() {
+        assert_eq!(parse_steps("1m 10m").unwrap(), vec![60, 600]);
+        assert_eq!(parse_steps("30s,1h, 2d").unwrap(), vec![30, 3600, 172800]);
+        assert_eq!(parse_steps("5").unwrap(), vec![300]);
+        assert_eq!(parse_steps("").unwrap(), Vec::<u32>::new());
+        assert!(parse_steps("1w").is_err());
+        assert!(parse_steps("m").is_err());
+    }
+
+    #[test]
+    fn test_format_steps() {
+        assert_eq!(
+            format_steps(&[30, 60, 600, 3600, 5400, 172800]),
+            "30s 1m 10m 1h 90m 2d"
+        );
+        assert_eq!(format_steps(&[]), "");
+
+        let steps = vec![45, 120, 7200];
+        assert_eq!(parse_steps(&format_steps(&steps)).unwrap(), steps);
+    }
+
+    #[test]
+    fn test_role() {
+        for role in [Role::User, Role::Teacher, Role::Admin] {
+            assert_eq!(role.as_str().parse(), Ok(role));
+        }
+        assert!("root".parse::<Role>().is_err());
+        assert!(Role::Admin > Role::Teacher && Role::Teacher > Role::User);
+    }
+
+    #[test]
+    fn test_validate_settings() {
+        assert!(Settings::default().validate().is_ok());
+
+        let settings = Settings {
+            rollover_hour: 24,
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+
+        let settings = Settings {
+            relearning_steps: vec![0],
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+}
@@ -0,0 +1,319 @@
+// Mining of sentences from subtitles (.srt and .vtt), shared by the client and the
+// desktop app. Cues are merged into sentences, which can be paired with the
+// translation shown at the same time in a second subtitle file.
+
+use serde::{Deserialize, Serialize};
+
+use crate::clippings::DRAFT_TAG;
+use crate::import::Row;
+
+// Cues further apart than this don't continue each other's sentence.
+const MAX_GAP_MS: u32 = 2000;
+
+// A subtitle shown from start to end, in milliseconds from the start of the video.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start_ms: u32,
+    pub end_ms: u32,
+    pub text: String,
+}
+
+// A sentence that can be turned into a QA.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Candidate {
+    pub start_ms: u32,
+    pub end_ms: u32,
+    pub text: String,
+    pub translation: Option<String>,
+}
+
+impl Candidate {
+    // The sentence with the given answer. Without one it's added as a draft, like
+    // highlights, whose answer holds the time of the sentence until it's written.
+    pub fn to_row(&self, line: usize, answer: Option<&str>) -> Row {
+        let (a, tags) = match answer.map(str::trim).filter(|a| !a.is_empty()) {
+            Some(a) => (a.to_string(), vec![]),
+            None => (format_time(self.start_ms), vec![DRAFT_TAG.to_string()]),
+        };
+        Row {
+            line,
+            q: self.text.clone(),
+            a,
+            deck: None,
+            tags,
+        }
+    }
+}
+
+// Parses SRT or WebVTT. Blocks without a timing line, like the WebVTT header, notes
+// and styles, are skipped, as are cues without text.
+pub fn parse(text: &str) -> Vec<Cue> {
+    let text = text
+        .strip_prefix('\u{feff}')
+        .unwrap_or(text)
+        .replace("\r\n", "\n");
+
+    let mut cues = Vec::new();
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().skip_while(|l| !l.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let Some((start, end)) = timing.split_once("-->") else {
+            continue;
+        };
+        // WebVTT cue settings follow the end time.
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (Some(start_ms), Some(end_ms)) = (parse_time(start), parse_time(end)) else {
+            continue;
+        };
+
+        let text = lines
+            .map(|l| strip_markup(l.trim()))
+            .map(|l| l.trim_start_matches('-').trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !text.is_empty() {
+            cues.push(Cue {
+                start_ms,
+                end_ms,
+                text,
+            });
+        }
+    }
+    cues
+}
+
+// Parses a timestamp like 01:02:03,456 (SRT) or 02:03.456 (WebVTT, hours optional).
+// The fraction is of a second, so 02:03.5 is 123500 ms.
+fn parse_time(s: &str) -> Option<u32> {
+    let (hms, frac) = s.trim().split_once([',', '.'])?;
+    let mut secs = 0;
+    for part in hms.split(':') {
+        secs = secs * 60 + part.parse::<u32>().ok()?;
+    }
+    if frac.is_empty() || frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let ms: u32 = format!("{frac:0<3}").parse().ok()?;
+    Some(secs * 1000 + ms)
+}
+
+// Removes HTML-like tags, such as <i> or <c.yellow>, and override tags like {\an8}.
+fn strip_markup(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut closing = None;
+    for c in line.chars() {
+        match (closing, c) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (None, c) => text.push(c),
+            (Some(end), c) if c == end => closing = None,
+            (Some(_), _) => {}
+        }
+    }
+    text.replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+}
+
+fn ends_sentence(text: &str) -> bool {
+    let text = text.trim_end_matches(['"', '\'', '»', '”', ')']);
+    text.ends_with(['.', '!', '?', '…'])
+}
+
+// Merges cues into sentences, skipping repeated ones and ones without any letters like
+// music notes. Each gets the translation cues that are mostly shown during it.
+pub fn candidates(cues: &[Cue], translation: Option<&[Cue]>) -> Vec<Candidate> {
+    let mut sentences: Vec<Candidate> = Vec::new();
+    let mut current: Option<Candidate> = None;
+    for cue in cues {
+        current = match current.take() {
+            Some(mut c) if cue.start_ms.saturating_sub(c.end_ms) <= MAX_GAP_MS => {
+                c.text.push(' ');
+                c.text.push_str(&cue.text);
+                c.end_ms = cue.end_ms;
+                Some(c)
+            }
+            prev => {
+                sentences.extend(prev);
+                Some(Candidate {
+                    start_ms: cue.start_ms,
+                    end_ms: cue.end_ms,
+                    text: cue.text.clone(),
+                    translation: None,
+                })
+            }
+        };
+        if ends_sentence(&cue.text) {
+            sentences.extend(current.take());
+        }
+    }
+    sentences.extend(current);
+
+    let mut seen = std::collections::HashSet::new();
+    sentences.retain(|c| c.text.chars().any(char::is_alphabetic) && seen.insert(c.text.clone()));
+    if let Some(translation) = translation {
+        for c in &mut sentences {
+            c.translation = translate(c, translation);
+        }
+    }
+    sentences
+}
+
+fn translate(candidate: &Candidate, translation: &[Cue]) -> Option<String> {
+    let texts: Vec<&str> = translation
+        .iter()
+        .filter(|t| {
+            let overlap =
+                t.end_ms.min(candidate.end_ms) as i64 - t.start_ms.max(candidate.start_ms) as i64;
+            overlap * 2 > t.end_ms.saturating_sub(t.start_ms) as i64
+        })
+        .map(|t| t.text.as_str())
+        .collect();
+    Some(texts.join(" ")).filter(|t| !t.is_empty())
+}
+
+// Formats a time like 1:02:03, or 02:03 under an hour.
+pub fn format_time(ms: u32) -> String {
+    let secs = ms / 1000;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    match h {
+        0 => format!("{m:02}:{s:02}"),
+        h => format!("{h}:{m:02}:{s:02}"),
+    }
+}
+
+// Parses a selection of candidates like "1,3,5-9", numbered from 1, into indices.
+pub fn parse_selection(s: &str, len: usize) -> Result<Vec<usize>, String> {
+    let mut picked = Vec::new();
+    for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let number = |n: &str| match n.trim().parse::<usize>() {
+            Ok(n) if (1..=len).contains(&n) => Ok(n - 1),
+            _ => Err(format!("No sentence {n:?}, there are {len}")),
+        };
+        match part.split_once('-') {
+            Some((from, to)) => picked.extend(number(from)?..=number(to)?),
+            None => picked.push(number(part)?),
+        }
+    }
+    picked.sort_unstable();
+    picked.dedup();
+    Ok(picked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Minä menen</i>\r\n\r\n\
+            2\n00:00:03,000 --> 00:00:04,000\n- Moi!\n- {\\an8}Hei.\n";
+        let cues = parse(srt);
+        assert_eq!(
+            cues,
+            vec![
+                Cue {
+                    start_ms: 1000,
+                    end_ms: 2500,
+                    text: "Minä menen".into()
+                },
+                Cue {
+                    start_ms: 3000,
+                    end_ms: 4000,
+                    text: "Moi! Hei.".into()
+                },
+            ]
+        );
+
+        let vtt = "WEBVTT\n\nNOTE a comment\n\nintro\n01:02.5 --> 01:03.000 line:0\n\
+            &lt;Kissa&gt; &amp; koira\n\n00:01:04.000 --> 00:01:05.000\n\n";
+        let cues = parse(vtt);
+        assert_eq!(cues.len(), 1);
+        assert_eq!(
+            (cues[0].start_ms, cues[0].end_ms, cues[0].text.as_str()),
+            (62500, 63000, "<Kissa> & koira")
+        );
+    }
+
+    #[test]
+    fn test_to_row() {
+        let c = Candidate {
+            start_ms: 63000,
+            end_ms: 64000,
+            text: "Moi!".into(),
+            translation: None,
+        };
+        let row = c.to_row(2, Some(" Hi! "));
+        assert_eq!(
+            (row.line, row.q.as_str(), row.a.as_str()),
+            (2, "Moi!", "Hi!")
+        );
+        assert!(row.tags.is_empty());
+
+        let row = c.to_row(2, Some(" "));
+        assert_eq!(
+            (row.a.as_str(), row.tags),
+            ("01:03", vec![DRAFT_TAG.to_string()])
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("01:02:03,456"), Some(3723456));
+        assert_eq!(parse_time("02:03.5"), Some(123500));
+        assert_eq!(parse_time("02:03.05"), Some(123050));
+        assert_eq!(parse_time("02:03.0005"), None);
+        assert_eq!(parse_time("02:03.+5"), None);
+        assert_eq!(parse_time("02:03"), None);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let cue = |start_ms, end_ms, text: &str| Cue {
+            start_ms,
+            end_ms,
+            text: text.into(),
+        };
+        let cues = vec![
+            cue(1000, 2000, "Minä menen"),
+            cue(2100, 3000, "kotiin."),
+            cue(4000, 5000, "Moi!"),
+            cue(9000, 10000, "Ilman pistettä"),
+            cue(20000, 21000, "Moi!"),
+            cue(22000, 23000, "♪ ♪"),
+        ];
+        let translation = vec![
+            cue(900, 2900, "I'm going home."),
+            cue(4200, 5500, "Hi!"),
+            cue(4900, 7000, "Later"),
+        ];
+
+        let candidates = candidates(&cues, Some(&translation));
+        let texts: Vec<_> = candidates
+            .iter()
+            .map(|c| (c.text.as_str(), c.translation.as_deref()))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("Minä menen kotiin.", Some("I'm going home.")),
+                ("Moi!", Some("Hi!")),
+                ("Ilman pistettä", None),
+            ]
+        );
+        assert_eq!((candidates[0].start_ms, candidates[0].end_ms), (1000, 3000));
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(parse_selection("1, 3,5-7,3", 9), Ok(vec![0, 2, 4, 5, 6]));
+        assert!(parse_selection("0", 9).is_err());
+        assert!(parse_selection("8-10", 9).is_err());
+        assert_eq!(format_time(62_500), "01:02");
+        assert_eq!(format_time(3_723_000), "1:02:03");
+    }
+}
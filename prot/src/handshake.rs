@@ -0,0 +1,258 @@
+// Challenge-response authentication, in which the token never crosses the wire.
+//
+// The client derives a key from its token, and the server only stores the hash of that
+// key. To authenticate, the client proves it holds the key by xoring it with an HMAC,
+// keyed with the stored key, over both nonces and the session data. The server takes
+// the HMAC back out of the proof and checks that the hash of what's left is the stored
+// key. A sniffed proof is no use in another session, whose nonces differ, and the
+// stored key alone isn't enough to make one.
+
+use hmac::{Hmac, Mac};
+use message::Message;
+use rand::rngs::OsRng;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tokio::net::TcpStream;
+
+use crate::{read_msg, write_msg, Error, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const PROTOCOL_VERSION: u8 = 1;
+
+pub const NONCE_LEN: usize = 16;
+pub const KEY_LEN: usize = 32;
+
+// Number of leading characters of a token that are stored in plaintext, which the
+// server finds the token by.
+pub const PREFIX_LEN: usize = 8;
+
+// Reason the server gives when the token was created before challenges and the server
+// doesn't have its stored key yet. If the client allows it, it then sends the token once,
+// which has the server store the key.
+pub const NO_STORED_KEY: &str = "The token has no key for challenges yet";
+
+pub fn prefix(token: &str) -> &str {
+    match token.char_indices().nth(PREFIX_LEN) {
+        Some((i, _)) => &token[..i],
+        None => token,
+    }
+}
+
+pub fn nonce() -> [u8; NONCE_LEN] {
+    OsRng.gen()
+}
+
+fn client_key(token: &str) -> [u8; KEY_LEN] {
+    let mut mac =
+        HmacSha256::new_from_slice(token.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(b"memryze client key");
+    mac.finalize().into_bytes().into()
+}
+
+// What the server stores to check proofs with.
+pub fn stored_key(token: &str) -> [u8; KEY_LEN] {
+    Sha256::digest(client_key(token)).into()
+}
+
+// The data a proof is bound to.
+fn signature(
+    stored_key: &[u8],
+    version: u8,
+    prefix: &str,
+    client_nonce: &[u8; NONCE_LEN],
+    server_nonce: &[u8; NONCE_LEN],
+) -> [u8; KEY_LEN] {
+    let mut mac = HmacSha256::new_from_slice(stored_key).expect("HMAC takes keys of any size");
+    mac.update(&[version]);
+    mac.update(prefix.as_bytes());
+    mac.update(client_nonce);
+    mac.update(server_nonce);
+    mac.finalize().into_bytes().into()
+}
+
+pub fn proof(
+    token: &str,
+    version: u8,
+    client_nonce: &[u8; NONCE_LEN],
+    server_nonce: &[u8; NONCE_LEN],
+) -> [u8; KEY_LEN] {
+    let key = client_key(token);
+    let sig = signature(
+        &stored_key(token),
+        version,
+        prefix(token),
+        client_nonce,
+        server_nonce,
+    );
+    xor(&key, &sig)
+}
+
+// Checks the proof against a stored key in constant time.
+pub fn verify(
+    stored_key: &[u8],
+    proof: &[u8; KEY_LEN],
+    version: u8,
+    prefix: &str,
+    client_nonce: &[u8; NONCE_LEN],
+    server_nonce: &[u8; NONCE_LEN],
+) -> bool {
+    let sig = signature(stored_key, version, prefix, client_nonce, server_nonce);
+    let key = xor(proof, &sig);
+    Sha256::digest(key).as_slice().ct_eq(stored_key).into()
+}
+
+fn xor(a: &[u8; KEY_LEN], b: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    let mut out = [0; KEY_LEN];
+    for (o, (a, b)) in out.iter_mut().zip(a.iter().zip(b)) {
+        *o = a ^ b;
+    }
+    out
+}
+
+// Authenticates the client side of a new connection with a challenge. Tokens the server
+// has no stored key for are only sent as they are with legacy, since anyone posing as
+// the server could otherwise get a token just by saying it has no key for it.
+pub async fn authenticate(
+    stream: &mut TcpStream,
+    in_buf: &mut [u8],
+    out_buf: &mut [u8],
+    token: &str,
+    legacy: bool,
+) -> Result<()> {
+    let client_nonce = nonce();
+    let start = Message::StartHandshake {
+        version: PROTOCOL_VERSION,
+        prefix: prefix(token),
+        nonce: client_nonce,
+    };
+    write_msg(stream, out_buf, &start).await?;
+
+    let server_nonce = match read_msg(stream, in_buf).await? {
+        Message::Challenge { nonce } => nonce,
+        msg => return Err(unexpected("StartHandshake", &msg)),
+    };
+    let proof = proof(token, PROTOCOL_VERSION, &client_nonce, &server_nonce);
+    write_msg(stream, out_buf, &Message::Proof { proof }).await?;
+
+    match read_msg(stream, in_buf).await? {
+        Message::HandshakeResp => return Ok(()),
+        Message::BadRequest {
+            reason: NO_STORED_KEY,
+        } if legacy => {}
+        Message::BadRequest {
+            reason: NO_STORED_KEY,
+        } => {
+            return Err(Error::Other(anyhow::anyhow!(
+                "{NO_STORED_KEY}, rotate it or send it once with the legacy handshake"
+            )))
+        }
+        Message::BadRequest { reason } => return Err(Error::Other(anyhow::anyhow!("{reason}"))),
+        msg => return Err(unexpected("Proof", &msg)),
+    }
+
+    let handshake = Message::Handshake {
+        version: PROTOCOL_VERSION,
+        token,
+    };
+    write_msg(stream, out_buf, &handshake).await?;
+    match read_msg(stream, in_buf).await? {
+        Message::HandshakeResp => Ok(()),
+        Message::BadRequest { reason } => Err(Error::Other(anyhow::anyhow!("{reason}"))),
+        msg => Err(unexpected("Handshake", &msg)),
+    }
+}
+
+// Answers the challenge the server re-authenticates the session with, after a request
+// like DeleteAccount without a password. The server then replies to the request.
+pub async fn answer_challenge(
+    stream: &mut TcpStream,
+    in_buf: &mut [u8],
+    out_buf: &mut [u8],
+    token: &str,
+    client_nonce: &[u8; NONCE_LEN],
+) -> Result<()> {
+    let server_nonce = match read_msg(stream, in_buf).await? {
+        Message::Challenge { nonce } => nonce,
+        Message::BadRequest { reason } => return Err(Error::Other(anyhow::anyhow!("{reason}"))),
+        msg => return Err(unexpected("Re-authentication", &msg)),
+    };
+    let proof = proof(token, PROTOCOL_VERSION, client_nonce, &server_nonce);
+    write_msg(stream, out_buf, &Message::Proof { proof }).await
+}
+
+// Sends Register or Login in place of the handshake and returns the token the server
+// issued for the device. The connection still has to be authenticated with it.
+pub async fn request_token(
+    stream: &mut TcpStream,
+    in_buf: &mut [u8],
+    out_buf: &mut [u8],
+    msg: &Message<'_>,
+) -> Result<String> {
+    write_msg(stream, out_buf, msg).await?;
+    match read_msg(stream, in_buf).await? {
+        Message::IssuedToken { token } => Ok(token.to_owned()),
+        Message::BadRequest { reason } => Err(Error::Other(anyhow::anyhow!("{reason}"))),
+        reply => Err(unexpected("Register or Login", &reply)),
+    }
+}
+
+fn unexpected(request: &str, reply: &Message) -> Error {
+    Error::Other(anyhow::anyhow!(
+        "{request} reply has the wrong type: {reply:?}"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proof() {
+        let token = "17bd4593ade7a97020ae0de719099f5b";
+        let stored = stored_key(token);
+        let (client_nonce, server_nonce) = (nonce(), nonce());
+        let p = proof(token, 1, &client_nonce, &server_nonce);
+
+        assert!(verify(
+            &stored,
+            &p,
+            1,
+            "17bd4593",
+            &client_nonce,
+            &server_nonce
+        ));
+        // Proofs can't be replayed in another session, or made with another token.
+        assert!(!verify(&stored, &p, 1, "17bd4593", &client_nonce, &nonce()));
+        assert!(!verify(
+            &stored,
+            &p,
+            2,
+            "17bd4593",
+            &client_nonce,
+            &server_nonce
+        ));
+        let other = proof("17bd4593ffff", 1, &client_nonce, &server_nonce);
+        assert!(!verify(
+            &stored,
+            &other,
+            1,
+            "17bd4593",
+            &client_nonce,
+            &server_nonce
+        ));
+        // The stored key isn't a proof.
+        assert!(!verify(
+            &stored,
+            &stored,
+            1,
+            "17bd4593",
+            &client_nonce,
+            &server_nonce
+        ));
+
+        assert_eq!(prefix(token), "17bd4593");
+        assert_eq!(prefix("17bd"), "17bd");
+    }
+}
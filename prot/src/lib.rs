@@ -7,6 +7,8 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tracing::trace;
 
+pub mod handshake;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -81,14 +83,32 @@ where
     T: Serialize,
 {
     let mut buf_used = 0;
-    for i in 0..data.len() {
-        let used = postcard::to_slice(&data[i], &mut dest[buf_used..])?;
+    for item in data {
+        let used = postcard::to_slice(item, &mut dest[buf_used..])?;
         buf_used += used.len();
     }
 
     Ok(&dest[0..buf_used])
 }
 
+// Serializes as many of the leading items as fit in dest, returning their bytes and
+// how many there were.
+pub fn ser_prefix<'a, T>(data: &[T], dest: &'a mut [u8]) -> Result<(&'a [u8], usize)>
+where
+    T: Serialize,
+{
+    let mut buf_used = 0;
+    for (i, item) in data.iter().enumerate() {
+        match postcard::to_slice(item, &mut dest[buf_used..]) {
+            Ok(used) => buf_used += used.len(),
+            Err(postcard::Error::SerializeBufferFull) => return Ok((&dest[0..buf_used], i)),
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok((&dest[0..buf_used], data.len()))
+}
+
 pub fn deser_from_bytes<'a, T>(mut src: &'a [u8], count: u16, dest: &mut Vec<T>) -> Result<()>
 where
     T: Deserialize<'a> + Debug,
@@ -108,7 +128,7 @@ fn hex(data: &[u8]) -> String {
         enc.push_str(&format!("0x{:02X} ", byte));
     }
     // Remove the space at the end.
-    if enc.len() > 0 {
+    if !enc.is_empty() {
         enc.pop();
     }
     enc
@@ -160,4 +180,17 @@ mod tests {
 
         assert_eq!(qas, quiz);
     }
+
+    #[test]
+    fn test_ser_prefix() {
+        let qas = vec![QA::default(); 3];
+        let one = postcard::to_slice(&qas[0], &mut [0u8; 16]).unwrap().len();
+
+        let mut buf = vec![0u8; one * 2 + 1];
+        let (bytes, count) = ser_prefix(&qas, &mut buf).unwrap();
+        assert_eq!((bytes.len(), count), (one * 2, 2));
+
+        let (bytes, count) = ser_prefix(&qas[..1], &mut buf).unwrap();
+        assert_eq!((bytes.len(), count), (one, 1));
+    }
 }
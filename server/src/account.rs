@@ -0,0 +1,100 @@
+// Usernames and passwords, with which customers register and get tokens for their
+// devices themselves.
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+pub const MIN_PASSWORD_LEN: usize = 10;
+pub const MAX_PASSWORD_LEN: usize = 128;
+pub const MAX_USERNAME_LEN: usize = 32;
+pub const MAX_DEVICE_LEN: usize = 64;
+
+// Hash that passwords given with an unknown username are checked against, so that
+// logging in takes as long whether the username exists or not. It has the parameters
+// of hash_password.
+pub const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$b95izA1iKLYFSTv/JXH3Tw$u1dM2uSnV23F4Kr6vNf8CANcTCRzYBeep/zblnr6sL8";
+
+// Usernames are compared as they are, so only lowercase letters, digits and a few
+// separators are allowed.
+pub fn validate_username(username: &str) -> Result<(), &'static str> {
+    if username.len() < 3 || username.len() > MAX_USERNAME_LEN {
+        return Err("The username has to be 3 to 32 characters long");
+    }
+    let allowed = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c);
+    if !username.chars().all(allowed) {
+        return Err("The username can only have lowercase letters, digits, '.', '_' and '-'");
+    }
+    Ok(())
+}
+
+pub fn validate_password(password: &str) -> Result<(), &'static str> {
+    let len = password.chars().count();
+    if len < MIN_PASSWORD_LEN {
+        return Err("The password has to be at least 10 characters long");
+    }
+    if len > MAX_PASSWORD_LEN {
+        return Err("The password can be at most 128 characters long");
+    }
+    Ok(())
+}
+
+// The device is what the token is labeled with.
+pub fn validate_device(device: &str) -> Result<(), &'static str> {
+    if device.trim().is_empty() || device.chars().count() > MAX_DEVICE_LEN {
+        return Err("The device name has to be 1 to 64 characters long");
+    }
+    Ok(())
+}
+
+// Hashes the password into a PHC string, which holds the salt and the parameters along
+// with the hash. This is slow on purpose, so it's done off the runtime's threads.
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| anyhow::anyhow!("Hashing the password: {err}"))?;
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password() {
+        let hash = hash_password("correct horse").unwrap();
+
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("correct hors", &hash));
+        assert!(!verify_password("correct horse", "not a hash"));
+        assert_ne!(hash, hash_password("correct horse").unwrap());
+
+        let params = |hash| PasswordHash::new(hash).unwrap().params.to_string();
+        assert_eq!(params(DUMMY_HASH), params(&hash));
+        assert!(!verify_password("correct horse", DUMMY_HASH));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate_username("amin.mir_1").is_ok());
+        assert!(validate_username("am").is_err());
+        assert!(validate_username("Amin").is_err());
+        assert!(validate_username("amin mir").is_err());
+        assert!(validate_password("lyhyt").is_err());
+        assert!(validate_password("pitkä salasana").is_ok());
+        assert!(validate_device("laptop").is_ok());
+        assert!(validate_device(" ").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use message::{BackupCursor, BackupPart, BackupQA, BackupReview, RestoreMode};
+
+// Returns the cursor after a backup reply of part, given the id the next request of the
+// same part starts after, if it has more items.
+pub fn next_cursor(part: BackupPart, next_id: Option<i64>) -> Option<BackupCursor> {
+    match next_id {
+        Some(after_id) => Some(BackupCursor { part, after_id }),
+        None => part.next().map(|part| BackupCursor { part, after_id: 0 }),
+    }
+}
+
+// A restore in progress on a connection. Maps the ids of the backup to the ids the
+// restored rows were given.
+#[derive(Debug, Default, Clone)]
+pub struct Restore {
+    pub mode: RestoreMode,
+    decks: HashMap<i64, i64>,
+    qas: HashMap<i64, i64>,
+    sessions: HashMap<i64, i64>,
+}
+
+impl Restore {
+    pub fn new(mode: RestoreMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_deck(&mut self, backup_id: i64, id: i64) {
+        self.decks.insert(backup_id, id);
+    }
+
+    pub fn add_qa(&mut self, backup_id: i64, id: i64) {
+        self.qas.insert(backup_id, id);
+    }
+
+    pub fn add_session(&mut self, backup_id: i64, id: i64) {
+        self.sessions.insert(backup_id, id);
+    }
+
+    // Returns the id the deck with the backup id was restored with.
+    pub fn deck(&self, backup_id: i64) -> Option<i64> {
+        self.decks.get(&backup_id).copied()
+    }
+
+    // A QA whose deck wasn't restored has none.
+    pub fn map_qa(&self, qa: &BackupQA) -> BackupQA {
+        BackupQA {
+            deck_id: qa.deck_id.and_then(|id| self.deck(id)),
+            ..qa.clone()
+        }
+    }
+
+    // Reviews of QAs that weren't restored are dropped. A review whose session wasn't
+    // restored has none.
+    pub fn map_review(&self, review: &BackupReview) -> Option<BackupReview> {
+        Some(BackupReview {
+            qa_id: *self.qas.get(&review.qa_id)?,
+            session_id: review
+                .session_id
+                .and_then(|id| self.sessions.get(&id).copied()),
+            ..*review
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_cursor() {
+        assert_eq!(
+            next_cursor(BackupPart::QAs, Some(7)),
+            Some(BackupCursor {
+                part: BackupPart::QAs,
+                after_id: 7
+            })
+        );
+        assert_eq!(
+            next_cursor(BackupPart::QAs, None),
+            Some(BackupCursor {
+                part: BackupPart::Sessions,
+                after_id: 0
+            })
+        );
+        assert_eq!(next_cursor(BackupPart::Reviews, None), None);
+    }
+
+    #[test]
+    fn test_restore_mapping() {
+        let mut restore = Restore::new(RestoreMode::Merge);
+        restore.add_deck(3, 30);
+        restore.add_qa(5, 50);
+        restore.add_session(2, 20);
+
+        let qa = BackupQA {
+            id: 5,
+            deck_id: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(restore.map_qa(&qa).deck_id, Some(30));
+        let qa = BackupQA {
+            deck_id: Some(4),
+            ..qa
+        };
+        assert_eq!(restore.map_qa(&qa).deck_id, None);
+
+        let review = BackupReview {
+            id: 9,
+            qa_id: 5,
+            session_id: Some(2),
+            ..Default::default()
+        };
+        let mapped = restore.map_review(&review).unwrap();
+        assert_eq!((mapped.qa_id, mapped.session_id), (50, Some(20)));
+        let review = BackupReview {
+            session_id: Some(8),
+            ..review
+        };
+        assert_eq!(restore.map_review(&review).unwrap().session_id, None);
+        let review = BackupReview { qa_id: 6, ..review };
+        assert_eq!(restore.map_review(&review), None);
+    }
+}
@@ -1,13 +1,30 @@
+use std::collections::HashMap;
+use std::env;
 use std::error::Error;
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use clap::{Parser, Subcommand};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use tokio::net::TcpStream;
 use tracing::{error, info};
 use tracing_subscriber::EnvFilter;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
 
-use message::{Message, QA};
-use prot;
+use message::backup::{Backup, BACKUP_ENTRY, BACKUP_VERSION};
+use message::clippings;
+use message::export;
+use message::import::{self, ColumnMap, Row};
+use message::subtitles;
+use message::{
+    BackupCursor, Class, DuplicateMode, ExportedQA, ImportOutcome, Message, MixOrder, NewQA,
+    PastReview, Practice, QAFilter, QuizOrder, RestoreMode, Role, Settings, SharedDeck,
+    StudentProgress, IMPORT_BATCH_BYTES, QA, RESTORE_BATCH_BYTES,
+};
+use prot::handshake;
 
 #[derive(Debug, Parser)]
 struct Args {
@@ -25,6 +42,13 @@ struct Args {
     )]
     server_addr: String,
 
+    #[arg(
+        long,
+        help = "Send a token created before challenge handshakes once, so the server can \
+            store its key"
+    )]
+    legacy_handshake: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -36,16 +60,538 @@ enum Commands {
         q: String,
         #[arg(help = "Answer")]
         a: String,
+        #[arg(long, help = "Deck to put the qa in")]
+        deck: Option<String>,
+        #[arg(long = "tag", help = "Tag of the qa, can be given multiple times")]
+        tags: Vec<String>,
+    },
+    GetQuiz {
+        #[arg(long, value_enum, default_value_t = Order::Newest)]
+        order: Order,
+        #[arg(long, help = "Seed for the random order", default_value_t = 0)]
+        seed: u64,
+    },
+    // Goes through the quiz one question at a time, timing how long each one takes.
+    Review {
+        #[arg(long, value_enum, default_value_t = Order::Newest)]
+        order: Order,
+        #[arg(long, help = "Seed for the random order", default_value_t = 0)]
+        seed: u64,
+    },
+    PracticeQuiz {
+        #[arg(long, help = "Only drill qas in this deck")]
+        deck: Option<String>,
+        #[arg(long, help = "Only drill qas with this tag")]
+        tag: Option<String>,
+        #[arg(
+            long,
+            help = "Also drill qas due within this many days",
+            default_value_t = 0
+        )]
+        ahead_days: u16,
+        #[arg(long, value_enum, default_value_t = Order::Newest)]
+        order: Order,
+        #[arg(long, help = "Seed for the random order", default_value_t = 0)]
+        seed: u64,
+        #[arg(long, help = "Number of qas to skip", default_value_t = 0)]
+        offset: u32,
+    },
+    Choices {
+        #[arg(help = "ID of qa")]
+        id: i64,
+    },
+    PracticeCorrect {
+        #[arg(help = "ID of qa")]
+        id: i64,
+        #[arg(long, help = "Milliseconds the question was shown before the answer")]
+        response_ms: Option<u32>,
+    },
+    PracticeWrong {
+        #[arg(help = "ID of qa")]
+        id: i64,
+        #[arg(long, help = "Milliseconds the question was shown before the answer")]
+        response_ms: Option<u32>,
     },
-    GetQuiz,
     CorrectReview {
         #[arg(help = "ID of qa")]
         id: i64,
+        #[arg(long, help = "Milliseconds the question was shown before the answer")]
+        response_ms: Option<u32>,
     },
     WrongReview {
         #[arg(help = "ID of qa")]
         id: i64,
+        #[arg(long, help = "Milliseconds the question was shown before the answer")]
+        response_ms: Option<u32>,
+    },
+    UpdateQA {
+        #[arg(help = "ID of qa")]
+        id: i64,
+        #[arg(help = "Question")]
+        q: String,
+        #[arg(help = "Answer")]
+        a: String,
+        #[arg(long, help = "Deck to put the qa in")]
+        deck: Option<String>,
+        #[arg(long = "tag", help = "Tag of the qa, can be given multiple times")]
+        tags: Vec<String>,
+        #[arg(long, conflicts_with = "tags", help = "Remove all tags of the qa")]
+        no_tags: bool,
+    },
+    Suspend {
+        #[arg(help = "ID of qa")]
+        id: i64,
+    },
+    Unsuspend {
+        #[arg(help = "ID of qa")]
+        id: i64,
+    },
+    Bury {
+        #[arg(help = "ID of qa")]
+        id: i64,
+    },
+    ListQAs {
+        #[arg(long, value_enum, default_value_t = Filter::All)]
+        filter: Filter,
+        #[arg(long, help = "Only list qas with a greater ID", default_value_t = 0)]
+        after_id: i64,
+    },
+    GetSettings,
+    UpdateSettings(SettingsUpdate),
+    Stats,
+    StartSession,
+    EndSession {
+        #[arg(help = "ID of session")]
+        id: i64,
+    },
+    Import(ImportArgs),
+    ImportAnki(AnkiArgs),
+    ImportSubtitles(SubtitleArgs),
+    ImportHighlights(HighlightArgs),
+    Export(ExportArgs),
+    Backup {
+        #[arg(help = "File to back up to, a zip archive if it ends in .zip, JSON otherwise")]
+        path: PathBuf,
+    },
+    Restore {
+        #[arg(help = "Backup to restore, as JSON or a zip archive")]
+        path: PathBuf,
+        #[arg(
+            long,
+            help = "Add to the account's data instead of requiring it to be empty"
+        )]
+        merge: bool,
+    },
+    // Creates an account and prints the token of this device, which --token takes.
+    Register(AccountArgs),
+    // Prints a new token for this device.
+    Login(AccountArgs),
+    // Deletes the account and everything in it.
+    DeleteAccount {
+        #[arg(
+            long,
+            help = "Confirm with the password of the account, read like for login, instead \
+                of the token"
+        )]
+        password: bool,
+        #[arg(
+            long,
+            help = "Back up the account to this file first, like backup does"
+        )]
+        export: Option<PathBuf>,
+        #[arg(long, help = "Confirm the deletion, which can't be undone")]
+        yes: bool,
+    },
+    // Shares the deck for others to subscribe to and prints its ID.
+    ShareDeck {
+        #[arg(help = "Name of the deck")]
+        deck: String,
+        #[arg(
+            long,
+            help = "Stop sharing it, which forks the decks of its subscribers"
+        )]
+        stop: bool,
     },
+    SharedDecks {
+        #[arg(long, help = "Only list decks with a greater ID", default_value_t = 0)]
+        after_id: i64,
+    },
+    Subscribe {
+        #[arg(help = "ID of the shared deck")]
+        id: i64,
+        #[arg(long, help = "Name of the deck, the shared deck's by default")]
+        name: Option<String>,
+    },
+    // Makes a subscribed deck an editable deck of one's own.
+    ForkDeck {
+        #[arg(help = "Name of the deck")]
+        deck: String,
+    },
+    // Creates a class to teach and prints its ID.
+    CreateClass {
+        #[arg(help = "Name of the class")]
+        name: String,
+    },
+    Enroll {
+        #[arg(help = "ID of the class")]
+        class_id: i64,
+        #[arg(help = "Username of the student")]
+        username: String,
+    },
+    RemoveStudent {
+        #[arg(help = "ID of the class")]
+        class_id: i64,
+        #[arg(help = "Username of the student")]
+        username: String,
+    },
+    // Joins a class one has been enrolled in.
+    JoinClass {
+        #[arg(help = "ID of the class")]
+        class_id: i64,
+    },
+    LeaveClass {
+        #[arg(help = "ID of the class")]
+        class_id: i64,
+    },
+    AssignDeck {
+        #[arg(help = "ID of the class")]
+        class_id: i64,
+        #[arg(help = "Name of the deck")]
+        deck: String,
+        #[arg(long, help = "Number of days the students have to learn it in")]
+        due_days: Option<u32>,
+    },
+    Classes {
+        #[arg(
+            long,
+            help = "Only list classes with a greater ID",
+            default_value_t = 0
+        )]
+        after_id: i64,
+    },
+    ClassProgress {
+        #[arg(help = "ID of the class")]
+        class_id: i64,
+        #[arg(
+            long,
+            help = "Only list students with a greater ID",
+            default_value_t = 0
+        )]
+        after_id: i64,
+    },
+    // The commands below are only allowed for admins. Creates a customer and prints
+    // their ID and token.
+    CreateCustomer {
+        #[arg(long, help = "Username, which others can enroll the customer by")]
+        username: Option<String>,
+        #[arg(long, help = "user, teacher or admin", default_value = "user")]
+        role: Role,
+        #[arg(long, help = "Label of the token", default_value = "default")]
+        label: String,
+    },
+    DisableCustomer {
+        #[arg(help = "ID of the customer")]
+        id: i64,
+    },
+    EnableCustomer {
+        #[arg(help = "ID of the customer")]
+        id: i64,
+    },
+    SetRole {
+        #[arg(help = "ID of the customer")]
+        id: i64,
+        #[arg(help = "user, teacher or admin")]
+        role: Role,
+    },
+    ServerStats,
+}
+
+// Environment variable the password of register and login is read from. Without it the
+// password is read from stdin, so that it doesn't show up in the process list or the
+// shell history.
+const PASSWORD_ENV: &str = "MEMRYZE_PASSWORD";
+
+#[derive(Debug, ClapArgs)]
+struct AccountArgs {
+    #[arg(long)]
+    username: String,
+    #[arg(long, help = "Name the token is labeled with", default_value = "cli")]
+    device: String,
+}
+
+#[derive(Debug, ClapArgs)]
+struct SubtitleArgs {
+    #[arg(help = "Subtitles (.srt or .vtt) to mine sentences from")]
+    path: PathBuf,
+    #[arg(
+        long,
+        help = "Subtitles in another language to pair sentences with as answers"
+    )]
+    translation: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Sentences to add, e.g. \"1,3,5-9\". Without it the sentences are listed"
+    )]
+    pick: Option<String>,
+    #[arg(long, help = "Deck to add the sentences to")]
+    deck: Option<String>,
+    #[arg(long, help = "Tags of the sentences, separated by commas or spaces")]
+    tags: Option<String>,
+    #[arg(long, help = "Only report what would be added")]
+    dry_run: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+struct HighlightArgs {
+    #[arg(help = "Kindle's My Clippings.txt or highlights exported by KOReader as JSON")]
+    path: PathBuf,
+    #[arg(long, help = "Deck to add the highlights to")]
+    deck: Option<String>,
+    #[arg(long, help = "Only report what would be added")]
+    dry_run: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+struct ExportArgs {
+    #[arg(help = "File to export to, an Anki package if it ends in .apkg, CSV otherwise")]
+    path: PathBuf,
+    #[arg(
+        long,
+        help = "Also export the review history, to <name>-reviews.csv for CSV exports"
+    )]
+    history: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+struct AnkiArgs {
+    #[arg(help = "Anki package (.apkg) to import")]
+    path: PathBuf,
+    #[arg(long, help = "Question field, by number or name", default_value = "1")]
+    q_field: String,
+    #[arg(long, help = "Answer field, by number or name", default_value = "2")]
+    a_field: String,
+    #[arg(long, help = "Deck to put all notes in instead of their Anki deck")]
+    deck: Option<String>,
+    #[arg(long, value_enum, default_value_t = Duplicates::Skip)]
+    duplicates: Duplicates,
+    #[arg(long, help = "Only report what would be imported")]
+    dry_run: bool,
+    #[arg(long, help = "Don't import the review history")]
+    no_history: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+struct ImportArgs {
+    #[arg(help = "CSV or TSV file to import")]
+    path: PathBuf,
+    #[arg(
+        long,
+        help = "Field delimiter, e.g. \",\" or \"tab\", detected if not given"
+    )]
+    delimiter: Option<String>,
+    #[arg(long, help = "Quote character, detected if not given")]
+    quote: Option<char>,
+    #[arg(long, help = "Whether the first row holds column names")]
+    header: bool,
+    #[arg(long, help = "Question column, by number or name", default_value = "1")]
+    q_col: String,
+    #[arg(long, help = "Answer column, by number or name", default_value = "2")]
+    a_col: String,
+    #[arg(long, help = "Deck column, by number or name")]
+    deck_col: Option<String>,
+    #[arg(long, help = "Tags column, by number or name")]
+    tags_col: Option<String>,
+    #[arg(long, help = "Deck of rows that don't have one")]
+    deck: Option<String>,
+    #[arg(long, value_enum, default_value_t = Duplicates::Skip)]
+    duplicates: Duplicates,
+    #[arg(long, help = "Only report what would be imported")]
+    dry_run: bool,
+}
+
+impl ImportArgs {
+    // Reads the file into rows, logging the records that can't be imported.
+    fn rows(&self) -> Result<Vec<Row>, Box<dyn Error>> {
+        let text = fs::read_to_string(&self.path)?;
+
+        let quote = self.quote.unwrap_or_else(|| import::detect_quote(&text));
+        let delim = match self.delimiter.as_deref() {
+            Some("tab" | "\\t") => '\t',
+            Some(d) if d.chars().count() == 1 => d.chars().next().unwrap(),
+            Some(d) => return Err(format!("Invalid delimiter {d:?}").into()),
+            None => import::detect_delimiter(&text, quote),
+        };
+        info!(?delim, ?quote, "Parsing file");
+
+        let mut records = import::parse(&text, delim, quote);
+        let header = if self.header && !records.is_empty() {
+            Some(records.remove(0))
+        } else {
+            None
+        };
+        let header = header.as_ref();
+        let column = |spec: &Option<String>| {
+            spec.as_deref()
+                .map(|spec| import::resolve_column(spec, header))
+                .transpose()
+        };
+        let columns = ColumnMap {
+            q: import::resolve_column(&self.q_col, header)?,
+            a: import::resolve_column(&self.a_col, header)?,
+            deck: column(&self.deck_col)?,
+            tags: column(&self.tags_col)?,
+        };
+
+        let mut rows = Vec::with_capacity(records.len());
+        for record in &records {
+            match import::to_row(record, &columns) {
+                Ok(mut row) => {
+                    row.deck = row.deck.or_else(|| self.deck.clone());
+                    rows.push(row);
+                }
+                Err(reason) => error!(line = record.line, reason, "Invalid row"),
+            }
+        }
+        Ok(rows)
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Duplicates {
+    Skip,
+    Update,
+    KeepBoth,
+}
+
+impl From<Duplicates> for DuplicateMode {
+    fn from(duplicates: Duplicates) -> Self {
+        match duplicates {
+            Duplicates::Skip => DuplicateMode::Skip,
+            Duplicates::Update => DuplicateMode::Update,
+            Duplicates::KeepBoth => DuplicateMode::KeepBoth,
+        }
+    }
+}
+
+// SettingsUpdate overrides the given fields of the current settings.
+#[derive(Debug, ClapArgs)]
+struct SettingsUpdate {
+    #[arg(long, help = "Max number of new qas per day")]
+    new_per_day: Option<u16>,
+    #[arg(long, help = "Max number of reviews per day")]
+    reviews_per_day: Option<u16>,
+    #[arg(long, value_enum, help = "How new qas and reviews are ordered")]
+    mix: Option<Mix>,
+    #[arg(long, help = "Learning steps, e.g. \"1m 10m\"")]
+    learning_steps: Option<String>,
+    #[arg(long, help = "Relearning steps, e.g. \"10m\"")]
+    relearning_steps: Option<String>,
+    #[arg(long, help = "Time zone, e.g. Europe/Helsinki")]
+    timezone: Option<String>,
+    #[arg(long, help = "Hour of the day at which a new day starts")]
+    rollover_hour: Option<u8>,
+    #[arg(long, help = "Number of lapses after which a qa becomes a leech")]
+    leech_threshold: Option<u16>,
+    #[arg(long, help = "Whether leeches are suspended automatically")]
+    leech_suspend: Option<bool>,
+    #[arg(
+        long,
+        help = "Seconds after which a correct answer counts as hard, 0 to disable"
+    )]
+    hard_after_secs: Option<u16>,
+}
+
+impl SettingsUpdate {
+    fn apply(&self, settings: &mut Settings) -> Result<(), String> {
+        if let Some(new_per_day) = self.new_per_day {
+            settings.new_per_day = new_per_day;
+        }
+        if let Some(reviews_per_day) = self.reviews_per_day {
+            settings.reviews_per_day = reviews_per_day;
+        }
+        if let Some(mix) = self.mix {
+            settings.mix = mix.into();
+        }
+        if let Some(ref steps) = self.learning_steps {
+            settings.learning_steps = message::parse_steps(steps)?;
+        }
+        if let Some(ref steps) = self.relearning_steps {
+            settings.relearning_steps = message::parse_steps(steps)?;
+        }
+        if let Some(ref timezone) = self.timezone {
+            settings.timezone = timezone.clone();
+        }
+        if let Some(rollover_hour) = self.rollover_hour {
+            settings.rollover_hour = rollover_hour;
+        }
+        if let Some(leech_threshold) = self.leech_threshold {
+            settings.leech_threshold = leech_threshold;
+        }
+        if let Some(leech_suspend) = self.leech_suspend {
+            settings.leech_suspend = leech_suspend;
+        }
+        if let Some(hard_after_secs) = self.hard_after_secs {
+            settings.hard_after_secs = hard_after_secs;
+        }
+        settings.validate()
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Mix {
+    Mixed,
+    NewFirst,
+    ReviewsFirst,
+}
+
+impl From<Mix> for MixOrder {
+    fn from(mix: Mix) -> Self {
+        match mix {
+            Mix::Mixed => MixOrder::Mixed,
+            Mix::NewFirst => MixOrder::NewFirst,
+            Mix::ReviewsFirst => MixOrder::ReviewsFirst,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Order {
+    Newest,
+    MostOverdue,
+    Random,
+    OldestFirst,
+    Interleaved,
+    LowestRetrievability,
+}
+
+impl Order {
+    fn with_seed(self, seed: u64) -> QuizOrder {
+        match self {
+            Order::Newest => QuizOrder::Newest,
+            Order::MostOverdue => QuizOrder::MostOverdue,
+            Order::Random => QuizOrder::Random { seed },
+            Order::OldestFirst => QuizOrder::OldestFirst,
+            Order::Interleaved => QuizOrder::Interleaved,
+            Order::LowestRetrievability => QuizOrder::LowestRetrievability,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Filter {
+    All,
+    Leeches,
+    Suspended,
+}
+
+impl From<Filter> for QAFilter {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::All => QAFilter::All,
+            Filter::Leeches => QAFilter::Leeches,
+            Filter::Suspended => QAFilter::Suspended,
+        }
+    }
 }
 
 #[tokio::main]
@@ -61,32 +607,253 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let mut stream = TcpStream::connect(args.server_addr).await?;
 
-    let mut in_buf = vec![0u8; 512];
+    let mut in_buf = vec![0u8; 2048];
     let mut prim_out_buf = vec![0u8; 512];
 
-    let handshake = Message::Handshake {
-        version: 1,
-        token: &args.token,
-    };
-
-    prot::write_msg(&mut stream, &mut prim_out_buf, &handshake).await?;
-
-    let handshake_reply = prot::read_msg(&mut stream, &mut in_buf).await?;
+    if let Commands::Register(ref account) | Commands::Login(ref account) = args.command {
+        let password = read_password()?;
+        let msg = match args.command {
+            Commands::Register(_) => Message::Register {
+                username: &account.username,
+                password: &password,
+                device: &account.device,
+            },
+            _ => Message::Login {
+                username: &account.username,
+                password: &password,
+                device: &account.device,
+            },
+        };
+        let token =
+            handshake::request_token(&mut stream, &mut in_buf, &mut prim_out_buf, &msg).await;
+        match token {
+            Ok(token) => println!("{token}"),
+            Err(err) => {
+                error!(%err, "Getting a token failed");
+                process::exit(1);
+            }
+        }
+        return Ok(());
+    }
 
-    let Message::HandshakeResp = handshake_reply else {
-        error!(?handshake_reply, "Handshake reply has the wrong type");
+    let handshake = handshake::authenticate(
+        &mut stream,
+        &mut in_buf,
+        &mut prim_out_buf,
+        &args.token,
+        args.legacy_handshake,
+    )
+    .await;
+    if let Err(err) = handshake {
+        error!(%err, "Handshake failed");
         process::exit(1);
-    };
+    }
 
     info!("Received handshake from server");
 
+    match args.command {
+        Commands::Import(ref import) => return import_rows(&mut stream, import).await,
+        Commands::ImportAnki(ref import) => return import_anki(&mut stream, import).await,
+        Commands::ImportSubtitles(ref import) => {
+            return import_subtitles(&mut stream, import).await
+        }
+        Commands::ImportHighlights(ref import) => {
+            return import_highlights(&mut stream, import).await
+        }
+        Commands::Review { order, seed } => {
+            return review(&mut stream, order.with_seed(seed)).await
+        }
+        Commands::Export(ref export) => return export_all(&mut stream, export).await,
+        Commands::Backup { ref path } => return backup(&mut stream, path).await,
+        Commands::Restore { ref path, merge } => return restore(&mut stream, path, merge).await,
+        Commands::DeleteAccount {
+            password,
+            ref export,
+            yes,
+        } => {
+            let password = if password {
+                Some(read_password()?)
+            } else {
+                None
+            };
+            let export = export.as_deref();
+            return delete_account(&mut stream, &args.token, password.as_deref(), export, yes)
+                .await;
+        }
+        _ => {}
+    }
+
     let mut qas: Vec<QA> = Vec::with_capacity(10);
 
+    // Only the given settings are updated, so the current ones are needed first.
+    let settings = match args.command {
+        Commands::UpdateSettings(ref update) => {
+            prot::write_msg(&mut stream, &mut prim_out_buf, &Message::GetSettings).await?;
+            let resp = prot::read_msg(&mut stream, &mut in_buf).await?;
+            let Message::Settings { mut settings } = resp else {
+                error!(?resp, "GetSettings reply has the wrong type");
+                process::exit(1);
+            };
+            update.apply(&mut settings)?;
+            Some(settings)
+        }
+        _ => None,
+    };
+
     let msg = match args.command {
-        Commands::InsertQA { ref q, ref a } => Message::AddQA { q, a },
-        Commands::GetQuiz => Message::GetQuiz,
-        Commands::CorrectReview { id } => Message::ReviewQA { id, correct: true },
-        Commands::WrongReview { id } => Message::ReviewQA { id, correct: false },
+        Commands::InsertQA {
+            ref q,
+            ref a,
+            ref deck,
+            ref tags,
+        } => Message::AddQA {
+            q,
+            a,
+            deck: deck.as_deref(),
+            tags: tags.iter().map(String::as_str).collect(),
+        },
+        Commands::GetQuiz { order, seed } => Message::GetQuiz {
+            order: order.with_seed(seed),
+        },
+        Commands::CorrectReview { id, response_ms } => Message::ReviewQA {
+            id,
+            correct: true,
+            response_ms,
+        },
+        Commands::WrongReview { id, response_ms } => Message::ReviewQA {
+            id,
+            correct: false,
+            response_ms,
+        },
+        Commands::UpdateQA {
+            id,
+            ref q,
+            ref a,
+            ref deck,
+            ref tags,
+            no_tags,
+        } => Message::UpdateQA {
+            id,
+            q,
+            a,
+            deck: deck.as_deref(),
+            // Without any tags given, the ones of the qa are kept.
+            tags: (no_tags || !tags.is_empty()).then(|| tags.iter().map(String::as_str).collect()),
+        },
+        Commands::PracticeQuiz {
+            ref deck,
+            ref tag,
+            ahead_days,
+            order,
+            seed,
+            offset,
+        } => Message::GetPracticeQuiz {
+            practice: Practice {
+                deck: deck.as_deref(),
+                tag: tag.as_deref(),
+                ahead_days,
+                order: order.with_seed(seed),
+                offset,
+            },
+        },
+        Commands::Choices { id } => Message::GetChoices { id },
+        Commands::PracticeCorrect { id, response_ms } => Message::PracticeReviewQA {
+            id,
+            correct: true,
+            response_ms,
+        },
+        Commands::PracticeWrong { id, response_ms } => Message::PracticeReviewQA {
+            id,
+            correct: false,
+            response_ms,
+        },
+        Commands::Suspend { id } => Message::SuspendQA { id },
+        Commands::Unsuspend { id } => Message::UnsuspendQA { id },
+        Commands::Bury { id } => Message::BuryQA { id },
+        Commands::ListQAs { filter, after_id } => Message::ListQAs {
+            filter: filter.into(),
+            after_id,
+        },
+        Commands::GetSettings => Message::GetSettings,
+        Commands::Stats => Message::GetStats,
+        Commands::StartSession => Message::StartSession,
+        Commands::EndSession { id } => Message::EndSession { id },
+        Commands::ShareDeck { ref deck, stop } => Message::ShareDeck {
+            deck,
+            shared: !stop,
+        },
+        Commands::SharedDecks { after_id } => Message::ListSharedDecks { after_id },
+        Commands::Subscribe { id, ref name } => Message::SubscribeDeck {
+            id,
+            name: name.as_deref(),
+        },
+        Commands::ForkDeck { ref deck } => Message::ForkDeck { deck },
+        Commands::CreateClass { ref name } => Message::CreateClass { name },
+        Commands::Enroll {
+            class_id,
+            ref username,
+        } => Message::EnrollStudent { class_id, username },
+        Commands::RemoveStudent {
+            class_id,
+            ref username,
+        } => Message::RemoveStudent { class_id, username },
+        Commands::JoinClass { class_id } => Message::JoinClass { class_id },
+        Commands::LeaveClass { class_id } => Message::LeaveClass { class_id },
+        Commands::AssignDeck {
+            class_id,
+            ref deck,
+            due_days,
+        } => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            Message::AssignDeck {
+                class_id,
+                deck,
+                due_at: due_days.map(|days| now + days as i64 * 24 * 60 * 60),
+            }
+        }
+        Commands::Classes { after_id } => Message::ListClasses { after_id },
+        Commands::CreateCustomer {
+            ref username,
+            role,
+            ref label,
+        } => Message::CreateCustomer {
+            username: username.as_deref(),
+            role,
+            label,
+        },
+        Commands::DisableCustomer { id } => Message::SetDisabled {
+            customer_id: id,
+            disabled: true,
+        },
+        Commands::EnableCustomer { id } => Message::SetDisabled {
+            customer_id: id,
+            disabled: false,
+        },
+        Commands::SetRole { id, role } => Message::SetRole {
+            customer_id: id,
+            role,
+        },
+        Commands::ServerStats => Message::GetServerStats,
+        Commands::ClassProgress { class_id, after_id } => {
+            Message::GetClassProgress { class_id, after_id }
+        }
+        Commands::UpdateSettings(_) => Message::UpdateSettings {
+            settings: settings.expect("settings are fetched before updating"),
+        },
+        Commands::Import(_)
+        | Commands::ImportAnki(_)
+        | Commands::ImportSubtitles(_)
+        | Commands::ImportHighlights(_)
+        | Commands::Export(_)
+        | Commands::Backup { .. }
+        | Commands::Restore { .. } => {
+            unreachable!("imports, exports and backups are sent in batches")
+        }
+        Commands::DeleteAccount { .. } => unreachable!("the account is deleted separately"),
+        Commands::Review { .. } => unreachable!("reviews are sent one QA at a time"),
+        Commands::Register(_) | Commands::Login(_) => {
+            unreachable!("tokens are requested before the handshake")
+        }
     };
 
     prot::write_msg(&mut stream, &mut prim_out_buf, &msg).await?;
@@ -100,8 +867,103 @@ async fn main() -> Result<(), Box<dyn Error>> {
             prot::deser_from_bytes(qas_bytes, count, &mut qas)?;
             info!(?qas, "Quiz");
         }
-        Message::ReviewQAResp => {
-            info!(?resp, "ReviewQA successful");
+        Message::ReviewQAResp { due_in } => {
+            info!(?due_in, "ReviewQA successful");
+        }
+        Message::Choices { choices } => {
+            info!(?choices, "Choices");
+        }
+        Message::Stats { stats } => {
+            info!(?stats, "Stats");
+        }
+        Message::StartSessionResp { id } => {
+            info!(id, "Session started");
+        }
+        Message::EndSessionResp { summary } => {
+            info!(?summary, "Session summary");
+        }
+        Message::Settings { settings } => {
+            info!(?settings, "Settings");
+        }
+        Message::UpdateQAResp
+        | Message::UpdateSettingsResp
+        | Message::SuspendQAResp
+        | Message::UnsuspendQAResp
+        | Message::BuryQAResp
+        | Message::PracticeReviewQAResp => {
+            info!(?resp, "Request successful");
+        }
+        Message::QAs { count, qas_bytes } => {
+            prot::deser_from_bytes(qas_bytes, count, &mut qas)?;
+            info!(?qas, "QAs");
+        }
+        Message::ShareDeckResp { id } => {
+            info!(id, "Deck sharing changed");
+        }
+        Message::SharedDecks {
+            count,
+            decks_bytes,
+            next,
+        } => {
+            let mut decks: Vec<SharedDeck> = Vec::with_capacity(count as usize);
+            prot::deser_from_bytes(decks_bytes, count, &mut decks)?;
+            for deck in &decks {
+                info!(?deck, "Shared deck");
+            }
+            info!(?next, "Next page is after");
+        }
+        Message::SubscribeDeckResp { cards } => {
+            info!(cards, "Subscribed");
+        }
+        Message::ForkDeckResp => {
+            info!("Deck forked");
+        }
+        Message::CreateClassResp { id } => {
+            info!(id, "Class created");
+        }
+        Message::AssignDeckResp { subscribed } => {
+            info!(subscribed, "Deck assigned");
+        }
+        Message::CreatedCustomer { id, token } => {
+            info!(id, token, "Customer created");
+        }
+        Message::ServerStats { stats } => {
+            info!(?stats, "Server stats");
+        }
+        Message::EnrollStudentResp
+        | Message::JoinClassResp
+        | Message::RemoveStudentResp
+        | Message::LeaveClassResp
+        | Message::SetDisabledResp
+        | Message::SetRoleResp => {
+            info!(?resp, "Request successful");
+        }
+        Message::Classes {
+            count,
+            classes_bytes,
+            next,
+        } => {
+            let mut classes: Vec<Class> = Vec::with_capacity(count as usize);
+            prot::deser_from_bytes(classes_bytes, count, &mut classes)?;
+            for class in &classes {
+                info!(?class, "Class");
+            }
+            info!(?next, "Next page is after");
+        }
+        Message::ClassProgress {
+            count,
+            students_bytes,
+            next,
+        } => {
+            let mut students: Vec<StudentProgress> = Vec::with_capacity(count as usize);
+            prot::deser_from_bytes(students_bytes, count, &mut students)?;
+            for student in &students {
+                info!(?student, "Student");
+            }
+            info!(?next, "Next page is after");
+        }
+        Message::BadRequest { reason } => {
+            error!(reason, "Bad request");
         }
         Message::InternalError => {
             error!("Internal server error");
@@ -111,3 +973,559 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+// Imports the rows of the file and logs what happened to each of them.
+async fn import_rows(stream: &mut TcpStream, args: &ImportArgs) -> Result<(), Box<dyn Error>> {
+    let rows = args.rows()?;
+    let new_qas: Vec<NewQA> = rows.iter().map(Row::as_new_qa).collect();
+
+    let (outcomes, _) = send_qas(stream, &new_qas, args.duplicates.into(), args.dry_run).await?;
+
+    let mut counts = HashMap::new();
+    for (row, outcome) in rows.iter().zip(outcomes) {
+        info!(line = row.line, ?outcome, q = row.q, "Row");
+        *counts.entry(format!("{outcome:?}")).or_insert(0) += 1;
+    }
+    info!(?counts, dry_run = args.dry_run, "Import finished");
+    Ok(())
+}
+
+// Imports the notes of the package along with their review history, and logs what
+// happened to each of them.
+async fn import_anki(stream: &mut TcpStream, args: &AnkiArgs) -> Result<(), Box<dyn Error>> {
+    let fields = anki::FieldMap {
+        q: args.q_field.clone(),
+        a: args.a_field.clone(),
+    };
+    let package = anki::read(&fs::read(&args.path)?, &fields)?;
+    for skipped in &package.skipped {
+        error!(note = skipped.id, reason = skipped.reason, "Skipped note");
+    }
+
+    let new_qas: Vec<NewQA> = package
+        .notes
+        .iter()
+        .map(|note| NewQA {
+            q: &note.q,
+            a: &note.a,
+            deck: args.deck.as_deref().or(note.deck.as_deref()),
+            tags: note.tags.iter().map(String::as_str).collect(),
+            schedule: Some(note.schedule),
+        })
+        .collect();
+    let (outcomes, ids) = send_qas(stream, &new_qas, args.duplicates.into(), args.dry_run).await?;
+
+    let mut counts = HashMap::new();
+    let mut ids = ids.into_iter();
+    let mut reviews = vec![];
+    for (note, outcome) in package.notes.iter().zip(outcomes) {
+        info!(note = note.id, ?outcome, q = note.q, "Note");
+        *counts.entry(format!("{outcome:?}")).or_insert(0) += 1;
+
+        if outcome != Some(ImportOutcome::Added) || args.dry_run {
+            continue;
+        }
+        let Some(qa_id) = ids.next() else {
+            break;
+        };
+        if !args.no_history {
+            reviews.extend(note.reviews.iter().map(|r| PastReview {
+                qa_id,
+                reviewed_at: r.reviewed_at,
+                correct: r.correct,
+                kind: r.kind,
+                response_ms: r.response_ms,
+            }));
+        }
+    }
+    send_reviews(stream, &reviews).await?;
+
+    info!(
+        ?counts,
+        reviews = reviews.len(),
+        dry_run = args.dry_run,
+        "Import finished"
+    );
+    Ok(())
+}
+
+// Lists the sentences of the subtitles, or adds the picked ones with their translation
+// as the answer.
+async fn import_subtitles(
+    stream: &mut TcpStream,
+    args: &SubtitleArgs,
+) -> Result<(), Box<dyn Error>> {
+    let cues = subtitles::parse(&fs::read_to_string(&args.path)?);
+    let translation = match &args.translation {
+        Some(path) => Some(subtitles::parse(&fs::read_to_string(path)?)),
+        None => None,
+    };
+    let candidates = subtitles::candidates(&cues, translation.as_deref());
+
+    let Some(pick) = &args.pick else {
+        for (i, c) in candidates.iter().enumerate() {
+            let time = subtitles::format_time(c.start_ms);
+            let translation = c.translation.as_deref().unwrap_or("");
+            println!(
+                "{:>4}  {time:>8}  {}\n                {translation}",
+                i + 1,
+                c.text
+            );
+        }
+        return Ok(());
+    };
+
+    let picked = subtitles::parse_selection(pick, candidates.len())?;
+    let tags = args
+        .tags
+        .as_deref()
+        .map(import::split_tags)
+        .unwrap_or_default();
+    let rows: Vec<Row> = picked
+        .iter()
+        .map(|&i| {
+            let c = &candidates[i];
+            let row = c.to_row(i + 1, c.translation.as_deref());
+            Row {
+                deck: args.deck.clone(),
+                tags: tags.iter().cloned().chain(row.tags).collect(),
+                ..row
+            }
+        })
+        .collect();
+    let new_qas: Vec<NewQA> = rows.iter().map(Row::as_new_qa).collect();
+
+    let (outcomes, _) = send_qas(stream, &new_qas, DuplicateMode::Skip, args.dry_run).await?;
+    let mut counts = HashMap::new();
+    for (qa, outcome) in new_qas.iter().zip(outcomes) {
+        info!(?outcome, q = qa.q, "Sentence");
+        *counts.entry(format!("{outcome:?}")).or_insert(0) += 1;
+    }
+    info!(?counts, dry_run = args.dry_run, "Import finished");
+    Ok(())
+}
+
+// Adds the highlights of e-reader books as draft QAs. Highlights whose question was
+// already added, by an earlier import of the same file, are skipped.
+async fn import_highlights(
+    stream: &mut TcpStream,
+    args: &HighlightArgs,
+) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(&args.path)?;
+    let highlights = if text.trim_start().starts_with('{') {
+        clippings::from_koreader(serde_json::from_str(&text)?)
+    } else {
+        clippings::parse_kindle(&text)
+    };
+
+    let rows: Vec<Row> = highlights
+        .iter()
+        .enumerate()
+        .map(|(i, highlight)| Row {
+            deck: args.deck.clone(),
+            ..highlight.to_row(i + 1)
+        })
+        .collect();
+    let new_qas: Vec<NewQA> = rows.iter().map(Row::as_new_qa).collect();
+
+    let (outcomes, _) = send_qas(stream, &new_qas, DuplicateMode::Skip, args.dry_run).await?;
+
+    let mut counts = HashMap::new();
+    for (row, outcome) in rows.iter().zip(outcomes) {
+        info!(?outcome, q = row.q, tags = ?row.tags, "Highlight");
+        *counts.entry(format!("{outcome:?}")).or_insert(0) += 1;
+    }
+    info!(?counts, dry_run = args.dry_run, "Import finished");
+    Ok(())
+}
+
+async fn export_all(stream: &mut TcpStream, args: &ExportArgs) -> Result<(), Box<dyn Error>> {
+    let qas = fetch_qas(stream).await?;
+    let reviews = match args.history {
+        true => fetch_reviews(stream).await?,
+        false => vec![],
+    };
+
+    let apkg = args.path.extension().is_some_and(|ext| ext == "apkg");
+    let n_qas = qas.len();
+    if apkg {
+        fs::write(&args.path, anki::write(&anki::export_notes(qas, &reviews))?)?;
+    } else {
+        fs::write(&args.path, export::qas_csv(&qas))?;
+        if args.history {
+            fs::write(
+                export::reviews_path(&args.path),
+                export::reviews_csv(&reviews),
+            )?;
+        }
+    }
+
+    info!(
+        qas = n_qas,
+        reviews = reviews.len(),
+        path = %args.path.display(),
+        "Export finished"
+    );
+    Ok(())
+}
+
+// Fetches all QAs, as many at a time as fit in a reply.
+async fn fetch_qas(stream: &mut TcpStream) -> Result<Vec<ExportedQA>, Box<dyn Error>> {
+    let mut in_buf = vec![0u8; 2048];
+    let mut prim_out_buf = vec![0u8; 512];
+    let mut qas = vec![];
+
+    let mut after_id = 0;
+    loop {
+        prot::write_msg(stream, &mut prim_out_buf, &Message::ExportQAs { after_id }).await?;
+
+        let resp = prot::read_msg(stream, &mut in_buf).await?;
+        let Message::ExportedQAs {
+            count,
+            qas_bytes,
+            next,
+        } = resp
+        else {
+            error!(?resp, "ExportQAs reply has the wrong type");
+            process::exit(1);
+        };
+        prot::deser_from_bytes(qas_bytes, count, &mut qas)?;
+        match next {
+            Some(next) => after_id = next,
+            None => return Ok(qas),
+        }
+    }
+}
+
+// Fetches the review log like fetch_qas.
+async fn fetch_reviews(stream: &mut TcpStream) -> Result<Vec<PastReview>, Box<dyn Error>> {
+    let mut in_buf = vec![0u8; 2048];
+    let mut prim_out_buf = vec![0u8; 512];
+    let mut reviews = vec![];
+
+    let mut after_id = 0;
+    loop {
+        let msg = Message::ExportReviews { after_id };
+        prot::write_msg(stream, &mut prim_out_buf, &msg).await?;
+
+        let resp = prot::read_msg(stream, &mut in_buf).await?;
+        let Message::ExportedReviews {
+            count,
+            reviews_bytes,
+            next,
+        } = resp
+        else {
+            error!(?resp, "ExportReviews reply has the wrong type");
+            process::exit(1);
+        };
+        prot::deser_from_bytes(reviews_bytes, count, &mut reviews)?;
+        match next {
+            Some(next) => after_id = next,
+            None => return Ok(reviews),
+        }
+    }
+}
+
+// Shows the question of each QA in the quiz and its answer once enter is pressed, then
+// sends the grade along with how long the question was shown before that.
+async fn review(stream: &mut TcpStream, order: QuizOrder) -> Result<(), Box<dyn Error>> {
+    let mut in_buf = vec![0u8; 2048];
+    let mut prim_out_buf = vec![0u8; 512];
+    let mut qas: Vec<QA> = Vec::with_capacity(10);
+
+    prot::write_msg(stream, &mut prim_out_buf, &Message::GetQuiz { order }).await?;
+    let resp = prot::read_msg(stream, &mut in_buf).await?;
+    let Message::Quiz { count, qas_bytes } = resp else {
+        error!(?resp, "GetQuiz reply has the wrong type");
+        process::exit(1);
+    };
+    prot::deser_from_bytes(qas_bytes, count, &mut qas)?;
+    if qas.is_empty() {
+        println!("There are no questions to review");
+    }
+
+    for qa in &qas {
+        println!("\n{}", qa.q);
+        let shown_at = Instant::now();
+        prompt("Press enter to reveal the answer")?;
+        let response_ms = shown_at.elapsed().as_millis().min(u32::MAX as u128) as u32;
+
+        println!("{}", qa.a);
+        let correct = loop {
+            match prompt("Correct? [y/n]")?.trim() {
+                "y" => break true,
+                "n" => break false,
+                _ => continue,
+            }
+        };
+
+        let msg = Message::ReviewQA {
+            id: qa.id,
+            correct,
+            response_ms: Some(response_ms),
+        };
+        prot::write_msg(stream, &mut prim_out_buf, &msg).await?;
+        match prot::read_msg(stream, &mut in_buf).await? {
+            Message::ReviewQAResp { due_in: Some(secs) } => println!("Shown again in {secs}s"),
+            Message::ReviewQAResp { due_in: None } => (),
+            resp => {
+                error!(?resp, "ReviewQA reply has the wrong type");
+                process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
+
+// The prompt goes to stderr, since the token is printed to stdout.
+fn read_password() -> io::Result<String> {
+    if let Ok(password) = env::var(PASSWORD_ENV) {
+        return Ok(password);
+    }
+    eprint!("Password: ");
+    io::stderr().flush()?;
+    let mut line = String::new();
+    if io::stdin().read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+// Reads a line from stdin after showing text.
+fn prompt(text: &str) -> io::Result<String> {
+    print!("{text} ");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line)
+}
+
+// Accounts without a password are confirmed by answering a challenge with the token.
+async fn delete_account(
+    stream: &mut TcpStream,
+    token: &str,
+    password: Option<&str>,
+    export: Option<&Path>,
+    yes: bool,
+) -> Result<(), Box<dyn Error>> {
+    if !yes {
+        error!("Deleting the account can't be undone, pass --yes to go ahead");
+        process::exit(1);
+    }
+    if let Some(path) = export {
+        backup(stream, path).await?;
+    }
+
+    let mut in_buf = vec![0u8; 2048];
+    let mut prim_out_buf = vec![0u8; 512];
+    let nonce = handshake::nonce();
+    let msg = Message::DeleteAccount { password, nonce };
+    prot::write_msg(stream, &mut prim_out_buf, &msg).await?;
+    if password.is_none() {
+        handshake::answer_challenge(stream, &mut in_buf, &mut prim_out_buf, token, &nonce).await?;
+    }
+
+    match prot::read_msg(stream, &mut in_buf).await? {
+        Message::DeleteAccountResp => info!("Deleted the account"),
+        Message::BadRequest { reason } => {
+            error!(reason, "Deleting the account failed");
+            process::exit(1);
+        }
+        resp => {
+            error!(?resp, "DeleteAccount reply has the wrong type");
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+async fn backup(stream: &mut TcpStream, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut in_buf = vec![0u8; 2048];
+    let mut prim_out_buf = vec![0u8; 512];
+    let mut items = vec![];
+
+    let mut after = Some(BackupCursor::default());
+    while let Some(cursor) = after {
+        let msg = Message::Backup { after: cursor };
+        prot::write_msg(stream, &mut prim_out_buf, &msg).await?;
+
+        let resp = prot::read_msg(stream, &mut in_buf).await?;
+        let Message::BackupItems {
+            count,
+            items_bytes,
+            next,
+        } = resp
+        else {
+            error!(?resp, "Backup reply has the wrong type");
+            process::exit(1);
+        };
+        prot::deser_from_bytes(items_bytes, count, &mut items)?;
+        after = next;
+    }
+
+    let mut backup = Backup::default();
+    let n_items = items.len();
+    for item in items {
+        backup.push(item);
+    }
+    let json = serde_json::to_vec_pretty(&backup)?;
+    if path.extension().is_some_and(|ext| ext == "zip") {
+        let mut zip = ZipWriter::new(fs::File::create(path)?);
+        zip.start_file(BACKUP_ENTRY, SimpleFileOptions::default())?;
+        zip.write_all(&json)?;
+        zip.finish()?;
+    } else {
+        fs::write(path, json)?;
+    }
+
+    info!(
+        items = n_items,
+        qas = backup.qas.len(),
+        reviews = backup.reviews.len(),
+        path = %path.display(),
+        "Backup finished"
+    );
+    Ok(())
+}
+
+async fn restore(stream: &mut TcpStream, path: &Path, merge: bool) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let backup: Backup = if bytes.starts_with(b"PK") {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let entry = archive.by_name(BACKUP_ENTRY)?;
+        serde_json::from_reader(entry)?
+    } else {
+        serde_json::from_slice(&bytes)?
+    };
+    if backup.version > BACKUP_VERSION {
+        return Err(format!("Backup version {} isn't supported", backup.version).into());
+    }
+    let items = backup.into_items();
+
+    let mut in_buf = vec![0u8; 2048];
+    let mut prim_out_buf = vec![0u8; 2048];
+    let mut items_buf = vec![0u8; RESTORE_BATCH_BYTES];
+
+    let mode = match merge {
+        true => RestoreMode::Merge,
+        false => RestoreMode::Empty,
+    };
+    prot::write_msg(stream, &mut prim_out_buf, &Message::StartRestore { mode }).await?;
+    match prot::read_msg(stream, &mut in_buf).await? {
+        Message::StartRestoreResp => {}
+        Message::BadRequest { reason } => return Err(reason.into()),
+        resp => {
+            error!(?resp, "StartRestore reply has the wrong type");
+            process::exit(1);
+        }
+    }
+
+    // Backups only have items that fit in a reply, which is as large as a Restore
+    // message, so a longer one means the backup was changed after it was made.
+    let (mut restored, mut skipped) = (0, 0);
+    let mut done = 0;
+    while done < items.len() {
+        let (items_bytes, count) = prot::ser_prefix(&items[done..], &mut items_buf)?;
+        if count == 0 {
+            error!(item = ?items[done], "Item is too long to be restored");
+            return Err(format!("Item {done} of the backup is too long to be restored").into());
+        }
+
+        let msg = Message::Restore {
+            count: count as u16,
+            items_bytes,
+        };
+        prot::write_msg(stream, &mut prim_out_buf, &msg).await?;
+
+        let resp = prot::read_msg(stream, &mut in_buf).await?;
+        let Message::RestoreResp {
+            restored: batch_restored,
+            skipped: batch_skipped,
+        } = resp
+        else {
+            error!(?resp, "Restore reply has the wrong type");
+            process::exit(1);
+        };
+        restored += batch_restored as usize;
+        skipped += batch_skipped as usize;
+        done += count;
+    }
+
+    info!(restored, skipped, "Restore finished");
+    Ok(())
+}
+
+// Sends the QAs in batches that fit in the server's buffer. Returns the outcome of each
+// of them, which is None if it's too long to be sent, and the ids of the added ones.
+async fn send_qas(
+    stream: &mut TcpStream,
+    new_qas: &[NewQA<'_>],
+    duplicates: DuplicateMode,
+    dry_run: bool,
+) -> Result<(Vec<Option<ImportOutcome>>, Vec<i64>), Box<dyn Error>> {
+    let mut in_buf = vec![0u8; 2048];
+    let mut prim_out_buf = vec![0u8; 512];
+    let mut qas_buf = vec![0u8; IMPORT_BATCH_BYTES];
+    let mut outcomes = Vec::with_capacity(new_qas.len());
+    let mut ids = vec![];
+
+    while outcomes.len() < new_qas.len() {
+        let (qas_bytes, count) = prot::ser_prefix(&new_qas[outcomes.len()..], &mut qas_buf)?;
+        if count == 0 {
+            outcomes.push(None);
+            continue;
+        }
+
+        let msg = Message::ImportQAs {
+            duplicates,
+            dry_run,
+            count: count as u16,
+            qas_bytes,
+        };
+        prot::write_msg(stream, &mut prim_out_buf, &msg).await?;
+
+        let resp = prot::read_msg(stream, &mut in_buf).await?;
+        let Message::ImportQAsResp {
+            outcomes: batch,
+            ids: batch_ids,
+        } = resp
+        else {
+            error!(?resp, "ImportQAs reply has the wrong type");
+            process::exit(1);
+        };
+        outcomes.extend(batch.into_iter().map(Some));
+        ids.extend(batch_ids);
+    }
+
+    Ok((outcomes, ids))
+}
+
+async fn send_reviews(
+    stream: &mut TcpStream,
+    reviews: &[PastReview],
+) -> Result<(), Box<dyn Error>> {
+    let mut in_buf = vec![0u8; 2048];
+    let mut prim_out_buf = vec![0u8; 512];
+    let mut reviews_buf = vec![0u8; IMPORT_BATCH_BYTES];
+
+    let mut done = 0;
+    while done < reviews.len() {
+        let (reviews_bytes, count) = prot::ser_prefix(&reviews[done..], &mut reviews_buf)?;
+        let msg = Message::ImportReviews {
+            count: count as u16,
+            reviews_bytes,
+        };
+        prot::write_msg(stream, &mut prim_out_buf, &msg).await?;
+
+        let resp = prot::read_msg(stream, &mut in_buf).await?;
+        let Message::ImportReviewsResp = resp else {
+            error!(?resp, "ImportReviews reply has the wrong type");
+            process::exit(1);
+        };
+        done += count;
+    }
+
+    Ok(())
+}
@@ -1,120 +1,2720 @@
 use std::pin::pin;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use deadpool_postgres::{GenericClient, Manager, Pool, Transaction};
 use futures::stream::StreamExt;
-use tokio_postgres::{Client, Statement};
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, Config, NoTls, RowStream, Statement};
 
-use message::QA;
+use message::{Assignment, Class, Role, ServerStats, StudentProgress};
+use message::{
+    BackupCursor, BackupDeck, BackupItem, BackupPart, BackupQA, BackupReview, BackupSession,
+    RestoreMode,
+};
+use message::{
+    CardCounts, DuplicateMode, ExportedQA, ImportOutcome, MixOrder, MultipleChoice, NewQA,
+    PastReview, Practice, QAFilter, QuizOrder, Schedule, SessionSummary, Settings, SharedDeck,
+    Stats, QA, STATS_DAYS,
+};
+
+use crate::backup::Restore;
+use crate::export::EXPORT_PAGE;
+use crate::import::{self, Action};
+use crate::quiz::{self, Candidate, Group};
+use crate::sched::{Answer, Kind, Next, State, Steps};
+use crate::stats;
+use crate::token::{self, TokenHasher};
 
 pub struct PgClient {
     client: Client,
-    custid_from_tkn_stmt: Statement,
+    // Transactions run on connections of their own, since client is shared by every
+    // session. Their statements are prepared on each connection as they're first used.
+    pool: Pool,
+    find_token_stmt: Statement,
+    touch_token_stmt: Statement,
+    token_revoked_stmt: Statement,
+    register_stmt: Statement,
+    find_login_stmt: Statement,
+    insert_token_stmt: Statement,
+    reauth_stmt: Statement,
+    delete_customer_stmt: Statement,
     insert_qa_stmt: Statement,
+    import_reviews_stmt: Statement,
+    export_qas_stmt: Statement,
+    export_reviews_stmt: Statement,
+    backup_decks_stmt: Statement,
+    backup_qas_stmt: Statement,
+    backup_sessions_stmt: Statement,
+    backup_reviews_stmt: Statement,
+    account_is_empty_stmt: Statement,
+    today_stmt: Statement,
     get_quiz_stmt: Statement,
-    correct_review_stmt: Statement,
-    wrong_review_stmt: Statement,
+    get_practice_quiz_stmt: Statement,
+    log_practice_stmt: Statement,
+    get_qa_stmt: Statement,
+    distractors_stmt: Statement,
+    stats_reviews_stmt: Statement,
+    stats_summary_stmt: Statement,
+    stats_time_stmt: Statement,
+    stats_cards_stmt: Statement,
+    stats_days_stmt: Statement,
+    stats_forecast_stmt: Statement,
+    start_session_stmt: Statement,
+    end_session_stmt: Statement,
+    next_due_stmt: Statement,
+    update_qa_stmt: Statement,
+    set_suspended_stmt: Statement,
+    bury_qa_stmt: Statement,
+    list_qas_stmt: Statement,
+    get_settings_stmt: Statement,
+    timezone_exists_stmt: Statement,
+    read_only_stmt: Statement,
+    share_deck_stmt: Statement,
+    list_shared_decks_stmt: Statement,
+    shared_deck_stmt: Statement,
+    subscribe_stmt: Statement,
+    deck_cards_stmt: Statement,
+    fork_deck_stmt: Statement,
+    sync_shared_stmt: Statement,
+    create_class_stmt: Statement,
+    teaches_stmt: Statement,
+    enroll_stmt: Statement,
+    join_class_stmt: Statement,
+    remove_student_stmt: Statement,
+    leave_class_stmt: Statement,
+    assign_deck_stmt: Statement,
+    subscribe_class_stmt: Statement,
+    list_classes_stmt: Statement,
+    class_progress_stmt: Statement,
+    role_stmt: Statement,
+    set_disabled_stmt: Statement,
+    set_role_stmt: Statement,
+    server_stats_stmt: Statement,
+}
+
+// Selects the session that reviews of customer $2 are attributed to.
+const OPEN_SESSION: &str = "(SELECT id FROM study_session \
+    WHERE customer_id = $2 AND ended_at IS NULL \
+    ORDER BY id DESC LIMIT 1)";
+
+// Number of wrong options in a multiple-choice question.
+const DISTRACTORS: i64 = 3;
+
+// Creates the deck named $4 for customer $3 if it doesn't exist yet, so that its id
+// can be selected from d. d is empty when $4 is NULL.
+const UPSERT_DECK: &str = "d AS ( \
+    INSERT INTO deck (customer_id, name) \
+    SELECT $3, $4::text WHERE $4 IS NOT NULL \
+    ON CONFLICT (customer_id, name) DO UPDATE SET name = EXCLUDED.name \
+    RETURNING id)";
+
+// Converts timestamps to microseconds since the epoch and back, which backups keep them
+// in. Both are exact, unlike to_timestamp.
+fn micros(col: &str) -> String {
+    format!("(extract(epoch FROM {col}) * 1000000)::bigint")
+}
+
+fn from_micros(param: &str) -> String {
+    format!("('epoch'::timestamptz + {param}::bigint * interval '1 microsecond')")
+}
+
+// Whether the customer has joined a class the deck is assigned to, other than the class
+// with the id if one is given. Such a customer can follow the deck without it being
+// shared.
+fn in_class(customer_id: &str, deck_id: &str, other_than: Option<&str>) -> String {
+    let other_than = other_than.map_or(String::new(), |id| format!(" AND acs.class_id <> {id}"));
+    format!(
+        "EXISTS (SELECT 1 FROM class_deck acd \
+        JOIN class_student acs ON acs.class_id = acd.class_id \
+        WHERE acd.deck_id = {deck_id} AND acs.customer_id = {customer_id} \
+        AND acs.joined_at IS NOT NULL{other_than})"
+    )
+}
+
+// Removes student {student} from class $1. Their decks that follow the decks of the
+// class become their own, unless they can still follow them, and the id of the student
+// is returned.
+fn leave_class_query(student: &str) -> String {
+    format!(
+        "WITH s AS ( \
+            DELETE FROM class_student WHERE class_id = $1 AND customer_id = {student} \
+            RETURNING customer_id), \
+        forked AS ( \
+            UPDATE deck f SET source_deck_id = NULL \
+            FROM s, class_deck cd JOIN deck sd ON sd.id = cd.deck_id \
+            WHERE cd.class_id = $1 AND f.customer_id = s.customer_id \
+            AND f.source_deck_id = sd.id AND NOT sd.shared \
+            AND NOT {} \
+            RETURNING f.id), \
+        cards AS ( \
+            UPDATE qa SET source_qa_id = NULL FROM forked \
+            WHERE qa.deck_id = forked.id) \
+        SELECT customer_id FROM s",
+        in_class("s.customer_id", "sd.id", Some("$1")),
+    )
+}
+
+// The QA is locked until its review is logged, so that concurrent reviews of it are
+// applied one after the other.
+const QA_STATE_QUERY: &str = "SELECT step, correct_count, last_shown_at IS NULL AS new \
+    FROM qa \
+    WHERE id = $1 AND customer_id = $2 \
+    FOR UPDATE";
+
+// Every wrong answer counts as a lapse. Once the lapses reach the threshold the QA is
+// flagged as a leech and, depending on the settings, suspended.
+const REVIEW_QUERY: &str = "UPDATE qa \
+    SET last_shown_at = CURRENT_TIMESTAMP, \
+        step = $3, \
+        due_at = CURRENT_TIMESTAMP + make_interval(secs => $4), \
+        correct_count = correct_count + $5, \
+        lapses = lapses + $6, \
+        leech = leech OR ($6 > 0 AND lapses + 1 >= $7), \
+        suspended = suspended OR ($6 > 0 AND $8 AND lapses + 1 >= $7) \
+    WHERE id = $1 AND customer_id = $2 \
+    RETURNING leech";
+
+fn log_review_query() -> String {
+    format!(
+        "INSERT INTO review_log (qa_id, customer_id, correct, kind, session_id, response_ms) \
+        VALUES ($1, $2, $3, $4, {OPEN_SESSION}, $5)"
+    )
+}
+
+const UPSERT_SETTINGS_QUERY: &str = "INSERT INTO settings (customer_id, new_per_day, \
+        reviews_per_day, mix, learning_steps, relearning_steps, timezone, rollover_hour, \
+        leech_threshold, leech_suspend, hard_after_secs) \
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+    ON CONFLICT (customer_id) DO UPDATE \
+    SET new_per_day = EXCLUDED.new_per_day, \
+        reviews_per_day = EXCLUDED.reviews_per_day, \
+        mix = EXCLUDED.mix, \
+        learning_steps = EXCLUDED.learning_steps, \
+        relearning_steps = EXCLUDED.relearning_steps, \
+        timezone = EXCLUDED.timezone, \
+        rollover_hour = EXCLUDED.rollover_hour, \
+        leech_threshold = EXCLUDED.leech_threshold, \
+        leech_suspend = EXCLUDED.leech_suspend, \
+        hard_after_secs = EXCLUDED.hard_after_secs";
+
+// Decks are merged by name. inserted is false for an existing deck. Nothing is returned
+// if the deck with the name follows a shared deck, since its cards can't be added to.
+const RESTORE_DECK_QUERY: &str = "INSERT INTO deck (customer_id, name) VALUES ($1, $2) \
+    ON CONFLICT (customer_id, name) DO UPDATE SET name = EXCLUDED.name \
+    WHERE deck.source_deck_id IS NULL \
+    RETURNING id, xmax = 0 AS inserted";
+
+fn restore_qa_query() -> String {
+    format!(
+        "INSERT INTO qa (customer_id, q, a, deck_id, tags, max, correct_count, lapses, \
+            leech, suspended, buried_until, step, due_at, created_at, last_shown_at) \
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, {}, $12, {}, {}, {}) \
+        RETURNING id",
+        from_micros("$11"),
+        from_micros("$13"),
+        from_micros("$14"),
+        from_micros("$15"),
+    )
+}
+
+fn restore_session_query() -> String {
+    format!(
+        "INSERT INTO study_session \
+            (customer_id, started_at, ended_at, reviewed, correct, failing) \
+        VALUES ($1, {}, {}, $4, $5, $6) \
+        RETURNING id",
+        from_micros("$2"),
+        from_micros("$3"),
+    )
+}
+
+fn restore_review_query() -> String {
+    format!(
+        "INSERT INTO review_log \
+            (customer_id, qa_id, session_id, correct, kind, reviewed_at, response_ms) \
+        VALUES ($1, $2, $3, $4, $5, {}, $7)",
+        from_micros("$6"),
+    )
+}
+
+// Builds the pool of connections that transactions run on.
+pub fn pool(config: Config) -> anyhow::Result<Pool> {
+    Ok(Pool::builder(Manager::new(config, NoTls)).build()?)
+}
+
+// Separates the tags of an imported QA, see IMPORT_INSERT_QUERY. Matches chr(31).
+const IMPORT_TAG_SEP: &str = "\u{1f}";
+
+// The oldest QA of the customer with each of the questions.
+const EXISTING_QAS_QUERY: &str = "SELECT DISTINCT ON (q) q, id FROM qa \
+    WHERE customer_id = $1 AND q = ANY($2) \
+    ORDER BY q, id";
+
+const UPSERT_DECKS_QUERY: &str = "INSERT INTO deck (customer_id, name) \
+    SELECT DISTINCT $1::bigint, unnest($2::text[]) \
+    ON CONFLICT (customer_id, name) DO NOTHING";
+
+// Imported QAs are passed as one array per column. Since arrays can't be nested, the tags
+// of each QA are joined by IMPORT_TAG_SEP. Their scheduling state is NULL unless it's
+// carried over from another app, see Schedule. The ids of the added QAs are returned in
+// the order the QAs were passed in, along with their decks.
+const IMPORT_INSERT_QUERY: &str = "INSERT INTO qa (q, a, customer_id, deck_id, tags, \
+        created_at, last_shown_at, correct_count, lapses, step, due_at, suspended) \
+    SELECT r.q, r.a, $1, d.id, string_to_array(r.tags, chr(31)), \
+        coalesce(to_timestamp(r.created), CURRENT_TIMESTAMP), to_timestamp(r.shown), \
+        coalesce(r.correct, 0), coalesce(r.lapses, 0), \
+        CASE WHEN r.learning THEN 0::smallint END, \
+        CASE WHEN r.learning THEN CURRENT_TIMESTAMP END, \
+        coalesce(r.suspended, FALSE) \
+    FROM unnest($2::text[], $3::text[], $4::text[], $5::text[], $6::bigint[], \
+        $7::bigint[], $8::int[], $9::int[], $10::bool[], $11::bool[]) \
+        WITH ORDINALITY AS r (q, a, deck, tags, created, shown, correct, lapses, \
+            learning, suspended, ord) \
+    LEFT JOIN deck d ON d.customer_id = $1 AND d.name = r.deck \
+    ORDER BY r.ord \
+    RETURNING id, deck_id";
+
+// Updated QAs keep their deck and tags unless the import has them. The decks they were
+// in before and after are returned.
+const IMPORT_UPDATE_QUERY: &str = "UPDATE qa SET a = r.a, \
+        deck_id = coalesce(d.id, qa.deck_id), \
+        tags = CASE WHEN r.tags = '' THEN qa.tags \
+            ELSE string_to_array(r.tags, chr(31)) END \
+    FROM unnest($2::bigint[], $3::text[], $4::text[], $5::text[]) AS r (id, a, deck, tags) \
+    JOIN qa old ON old.id = r.id \
+    LEFT JOIN deck d ON d.customer_id = $1 AND d.name = r.deck \
+    WHERE qa.id = r.id AND qa.customer_id = $1 \
+    RETURNING old.deck_id AS old_deck_id, qa.deck_id";
+
+// Today is the current day of a customer, which starts at their rollover hour in
+// their time zone, along with how much they've studied so far.
+#[derive(Debug)]
+pub struct Today {
+    pub start: SystemTime,
+    pub new_done: i64,
+    pub reviews_done: i64,
 }
 
 impl PgClient {
-    pub async fn prepare(client: Client) -> anyhow::Result<Self> {
-        let custid_from_tkn_stmt = client
-            .prepare("SELECT id FROM customer WHERE token = $1")
+    pub async fn prepare(client: Client, pool: Pool) -> anyhow::Result<Self> {
+        let find_token_stmt = client
+            .prepare(
+                "SELECT t.id, t.customer_id, t.token_hash, t.stored_key FROM api_token t \
+                JOIN customer c ON c.id = t.customer_id \
+                WHERE t.token_prefix = $1 AND NOT t.revoked AND NOT c.disabled \
+                AND (t.expires_at IS NULL OR t.expires_at > CURRENT_TIMESTAMP)",
+            )
+            .await?;
+
+        let touch_token_stmt = client
+            .prepare(
+                "UPDATE api_token SET last_used_at = CURRENT_TIMESTAMP, \
+                stored_key = COALESCE(stored_key, $2) WHERE id = $1",
+            )
+            .await?;
+
+        let token_revoked_stmt = client
+            .prepare(
+                "SELECT t.revoked OR c.disabled AS revoked FROM api_token t \
+                JOIN customer c ON c.id = t.customer_id WHERE t.id = $1",
+            )
+            .await?;
+
+        // The customer and the token of the device they registered on are inserted at
+        // once, and nothing is if the username is taken.
+        let register_stmt = client
+            .prepare(
+                "WITH c AS (INSERT INTO customer (username, password_hash, role) \
+                VALUES ($1, $2, $7) ON CONFLICT (username) DO NOTHING RETURNING id) \
+                INSERT INTO api_token (customer_id, label, token_prefix, token_hash, stored_key) \
+                SELECT id, $3, $4, $5, $6 FROM c RETURNING customer_id, id",
+            )
+            .await?;
+
+        let find_login_stmt = client
+            .prepare(
+                "SELECT id, password_hash FROM customer \
+                WHERE username = $1 AND password_hash IS NOT NULL AND NOT disabled",
+            )
+            .await?;
+
+        // Tokens without a number of days to expire in never expire.
+        let insert_token_stmt = client
+            .prepare(
+                "INSERT INTO api_token \
+                    (customer_id, label, token_prefix, token_hash, stored_key, expires_at) \
+                VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP + make_interval(days => $6)) \
+                RETURNING id",
+            )
+            .await?;
+
+        let reauth_stmt = client
+            .prepare(
+                "SELECT c.password_hash, t.token_prefix, t.stored_key FROM customer c \
+                JOIN api_token t ON t.customer_id = c.id WHERE c.id = $1 AND t.id = $2",
+            )
+            .await?;
+
+        // Everything else of the customer is deleted along, and the sessions using their
+        // tokens are closed.
+        let delete_customer_stmt = client
+            .prepare(&format!(
+                "DELETE FROM customer WHERE id = $1 RETURNING (SELECT count(*) FROM \
+                (SELECT pg_notify('{}', id::text) FROM api_token \
+                WHERE customer_id = $1 AND NOT revoked) n) AS closed",
+                token::REVOKED_CHANNEL
+            ))
             .await?;
 
         let insert_qa_stmt = client
-            .prepare("INSERT INTO qa (q, a, customer_id) VALUES ($1, $2, $3)")
+            .prepare(&format!(
+                "WITH {UPSERT_DECK} \
+                INSERT INTO qa (q, a, customer_id, deck_id, tags) \
+                VALUES ($1, $2, $3, (SELECT id FROM d), $5) \
+                RETURNING deck_id",
+            ))
             .await?;
 
-        let get_quiz_stmt = client
+        // Only reviews of the customer's own QAs are added.
+        let import_reviews_stmt = client
             .prepare(
-                "SELECT id, q, a \
+                "INSERT INTO review_log \
+                    (qa_id, customer_id, correct, kind, reviewed_at, response_ms) \
+                SELECT r.qa_id, $1, r.correct, r.kind, to_timestamp(r.at / 1000.0), r.ms \
+                FROM unnest($2::bigint[], $3::bigint[], $4::bool[], $5::smallint[], $6::int[]) \
+                    AS r (qa_id, at, correct, kind, ms) \
+                JOIN qa ON qa.id = r.qa_id AND qa.customer_id = $1",
+            )
+            .await?;
+
+        // QAs count as learning once they've been shown and until they graduate, like
+        // in IMPORT_INSERT_QUERY.
+        let export_qas_stmt = client
+            .prepare(
+                "SELECT qa.id, q, a, d.name, tags, \
+                    extract(epoch FROM created_at)::bigint, \
+                    extract(epoch FROM last_shown_at)::bigint, \
+                    correct_count, lapses, \
+                    step IS NOT NULL AND last_shown_at IS NOT NULL, suspended \
+                FROM qa \
+                LEFT JOIN deck d ON d.id = qa.deck_id \
+                WHERE qa.customer_id = $1 AND qa.id > $2 \
+                ORDER BY qa.id \
+                LIMIT $3",
+            )
+            .await?;
+
+        let export_reviews_stmt = client
+            .prepare(
+                "SELECT id, qa_id, (extract(epoch FROM reviewed_at) * 1000)::bigint, \
+                    correct, kind, response_ms \
+                FROM review_log \
+                WHERE customer_id = $1 AND id > $2 \
+                ORDER BY id \
+                LIMIT $3",
+            )
+            .await?;
+
+        // The backup statements select rows of a customer with id > $2, a page at a time.
+        let backup_decks_stmt = client
+            .prepare(
+                "SELECT id, name FROM deck \
+                WHERE customer_id = $1 AND id > $2 ORDER BY id LIMIT $3",
+            )
+            .await?;
+
+        let backup_qas_stmt = client
+            .prepare(&format!(
+                "SELECT id, q, a, deck_id, tags, max, correct_count, lapses, leech, suspended, \
+                    {}, step, {}, {}, {} \
                 FROM qa \
-                WHERE customer_id = $1
-                AND correct_count < max \
-                AND (last_shown_at IS NULL OR last_shown_at < CURRENT_DATE) \
-                ORDER BY created_at DESC \
+                WHERE customer_id = $1 AND id > $2 ORDER BY id LIMIT $3",
+                micros("buried_until"),
+                micros("due_at"),
+                micros("created_at"),
+                micros("last_shown_at"),
+            ))
+            .await?;
+
+        let backup_sessions_stmt = client
+            .prepare(&format!(
+                "SELECT id, {}, {}, reviewed, correct, failing \
+                FROM study_session \
+                WHERE customer_id = $1 AND id > $2 ORDER BY id LIMIT $3",
+                micros("started_at"),
+                micros("ended_at"),
+            ))
+            .await?;
+
+        let backup_reviews_stmt = client
+            .prepare(&format!(
+                "SELECT id, qa_id, session_id, correct, kind, {}, response_ms \
+                FROM review_log \
+                WHERE customer_id = $1 AND id > $2 ORDER BY id LIMIT $3",
+                micros("reviewed_at"),
+            ))
+            .await?;
+
+        let account_is_empty_stmt = client
+            .prepare(
+                "SELECT NOT EXISTS (SELECT 1 FROM qa WHERE customer_id = $1) \
+                    AND NOT EXISTS (SELECT 1 FROM deck WHERE customer_id = $1) \
+                    AND NOT EXISTS (SELECT 1 FROM study_session WHERE customer_id = $1) \
+                    AND NOT EXISTS (SELECT 1 FROM review_log WHERE customer_id = $1)",
+            )
+            .await?;
+
+        let today_stmt = client
+            .prepare(
+                "WITH day AS ( \
+                    SELECT (date_trunc('day', \
+                            CURRENT_TIMESTAMP AT TIME ZONE $3 - make_interval(hours => $2)) \
+                        + make_interval(hours => $2)) AT TIME ZONE $3 AS start \
+                ) \
+                SELECT day.start, \
+                    count(r.id) FILTER (WHERE r.kind = 0) AS new_done, \
+                    count(r.id) FILTER (WHERE r.kind = 2) AS reviews_done \
+                FROM day \
+                LEFT JOIN review_log r ON r.customer_id = $1 AND r.reviewed_at >= day.start \
+                GROUP BY day.start",
+            )
+            .await?;
+
+        // QAs in the (re)learning steps are shown as soon as they're due, the rest at
+        // most once a day and only as many as the daily limits allow. Which reviews and
+        // new QAs make the cut depends on the QuizOrder in $5, see order_key.
+        let get_quiz_stmt = client
+            .prepare(&format!(
+                "WITH pending AS ( \
+                    SELECT id, q, a, created_at, last_shown_at IS NULL AS new, \
+                        {} AS key \
+                    FROM qa \
+                    WHERE customer_id = $1 \
+                    AND NOT suspended \
+                    AND (buried_until IS NULL OR buried_until <= CURRENT_TIMESTAMP) \
+                    AND step IS NULL AND correct_count < max \
+                    AND (last_shown_at IS NULL OR last_shown_at < $2) \
+                ) \
+                SELECT id, q, a, grp FROM ( \
+                    (SELECT id, q, a, 0 AS grp, extract(epoch FROM due_at)::float8 AS key, \
+                        created_at \
+                    FROM qa \
+                    WHERE customer_id = $1 \
+                    AND NOT suspended \
+                    AND (buried_until IS NULL OR buried_until <= CURRENT_TIMESTAMP) \
+                    AND step IS NOT NULL AND due_at <= CURRENT_TIMESTAMP \
+                    ORDER BY due_at \
+                    LIMIT 20) \
+                    UNION ALL \
+                    (SELECT id, q, a, 1, key, created_at FROM pending WHERE NOT new \
+                    ORDER BY key, created_at DESC \
+                    LIMIT $3) \
+                    UNION ALL \
+                    (SELECT id, q, a, 2, key, created_at FROM pending WHERE new \
+                    ORDER BY key, created_at DESC \
+                    LIMIT $4) \
+                ) quiz \
+                ORDER BY grp, key, created_at DESC",
+                order_key("$5", "$6"),
+            ))
+            .await?;
+
+        // Practice quizzes drill a deck or a tag regardless of what's due. Without
+        // either, they review ahead: QAs that will be due within $4 days are picked,
+        // even if they've already been shown today. Daily limits don't apply.
+        let get_practice_quiz_stmt = client
+            .prepare(&format!(
+                "SELECT id, q, a FROM ( \
+                    SELECT id, q, a, created_at, {} AS key \
+                    FROM qa \
+                    WHERE customer_id = $1 \
+                    AND NOT suspended \
+                    AND ($2::text IS NULL OR deck_id IN \
+                        (SELECT id FROM deck WHERE customer_id = $1 AND name = $2)) \
+                    AND ($3::text IS NULL OR $3 = ANY(tags)) \
+                    AND ($2 IS NOT NULL OR $3 IS NOT NULL \
+                        OR (step IS NOT NULL \
+                            AND due_at <= CURRENT_TIMESTAMP + make_interval(days => $4)) \
+                        OR (step IS NULL AND correct_count < max \
+                            AND (last_shown_at IS NULL \
+                                OR last_shown_at < $5::timestamptz + make_interval(days => $4)))) \
+                ) practice \
+                ORDER BY key, created_at DESC, id \
+                OFFSET $8 \
                 LIMIT 20",
+                order_key("$6", "$7"),
+            ))
+            .await?;
+
+        let get_qa_stmt = client
+            .prepare("SELECT id, q, a FROM qa WHERE id = $1 AND customer_id = $2")
+            .await?;
+
+        // Distractors are other answers of the customer, preferably from the same deck
+        // or with a tag in common and of a similar length to the QA's answer.
+        let distractors_stmt = client
+            .prepare(
+                "WITH target AS ( \
+                    SELECT a, deck_id, tags FROM qa WHERE id = $1 AND customer_id = $2 \
+                ) \
+                SELECT a FROM ( \
+                    SELECT DISTINCT ON (o.a) o.a, \
+                        coalesce(o.deck_id = t.deck_id, FALSE) OR o.tags && t.tags AS related, \
+                        abs(length(o.a) - length(t.a)) AS distance \
+                    FROM qa o, target t \
+                    WHERE o.customer_id = $2 AND o.id <> $1 AND o.a <> t.a \
+                    ORDER BY o.a, related DESC, distance \
+                ) candidates \
+                ORDER BY related DESC, distance, random() \
+                LIMIT $3",
+            )
+            .await?;
+
+        // Practice reviews are only logged, the QA's schedule is left as it is.
+        let log_practice_stmt = client
+            .prepare(&format!(
+                "INSERT INTO review_log \
+                    (qa_id, customer_id, correct, kind, session_id, response_ms) \
+                SELECT id, customer_id, $3, $4, {OPEN_SESSION}, $5 FROM qa \
+                WHERE id = $1 AND customer_id = $2",
+            ))
+            .await?;
+
+        // The stats statements number days relative to the start of today in $2, see
+        // the stats module. The days are those of the customer's time zone.
+        let stats_reviews_stmt = client
+            .prepare(&format!(
+                "SELECT {} AS day, count(*) AS count \
+                FROM review_log \
+                WHERE customer_id = $1 AND reviewed_at >= {} \
+                GROUP BY day",
+                day_of("reviewed_at", "$4"),
+                days_before_today("$3", "$4"),
+            ))
+            .await?;
+
+        let stats_summary_stmt = client
+            .prepare(&format!(
+                "SELECT count(*) FILTER (WHERE kind = 2) AS reviews, \
+                    count(*) FILTER (WHERE kind = 2 AND correct) AS correct \
+                FROM review_log \
+                WHERE customer_id = $1 AND reviewed_at >= {}",
+                days_before_today("$3", "$4"),
+            ))
+            .await?;
+
+        // The reviews time spent is estimated from, see stats::time_spent.
+        let stats_time_stmt = client
+            .prepare(&format!(
+                "SELECT extract(epoch FROM reviewed_at)::float8 AS at, response_ms \
+                FROM review_log \
+                WHERE customer_id = $1 AND reviewed_at >= {} \
+                ORDER BY reviewed_at",
+                days_before_today("$3", "$4"),
+            ))
+            .await?;
+
+        let stats_cards_stmt = client
+            .prepare(
+                "SELECT count(*) FILTER (WHERE NOT suspended AND last_shown_at IS NULL) AS new, \
+                    count(*) FILTER (WHERE NOT suspended AND step IS NOT NULL) AS learning, \
+                    count(*) FILTER (WHERE NOT suspended AND step IS NULL \
+                        AND last_shown_at IS NOT NULL AND correct_count < max) AS young, \
+                    count(*) FILTER (WHERE NOT suspended AND step IS NULL \
+                        AND correct_count >= max) AS mature, \
+                    count(*) FILTER (WHERE suspended) AS suspended \
+                FROM qa \
+                WHERE customer_id = $1",
+            )
+            .await?;
+
+        let stats_days_stmt = client
+            .prepare(&format!(
+                "SELECT DISTINCT {} AS day \
+                FROM review_log \
+                WHERE customer_id = $1 \
+                ORDER BY day DESC",
+                day_of("reviewed_at", "$3"),
+            ))
+            .await?;
+
+        // QAs in the (re)learning steps are due at due_at, the rest the day after they
+        // were last shown. Overdue QAs count as due today.
+        let stats_forecast_stmt = client
+            .prepare(&format!(
+                "SELECT greatest(CASE WHEN step IS NOT NULL THEN {} ELSE {} + 1 END, 0) AS day, \
+                    count(*) AS count \
+                FROM qa \
+                WHERE customer_id = $1 \
+                AND NOT suspended \
+                AND last_shown_at IS NOT NULL AND correct_count < max \
+                GROUP BY day",
+                day_of("due_at", "$3"),
+                day_of("last_shown_at", "$3"),
+            ))
+            .await?;
+
+        let start_session_stmt = client
+            .prepare("INSERT INTO study_session (customer_id) VALUES ($1) RETURNING id")
+            .await?;
+
+        // Ends session $2 of customer $1, or any session still going when $2 is NULL,
+        // and stores its stats. Ending a session again updates its stats but keeps
+        // the time it first ended.
+        let end_session_stmt = client
+            .prepare(
+                "UPDATE study_session s \
+                SET ended_at = coalesce(ended_at, CURRENT_TIMESTAMP), \
+                    reviewed = (SELECT count(*) FROM review_log WHERE session_id = s.id), \
+                    correct = (SELECT count(*) FROM review_log \
+                        WHERE session_id = s.id AND correct), \
+                    failing = (SELECT count(*) FROM ( \
+                        SELECT DISTINCT ON (qa_id) correct FROM review_log \
+                        WHERE session_id = s.id \
+                        ORDER BY qa_id, reviewed_at DESC, id DESC \
+                    ) last WHERE NOT correct) \
+                WHERE customer_id = $1 \
+                AND CASE WHEN $2::bigint IS NULL THEN ended_at IS NULL ELSE id = $2 END \
+                RETURNING reviewed, correct, failing, \
+                    extract(epoch FROM ended_at - started_at)::float8 AS duration",
+            )
+            .await?;
+
+        // QAs in the (re)learning steps are due at due_at, the rest on the day after
+        // they were last shown, which starts at $2 + 1 day when that's today.
+        let next_due_stmt = client
+            .prepare(
+                "SELECT extract(epoch FROM \
+                    greatest(min(due), CURRENT_TIMESTAMP) - CURRENT_TIMESTAMP)::float8 AS due_in \
+                FROM ( \
+                    SELECT due_at AS due FROM qa \
+                    WHERE customer_id = $1 AND NOT suspended AND step IS NOT NULL \
+                    UNION ALL \
+                    SELECT CASE WHEN last_shown_at < $2 THEN $2 \
+                        ELSE $2::timestamptz + interval '1 day' END \
+                    FROM qa \
+                    WHERE customer_id = $1 AND NOT suspended AND step IS NULL \
+                    AND correct_count < max AND last_shown_at IS NOT NULL \
+                ) d",
+            )
+            .await?;
+
+        // Like UPSERT_DECK, but the deck is only created if the QA exists.
+        let update_qa_stmt = client
+            .prepare(
+                "WITH d AS ( \
+                    INSERT INTO deck (customer_id, name) \
+                    SELECT $3, $4::text \
+                    WHERE $4 IS NOT NULL \
+                    AND EXISTS (SELECT 1 FROM qa WHERE id = $6 AND customer_id = $3) \
+                    ON CONFLICT (customer_id, name) DO UPDATE SET name = EXCLUDED.name \
+                    RETURNING id \
+                ) \
+                UPDATE qa \
+                SET q = $1, a = $2, deck_id = coalesce((SELECT id FROM d), qa.deck_id), \
+                    tags = coalesce($5, qa.tags), leech = FALSE, lapses = 0 \
+                FROM qa old \
+                WHERE qa.id = $6 AND qa.customer_id = $3 AND old.id = qa.id \
+                RETURNING old.deck_id AS old_deck_id, qa.deck_id",
             )
             .await?;
 
-        let correct_review_stmt = client
+        let set_suspended_stmt = client
             .prepare(
                 "UPDATE qa \
-                SET correct_count = correct_count + 1, \
-                    last_shown_at = CURRENT_TIMESTAMP \
-                WHERE id = $1",
+                SET suspended = $3 \
+                WHERE id = $1 AND customer_id = $2",
             )
             .await?;
 
-        let wrong_review_stmt = client
+        let bury_qa_stmt = client
             .prepare(
                 "UPDATE qa \
-                SET last_shown_at = CURRENT_TIMESTAMP \
-                WHERE id = $1",
+                SET buried_until = (date_trunc('day', \
+                        CURRENT_TIMESTAMP AT TIME ZONE $4 - make_interval(hours => $3)) \
+                    + make_interval(hours => $3) + interval '1 day') AT TIME ZONE $4 \
+                WHERE id = $1 AND customer_id = $2",
+            )
+            .await?;
+
+        let list_qas_stmt = client
+            .prepare(
+                "SELECT id, q, a \
+                FROM qa \
+                WHERE customer_id = $1 \
+                AND id > $2 \
+                AND (NOT $3 OR leech) \
+                AND (NOT $4 OR suspended) \
+                ORDER BY id \
+                LIMIT 20",
+            )
+            .await?;
+
+        let get_settings_stmt = client
+            .prepare(
+                "SELECT new_per_day, reviews_per_day, mix, learning_steps, relearning_steps, \
+                    timezone, rollover_hour, leech_threshold, leech_suspend, hard_after_secs \
+                FROM settings \
+                WHERE customer_id = $1",
+            )
+            .await?;
+
+        let timezone_exists_stmt = client
+            .prepare("SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1)")
+            .await?;
+
+        // Copies of shared cards, and the decks that follow shared decks, can't be edited.
+        let read_only_stmt = client
+            .prepare(
+                "SELECT EXISTS (SELECT 1 FROM qa \
+                        WHERE id = $2 AND customer_id = $1 AND source_qa_id IS NOT NULL) \
+                    OR EXISTS (SELECT 1 FROM deck \
+                        WHERE customer_id = $1 AND name = ANY($3) AND source_deck_id IS NOT NULL)",
+            )
+            .await?;
+
+        // Decks that follow another one can't be shared. When a deck stops being shared,
+        // the decks of its subscribers are forked, except for those of the students of
+        // the classes it's assigned to.
+        let share_deck_stmt = client
+            .prepare(&format!(
+                "WITH d AS ( \
+                    UPDATE deck SET shared = $3 \
+                    WHERE customer_id = $1 AND name = $2 AND source_deck_id IS NULL \
+                    RETURNING id), \
+                forked AS ( \
+                    UPDATE deck s SET source_deck_id = NULL FROM d \
+                    WHERE NOT $3 AND s.source_deck_id = d.id \
+                    AND NOT {} \
+                    RETURNING s.id), \
+                cards AS ( \
+                    UPDATE qa SET source_qa_id = NULL FROM forked \
+                    WHERE qa.deck_id = forked.id) \
+                SELECT id FROM d",
+                in_class("s.customer_id", "d.id", None),
+            ))
+            .await?;
+
+        let list_shared_decks_stmt = client
+            .prepare(
+                "SELECT d.id, d.name, c.username, \
+                    (SELECT count(*) FROM qa WHERE qa.deck_id = d.id) AS cards, \
+                    (SELECT count(*) FROM deck s WHERE s.source_deck_id = d.id) AS subscribers \
+                FROM deck d \
+                JOIN customer c ON c.id = d.customer_id \
+                WHERE d.shared AND NOT c.disabled AND d.id > $1 \
+                ORDER BY d.id \
+                LIMIT $2",
+            )
+            .await?;
+
+        let shared_deck_stmt = client
+            .prepare(
+                "SELECT d.customer_id, d.name, EXISTS (SELECT 1 FROM deck s \
+                    WHERE s.customer_id = $2 AND s.source_deck_id = d.id) AS subscribed \
+                FROM deck d \
+                JOIN customer c ON c.id = d.customer_id \
+                WHERE d.id = $1 AND d.shared AND NOT c.disabled",
+            )
+            .await?;
+
+        let subscribe_stmt = client
+            .prepare(
+                "INSERT INTO deck (customer_id, name, source_deck_id) VALUES ($1, $2, $3) \
+                ON CONFLICT (customer_id, name) DO NOTHING \
+                RETURNING id",
+            )
+            .await?;
+
+        let deck_cards_stmt = client
+            .prepare("SELECT count(*) FROM qa WHERE deck_id = $1")
+            .await?;
+
+        let fork_deck_stmt = client
+            .prepare(
+                "WITH d AS ( \
+                    UPDATE deck SET source_deck_id = NULL \
+                    WHERE customer_id = $1 AND name = $2 AND source_deck_id IS NOT NULL \
+                    RETURNING id), \
+                cards AS ( \
+                    UPDATE qa SET source_qa_id = NULL FROM d \
+                    WHERE qa.deck_id = d.id AND qa.customer_id = $1) \
+                SELECT id FROM d",
+            )
+            .await?;
+
+        // Brings the copies of shared cards up to date in the decks that follow the
+        // given decks, or that the given decks follow. Copies are added for new cards of
+        // a shared deck, and once their card has left it they're detached, so that they
+        // and their reviews stay with the subscriber as their own cards. Copies keep
+        // their scheduling state either way. Decks assigned to a class count as shared
+        // with its students.
+        let sync_shared_stmt = client
+            .prepare(&format!(
+                "WITH sub AS ( \
+                    SELECT d.id, d.customer_id, d.source_deck_id FROM deck d \
+                    JOIN deck sd ON sd.id = d.source_deck_id \
+                    WHERE (sd.shared OR {}) AND (sd.id = ANY($1) OR d.id = ANY($1))), \
+                src AS ( \
+                    SELECT sub.id AS deck_id, sub.customer_id, s.id AS source_qa_id, \
+                        s.q, s.a, s.tags \
+                    FROM sub JOIN qa s ON s.deck_id = sub.source_deck_id), \
+                updated AS ( \
+                    UPDATE qa SET q = src.q, a = src.a, tags = src.tags FROM src \
+                    WHERE qa.customer_id = src.customer_id \
+                        AND qa.source_qa_id = src.source_qa_id \
+                        AND (qa.q, qa.a, qa.tags) IS DISTINCT FROM (src.q, src.a, src.tags) \
+                    RETURNING qa.id), \
+                inserted AS ( \
+                    INSERT INTO qa (q, a, tags, customer_id, deck_id, source_qa_id) \
+                    SELECT src.q, src.a, src.tags, src.customer_id, src.deck_id, \
+                        src.source_qa_id \
+                    FROM src \
+                    WHERE NOT EXISTS (SELECT 1 FROM qa c \
+                        WHERE c.customer_id = src.customer_id \
+                            AND c.source_qa_id = src.source_qa_id) \
+                    RETURNING id), \
+                detached AS ( \
+                    UPDATE qa SET source_qa_id = NULL FROM sub \
+                    WHERE qa.deck_id = sub.id AND qa.source_qa_id IS NOT NULL \
+                        AND NOT EXISTS (SELECT 1 FROM src \
+                            WHERE src.customer_id = qa.customer_id \
+                                AND src.source_qa_id = qa.source_qa_id) \
+                    RETURNING qa.id) \
+                SELECT (SELECT count(*) FROM updated) + (SELECT count(*) FROM inserted) \
+                    + (SELECT count(*) FROM detached)",
+                in_class("d.customer_id", "sd.id", None),
+            ))
+            .await?;
+
+        let create_class_stmt = client
+            .prepare(
+                "INSERT INTO class (teacher_id, name) VALUES ($1, $2) \
+                ON CONFLICT (teacher_id, name) DO NOTHING \
+                RETURNING id",
+            )
+            .await?;
+
+        let teaches_stmt = client
+            .prepare("SELECT EXISTS (SELECT 1 FROM class WHERE id = $1 AND teacher_id = $2)")
+            .await?;
+
+        // Returns the id of the student, unless no customer other than the teacher has
+        // the username. Students already in the class are left as they are, and new ones
+        // are only invited until they join.
+        let enroll_stmt = client
+            .prepare(
+                "WITH s AS ( \
+                    SELECT c.id FROM customer c \
+                    JOIN class cl ON cl.id = $1 AND cl.teacher_id <> c.id \
+                    WHERE c.username = $2 AND NOT c.disabled), \
+                i AS ( \
+                    INSERT INTO class_student (class_id, customer_id) SELECT $1, id FROM s \
+                    ON CONFLICT (class_id, customer_id) DO NOTHING) \
+                SELECT id FROM s",
+            )
+            .await?;
+
+        let join_class_stmt = client
+            .prepare(
+                "UPDATE class_student SET joined_at = coalesce(joined_at, CURRENT_TIMESTAMP) \
+                WHERE class_id = $1 AND customer_id = $2",
+            )
+            .await?;
+
+        let remove_student_stmt = client
+            .prepare(&leave_class_query(
+                "(SELECT id FROM customer WHERE username = $2)",
+            ))
+            .await?;
+
+        let leave_class_stmt = client.prepare(&leave_class_query("$2")).await?;
+
+        // Decks that follow another one can't be assigned. Returns the id of the deck.
+        let assign_deck_stmt = client
+            .prepare(
+                "INSERT INTO class_deck (class_id, deck_id, due_at) \
+                SELECT $1, id, to_timestamp($4) FROM deck \
+                WHERE customer_id = $2 AND name = $3 AND source_deck_id IS NULL \
+                ON CONFLICT (class_id, deck_id) DO UPDATE SET due_at = EXCLUDED.due_at \
+                RETURNING deck_id",
+            )
+            .await?;
+
+        // Adds decks that follow the decks assigned to the class for the students who
+        // joined it and don't follow them yet. A student who has a deck with the name gets
+        // one named after the class as well.
+        let subscribe_class_stmt = client
+            .prepare(
+                "INSERT INTO deck (customer_id, name, source_deck_id) \
+                SELECT s.customer_id, \
+                    CASE WHEN EXISTS (SELECT 1 FROM deck x \
+                        WHERE x.customer_id = s.customer_id AND x.name = sd.name) \
+                    THEN sd.name || ' (' || cl.name || ')' ELSE sd.name END, \
+                    sd.id \
+                FROM class_student s \
+                JOIN class cl ON cl.id = s.class_id \
+                JOIN class_deck cd ON cd.class_id = s.class_id \
+                JOIN deck sd ON sd.id = cd.deck_id \
+                WHERE s.class_id = $1 AND s.joined_at IS NOT NULL \
+                AND NOT EXISTS (SELECT 1 FROM deck f \
+                    WHERE f.customer_id = s.customer_id AND f.source_deck_id = sd.id) \
+                ON CONFLICT (customer_id, name) DO NOTHING \
+                RETURNING id",
+            )
+            .await?;
+
+        // Assignments are ordered the same way in both arrays.
+        let list_classes_stmt = client
+            .prepare(
+                "SELECT cl.id, cl.name, t.username AS teacher, cl.teacher_id = $1 AS teaching, \
+                    EXISTS (SELECT 1 FROM class_student s WHERE s.class_id = cl.id \
+                        AND s.customer_id = $1 AND s.joined_at IS NULL) AS invited, \
+                    (SELECT count(*) FROM class_student s \
+                        WHERE s.class_id = cl.id AND s.joined_at IS NOT NULL) AS students, \
+                    ARRAY(SELECT d.name FROM class_deck cd JOIN deck d ON d.id = cd.deck_id \
+                        WHERE cd.class_id = cl.id ORDER BY cd.due_at NULLS LAST, d.id) \
+                        AS decks, \
+                    ARRAY(SELECT extract(epoch FROM cd.due_at)::bigint \
+                        FROM class_deck cd JOIN deck d ON d.id = cd.deck_id \
+                        WHERE cd.class_id = cl.id ORDER BY cd.due_at NULLS LAST, d.id) \
+                        AS due \
+                FROM class cl \
+                JOIN customer t ON t.id = cl.teacher_id \
+                WHERE (cl.teacher_id = $1 OR EXISTS (SELECT 1 FROM class_student s \
+                    WHERE s.class_id = cl.id AND s.customer_id = $1)) \
+                AND cl.id > $2 \
+                ORDER BY cl.id \
+                LIMIT $3",
+            )
+            .await?;
+
+        // Only the cards in the students' decks that follow the assigned decks are
+        // counted, so forked decks drop out. Cards are overdue when they were due more
+        // than a day ago, see stats_forecast_stmt for when they're due.
+        let class_progress_stmt = client
+            .prepare(
+                "SELECT s.customer_id AS id, c.username, \
+                    count(qa.id) AS cards, \
+                    count(qa.id) FILTER (WHERE qa.step IS NULL \
+                        AND qa.last_shown_at IS NOT NULL) AS learned, \
+                    count(qa.id) FILTER (WHERE NOT qa.suspended \
+                        AND qa.last_shown_at IS NOT NULL AND qa.correct_count < qa.max \
+                        AND CASE WHEN qa.step IS NOT NULL THEN qa.due_at \
+                            ELSE qa.last_shown_at + interval '1 day' END \
+                            < CURRENT_TIMESTAMP - interval '1 day') AS overdue, \
+                    count(qa.id) FILTER (WHERE cd.due_at < CURRENT_TIMESTAMP \
+                        AND (qa.step IS NOT NULL OR qa.last_shown_at IS NULL)) AS late, \
+                    (SELECT count(*) FILTER (WHERE r.correct)::float8 / nullif(count(*), 0) \
+                        FROM review_log r \
+                        JOIN qa rq ON rq.id = r.qa_id \
+                        JOIN deck rd ON rd.id = rq.deck_id \
+                        JOIN class_deck rcd ON rcd.class_id = $1 \
+                            AND rcd.deck_id = rd.source_deck_id \
+                        WHERE r.customer_id = s.customer_id AND r.kind = 2 \
+                        AND r.reviewed_at >= CURRENT_TIMESTAMP - make_interval(days => $4) \
+                    ) AS retention \
+                FROM class_student s \
+                JOIN customer c ON c.id = s.customer_id \
+                LEFT JOIN class_deck cd ON cd.class_id = s.class_id \
+                LEFT JOIN deck d ON d.customer_id = s.customer_id \
+                    AND d.source_deck_id = cd.deck_id \
+                LEFT JOIN qa ON qa.deck_id = d.id \
+                WHERE s.class_id = $1 AND s.joined_at IS NOT NULL AND s.customer_id > $2 \
+                GROUP BY s.customer_id, c.username \
+                ORDER BY s.customer_id \
+                LIMIT $3",
+            )
+            .await?;
+
+        let role_stmt = client
+            .prepare("SELECT role FROM customer WHERE id = $1")
+            .await?;
+
+        // Disabling the customer closes their sessions, like revoking their tokens does.
+        let set_disabled_stmt = client
+            .prepare(&format!(
+                "UPDATE customer SET disabled = $2 WHERE id = $1 RETURNING (SELECT count(*) \
+                FROM (SELECT pg_notify('{}', id::text) FROM api_token \
+                WHERE customer_id = $1 AND NOT revoked AND $2) n) AS closed",
+                token::REVOKED_CHANNEL
+            ))
+            .await?;
+
+        let set_role_stmt = client
+            .prepare(&format!(
+                "UPDATE customer SET role = $2 WHERE id = $1 RETURNING (SELECT count(*) \
+                FROM (SELECT pg_notify('{}', id::text) FROM api_token \
+                WHERE customer_id = $1 AND NOT revoked) n) AS closed",
+                token::REVOKED_CHANNEL
+            ))
+            .await?;
+
+        let server_stats_stmt = client
+            .prepare(
+                "SELECT (SELECT count(*) FROM customer) AS customers, \
+                    (SELECT count(*) FROM customer WHERE disabled) AS disabled, \
+                    (SELECT count(*) FROM customer WHERE role = 'teacher') AS teachers, \
+                    (SELECT count(*) FROM customer WHERE role = 'admin') AS admins, \
+                    (SELECT count(DISTINCT customer_id) FROM review_log \
+                        WHERE reviewed_at >= CURRENT_TIMESTAMP - make_interval(days => $1)) \
+                        AS active, \
+                    (SELECT count(*) FROM qa) AS cards, \
+                    (SELECT count(*) FROM review_log \
+                        WHERE reviewed_at >= CURRENT_TIMESTAMP - interval '1 day') \
+                        AS reviews_today, \
+                    (SELECT count(*) FROM deck WHERE shared) AS shared_decks, \
+                    (SELECT count(*) FROM class) AS classes",
             )
             .await?;
 
         Ok(Self {
             client,
-            custid_from_tkn_stmt,
+            pool,
+            find_token_stmt,
+            touch_token_stmt,
+            token_revoked_stmt,
+            register_stmt,
+            find_login_stmt,
+            insert_token_stmt,
+            reauth_stmt,
+            delete_customer_stmt,
             insert_qa_stmt,
+            import_reviews_stmt,
+            export_qas_stmt,
+            export_reviews_stmt,
+            backup_decks_stmt,
+            backup_qas_stmt,
+            backup_sessions_stmt,
+            backup_reviews_stmt,
+            account_is_empty_stmt,
+            today_stmt,
             get_quiz_stmt,
-            correct_review_stmt,
-            wrong_review_stmt,
+            get_practice_quiz_stmt,
+            log_practice_stmt,
+            get_qa_stmt,
+            distractors_stmt,
+            stats_reviews_stmt,
+            stats_summary_stmt,
+            stats_time_stmt,
+            stats_cards_stmt,
+            stats_days_stmt,
+            stats_forecast_stmt,
+            start_session_stmt,
+            end_session_stmt,
+            next_due_stmt,
+            update_qa_stmt,
+            set_suspended_stmt,
+            bury_qa_stmt,
+            list_qas_stmt,
+            get_settings_stmt,
+            timezone_exists_stmt,
+            read_only_stmt,
+            share_deck_stmt,
+            list_shared_decks_stmt,
+            shared_deck_stmt,
+            subscribe_stmt,
+            deck_cards_stmt,
+            fork_deck_stmt,
+            sync_shared_stmt,
+            create_class_stmt,
+            teaches_stmt,
+            enroll_stmt,
+            join_class_stmt,
+            remove_student_stmt,
+            leave_class_stmt,
+            assign_deck_stmt,
+            subscribe_class_stmt,
+            list_classes_stmt,
+            class_progress_stmt,
+            role_stmt,
+            set_disabled_stmt,
+            set_role_stmt,
+            server_stats_stmt,
         })
     }
 
-    pub async fn customer_id_from_token(&self, token: &str) -> anyhow::Result<i64> {
-        let row = self
+    // Finds the usable tokens that start like the given one and returns the ids of the
+    // customer and the token whose hash matches, marking the token as used. Tokens
+    // created before challenge handshakes get their stored key here.
+    pub async fn authenticate(
+        &self,
+        hasher: &TokenHasher,
+        token: &str,
+    ) -> anyhow::Result<(i64, i64)> {
+        let rows = self
             .client
-            .query_one(&self.custid_from_tkn_stmt, &[&token])
+            .query(&self.find_token_stmt, &[&token::prefix(token)])
             .await?;
-        let id: i64 = row.get("id");
-        Ok(id)
+        let row = rows
+            .iter()
+            .find(|row| hasher.verify(token, row.get("token_hash")))
+            .ok_or_else(|| anyhow::anyhow!("No customer has the token"))?;
+
+        let token_id: i64 = row.get("id");
+        let stored_key = token::stored_key(token);
+        self.client
+            .execute(
+                &self.touch_token_stmt,
+                &[&token_id, &Some(stored_key.as_slice())],
+            )
+            .await?;
+        Ok((row.get("customer_id"), token_id))
     }
 
-    pub async fn insert_qa(&self, customer_id: i64, q: &str, a: &str) -> anyhow::Result<()> {
+    // Like authenticate, for challenge handshakes, where the proof is checked against
+    // the stored keys of the tokens with the prefix. Returns None if no token has a
+    // stored key to check it against, but one without could be the client's.
+    pub async fn authenticate_proof(
+        &self,
+        prefix: &str,
+        verify: impl Fn(&[u8]) -> bool,
+    ) -> anyhow::Result<Option<(i64, i64)>> {
+        let rows = self.client.query(&self.find_token_stmt, &[&prefix]).await?;
+        let row = rows.iter().find(|row| {
+            row.get::<_, Option<&[u8]>>("stored_key")
+                .is_some_and(&verify)
+        });
+        let Some(row) = row else {
+            if rows
+                .iter()
+                .any(|row| row.get::<_, Option<&[u8]>>("stored_key").is_none())
+            {
+                return Ok(None);
+            }
+            return Err(anyhow::anyhow!("No customer has a token with the proof"));
+        };
+
+        let token_id: i64 = row.get("id");
         self.client
-            .execute(&self.insert_qa_stmt, &[&q, &a, &customer_id])
+            .execute(&self.touch_token_stmt, &[&token_id, &None::<&[u8]>])
             .await?;
-        Ok(())
+        Ok(Some((row.get("customer_id"), token_id)))
     }
 
-    // qas should have enough space for at least 20 QA because that is the limit
-    // that we're using in the query.
-    pub async fn get_quiz(&self, customer_id: i64, qas: &mut [QA]) -> anyhow::Result<usize> {
-        let row_iter = self
+    // Creates a customer with the username and password hash and returns the token of
+    // their first device, or None if the username is taken.
+    pub async fn register(
+        &self,
+        hasher: &TokenHasher,
+        username: &str,
+        password_hash: &str,
+        device: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let created = self
+            .create_customer(
+                hasher,
+                Some(username),
+                Some(password_hash),
+                Role::User,
+                device,
+            )
+            .await?;
+        Ok(created.map(|(_, _, token)| token))
+    }
+
+    // Creates a customer with a token labeled label and returns their id, the id of the
+    // token and the token, or None if the username is taken.
+    pub async fn create_customer(
+        &self,
+        hasher: &TokenHasher,
+        username: Option<&str>,
+        password_hash: Option<&str>,
+        role: Role,
+        label: &str,
+    ) -> anyhow::Result<Option<(i64, i64, String)>> {
+        let token = token::generate();
+        let row = self
             .client
-            .query_raw(&self.get_quiz_stmt, &[customer_id])
+            .query_opt(
+                &self.register_stmt,
+                &[
+                    &username,
+                    &password_hash,
+                    &label,
+                    &token::prefix(&token),
+                    &hasher.hash(&token),
+                    &token::stored_key(&token).as_slice(),
+                    &role.as_str(),
+                ],
+            )
             .await?;
+        Ok(row.map(|row| (row.get("customer_id"), row.get("id"), token)))
+    }
 
-        let mut row_iter = pin!(row_iter);
+    pub async fn role(&self, customer_id: i64) -> anyhow::Result<Role> {
+        let row = self
+            .client
+            .query_one(&self.role_stmt, &[&customer_id])
+            .await?;
+        row.get::<_, &str>("role")
+            .parse()
+            .map_err(anyhow::Error::msg)
+    }
 
-        let mut i = 0;
-        while let Some(r) = row_iter.next().await {
-            let r = r?;
-            qas[i].id = r.get(0);
+    // Returns false if there's no customer with the id.
+    pub async fn set_disabled(&self, customer_id: i64, disabled: bool) -> anyhow::Result<bool> {
+        let n = self
+            .client
+            .execute(&self.set_disabled_stmt, &[&customer_id, &disabled])
+            .await?;
+        Ok(n == 1)
+    }
 
-            qas[i].q.clear();
-            qas[i].q.push_str(r.get(1));
+    // Returns false if there's no customer with the id.
+    pub async fn set_role(&self, customer_id: i64, role: Role) -> anyhow::Result<bool> {
+        let n = self
+            .client
+            .execute(&self.set_role_stmt, &[&customer_id, &role.as_str()])
+            .await?;
+        Ok(n == 1)
+    }
 
-            qas[i].a.clear();
-            qas[i].a.push_str(r.get(2));
-            i += 1;
-        }
+    // The stats of the database, the sessions are counted by the server.
+    pub async fn server_stats(&self) -> anyhow::Result<ServerStats> {
+        let days = STATS_DAYS as i32;
+        let row = self
+            .client
+            .query_one(&self.server_stats_stmt, &[&days])
+            .await?;
+        let count = |col: &str| row.get::<_, i64>(col);
+        Ok(ServerStats {
+            customers: count("customers") as u32,
+            disabled: count("disabled") as u32,
+            teachers: count("teachers") as u32,
+            admins: count("admins") as u32,
+            active: count("active") as u32,
+            sessions: 0,
+            cards: count("cards") as u64,
+            reviews_today: count("reviews_today") as u64,
+            shared_decks: count("shared_decks") as u32,
+            classes: count("classes") as u32,
+        })
+    }
 
-        Ok(i)
+    // Returns the id and password hash of the customer with the username, unless they
+    // are disabled.
+    pub async fn find_login(&self, username: &str) -> anyhow::Result<Option<(i64, String)>> {
+        let row = self
+            .client
+            .query_opt(&self.find_login_stmt, &[&username])
+            .await?;
+        Ok(row.map(|row| (row.get("id"), row.get("password_hash"))))
     }
 
-    pub async fn review_qa(&self, id: i64, correct: bool) -> anyhow::Result<()> {
-        let stmt = if correct {
-            &self.correct_review_stmt
-        } else {
-            &self.wrong_review_stmt
-        };
+    // Issues a token for another device of the customer, which expires in the number of
+    // days if it's given. Returns the id of the token and the token.
+    pub async fn issue_token(
+        &self,
+        hasher: &TokenHasher,
+        customer_id: i64,
+        device: &str,
+        expires_in_days: Option<i32>,
+    ) -> anyhow::Result<(i64, String)> {
+        let token = token::generate();
+        let row = self
+            .client
+            .query_one(
+                &self.insert_token_stmt,
+                &[
+                    &customer_id,
+                    &device,
+                    &token::prefix(&token),
+                    &hasher.hash(&token),
+                    &token::stored_key(&token).as_slice(),
+                    &expires_in_days,
+                ],
+            )
+            .await?;
+        Ok((row.get("id"), token))
+    }
+
+    // Returns the password hash of the customer, if they have one, and the prefix and
+    // stored key of the token the session uses, to check that it's them before something
+    // drastic.
+    pub async fn reauth_secrets(
+        &self,
+        customer_id: i64,
+        token_id: i64,
+    ) -> anyhow::Result<(Option<String>, String, Option<Vec<u8>>)> {
+        let row = self
+            .client
+            .query_one(&self.reauth_stmt, &[&customer_id, &token_id])
+            .await?;
+        Ok((
+            row.get("password_hash"),
+            row.get("token_prefix"),
+            row.get("stored_key"),
+        ))
+    }
+
+    pub async fn delete_customer(&self, customer_id: i64) -> anyhow::Result<()> {
+        let n = self
+            .client
+            .execute(&self.delete_customer_stmt, &[&customer_id])
+            .await?;
+        anyhow::ensure!(n == 1, "No customer with id {customer_id}");
+        Ok(())
+    }
+
+    pub async fn token_revoked(&self, token_id: i64) -> anyhow::Result<bool> {
+        let row = self
+            .client
+            .query_one(&self.token_revoked_stmt, &[&token_id])
+            .await?;
+        Ok(row.get("revoked"))
+    }
+
+    // Has the connection receive the notifications sent on the channel.
+    pub async fn listen(&self, channel: &str) -> anyhow::Result<()> {
+        self.client
+            .batch_execute(&format!("LISTEN {channel}"))
+            .await?;
+        Ok(())
+    }
+
+    // Returns the deck the QA was added to.
+    pub async fn insert_qa(
+        &self,
+        customer_id: i64,
+        q: &str,
+        a: &str,
+        deck: Option<&str>,
+        tags: &[&str],
+    ) -> anyhow::Result<Option<i64>> {
+        let row = self
+            .client
+            .query_one(&self.insert_qa_stmt, &[&q, &a, &customer_id, &deck, &tags])
+            .await?;
+        Ok(row.get("deck_id"))
+    }
+
+    // Adds a batch of QAs, handling the ones whose question the customer already has
+    // according to mode. With dry_run nothing is written. The batch is added in a
+    // transaction, so that a failure doesn't leave half of it behind. Returns the outcome
+    // for each QA, the ids of the added ones and the decks that changed.
+    pub async fn import_qas(
+        &self,
+        customer_id: i64,
+        qas: &[NewQA<'_>],
+        mode: DuplicateMode,
+        dry_run: bool,
+    ) -> anyhow::Result<(Vec<ImportOutcome>, Vec<i64>, Vec<i64>)> {
+        let mut conn = self.pool.get().await?;
+        let tx = conn.transaction().await?;
+
+        let questions: Vec<&str> = qas.iter().map(|qa| qa.q).collect();
+        let existing_qas_stmt = tx.prepare_cached(EXISTING_QAS_QUERY).await?;
+        let rows = tx
+            .query(&existing_qas_stmt, &[&customer_id, &questions])
+            .await?;
+        let existing = rows
+            .iter()
+            .map(|row| (row.get("q"), row.get("id")))
+            .collect();
+
+        let actions = import::plan(qas, &existing, mode);
+        let outcomes = actions.iter().copied().map(Into::into).collect();
+        if dry_run {
+            return Ok((outcomes, vec![], vec![]));
+        }
+
+        let decks: Vec<&str> = qas.iter().filter_map(|qa| qa.deck).collect();
+        if !decks.is_empty() {
+            let upsert_decks_stmt = tx.prepare_cached(UPSERT_DECKS_QUERY).await?;
+            tx.execute(&upsert_decks_stmt, &[&customer_id, &decks])
+                .await?;
+        }
+
+        let inserted: Vec<_> = qas
+            .iter()
+            .zip(&actions)
+            .filter(|(_, action)| **action == Action::Insert)
+            .map(|(qa, _)| qa)
+            .collect();
+        let (mut ids, mut deck_ids) = (vec![], vec![]);
+        if !inserted.is_empty() {
+            let qs: Vec<&str> = inserted.iter().map(|qa| qa.q).collect();
+            let (answers, deck_names, tags) = import_columns(&inserted);
+            let schedules: Vec<_> = inserted.iter().map(|qa| qa.schedule).collect();
+            let created: Vec<_> = schedules.iter().map(|s| s.map(|s| s.created_at)).collect();
+            let shown: Vec<_> = schedules
+                .iter()
+                .map(|s| s.and_then(|s| s.last_shown_at))
+                .collect();
+            let correct: Vec<_> = schedules
+                .iter()
+                .map(|s| s.map(|s| s.correct_count as i32))
+                .collect();
+            let lapses: Vec<_> = schedules
+                .iter()
+                .map(|s| s.map(|s| s.lapses as i32))
+                .collect();
+            let learning: Vec<_> = schedules.iter().map(|s| s.map(|s| s.learning)).collect();
+            let suspended: Vec<_> = schedules.iter().map(|s| s.map(|s| s.suspended)).collect();
+
+            let import_insert_stmt = tx.prepare_cached(IMPORT_INSERT_QUERY).await?;
+            let rows = tx
+                .query(
+                    &import_insert_stmt,
+                    &[
+                        &customer_id,
+                        &qs,
+                        &answers,
+                        &deck_names,
+                        &tags,
+                        &created,
+                        &shown,
+                        &correct,
+                        &lapses,
+                        &learning,
+                        &suspended,
+                    ],
+                )
+                .await?;
+            for row in rows {
+                ids.push(row.get("id"));
+                deck_ids.extend(row.get::<_, Option<i64>>("deck_id"));
+            }
+        }
+
+        let (updated_ids, updated): (Vec<i64>, Vec<_>) = qas
+            .iter()
+            .zip(&actions)
+            .filter_map(|(qa, action)| match *action {
+                Action::Update(id) => Some((id, qa)),
+                _ => None,
+            })
+            .unzip();
+        if !updated_ids.is_empty() {
+            let (answers, deck_names, tags) = import_columns(&updated);
+            let import_update_stmt = tx.prepare_cached(IMPORT_UPDATE_QUERY).await?;
+            let rows = tx
+                .query(
+                    &import_update_stmt,
+                    &[&customer_id, &updated_ids, &answers, &deck_names, &tags],
+                )
+                .await?;
+            for row in rows {
+                deck_ids.extend(row.get::<_, Option<i64>>("old_deck_id"));
+                deck_ids.extend(row.get::<_, Option<i64>>("deck_id"));
+            }
+        }
+
+        tx.commit().await?;
+        deck_ids.sort_unstable();
+        deck_ids.dedup();
+        Ok((outcomes, ids, deck_ids))
+    }
+
+    pub async fn import_reviews(
+        &self,
+        customer_id: i64,
+        reviews: &[PastReview],
+    ) -> anyhow::Result<()> {
+        let qa_ids: Vec<i64> = reviews.iter().map(|r| r.qa_id).collect();
+        let reviewed_at: Vec<i64> = reviews.iter().map(|r| r.reviewed_at).collect();
+        let correct: Vec<bool> = reviews.iter().map(|r| r.correct).collect();
+        let kinds: Vec<i16> = reviews.iter().map(|r| r.kind as i16).collect();
+        let response_ms: Vec<Option<i32>> = reviews
+            .iter()
+            .map(|r| r.response_ms.map(|ms| ms as i32))
+            .collect();
+        self.client
+            .execute(
+                &self.import_reviews_stmt,
+                &[
+                    &customer_id,
+                    &qa_ids,
+                    &reviewed_at,
+                    &correct,
+                    &kinds,
+                    &response_ms,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn export_qas(
+        &self,
+        customer_id: i64,
+        after_id: i64,
+    ) -> anyhow::Result<Vec<ExportedQA>> {
+        let rows = self
+            .client
+            .query(
+                &self.export_qas_stmt,
+                &[&customer_id, &after_id, &EXPORT_PAGE],
+            )
+            .await?;
+
+        let qas = rows
+            .iter()
+            .map(|r| ExportedQA {
+                id: r.get(0),
+                q: r.get(1),
+                a: r.get(2),
+                deck: r.get(3),
+                tags: r.get(4),
+                schedule: Schedule {
+                    created_at: r.get(5),
+                    last_shown_at: r.get(6),
+                    correct_count: r.get::<_, i32>(7).clamp(0, u8::MAX as i32) as u8,
+                    lapses: r.get::<_, i32>(8).clamp(0, u16::MAX as i32) as u16,
+                    learning: r.get(9),
+                    suspended: r.get(10),
+                },
+            })
+            .collect();
+        Ok(qas)
+    }
+
+    // Returns the reviews along with their ids, which the next page starts after.
+    pub async fn export_reviews(
+        &self,
+        customer_id: i64,
+        after_id: i64,
+    ) -> anyhow::Result<Vec<(i64, PastReview)>> {
+        let rows = self
+            .client
+            .query(
+                &self.export_reviews_stmt,
+                &[&customer_id, &after_id, &EXPORT_PAGE],
+            )
+            .await?;
+
+        let reviews = rows
+            .iter()
+            .map(|r| {
+                let review = PastReview {
+                    qa_id: r.get(1),
+                    reviewed_at: r.get(2),
+                    correct: r.get(3),
+                    kind: r.get::<_, i16>(4) as u8,
+                    response_ms: r.get::<_, Option<i32>>(5).map(|ms| ms as u32),
+                };
+                (r.get(0), review)
+            })
+            .collect();
+        Ok(reviews)
+    }
+
+    // Returns the items of the first part from the cursor on that has any, along with
+    // the part. The items are empty once there are no more.
+    pub async fn backup(
+        &self,
+        customer_id: i64,
+        after: BackupCursor,
+    ) -> anyhow::Result<(BackupPart, Vec<BackupItem>)> {
+        let mut cursor = after;
+        loop {
+            let items = self.backup_part(customer_id, cursor).await?;
+            match cursor.part.next() {
+                Some(part) if items.is_empty() => cursor = BackupCursor { part, after_id: 0 },
+                _ => return Ok((cursor.part, items)),
+            }
+        }
+    }
+
+    async fn backup_part(
+        &self,
+        customer_id: i64,
+        cursor: BackupCursor,
+    ) -> anyhow::Result<Vec<BackupItem>> {
+        let stmt = match cursor.part {
+            BackupPart::Settings if cursor.after_id == 0 => {
+                let settings = self.get_settings(customer_id).await?;
+                return Ok(vec![BackupItem::Settings(settings)]);
+            }
+            BackupPart::Settings => return Ok(vec![]),
+            BackupPart::Decks => &self.backup_decks_stmt,
+            BackupPart::QAs => &self.backup_qas_stmt,
+            BackupPart::Sessions => &self.backup_sessions_stmt,
+            BackupPart::Reviews => &self.backup_reviews_stmt,
+        };
+        let rows = self
+            .client
+            .query(stmt, &[&customer_id, &cursor.after_id, &EXPORT_PAGE])
+            .await?;
+
+        let items = rows.iter().map(|r| match cursor.part {
+            BackupPart::Settings => unreachable!("settings aren't queried"),
+            BackupPart::Decks => BackupItem::Deck(BackupDeck {
+                id: r.get(0),
+                name: r.get(1),
+            }),
+            BackupPart::QAs => BackupItem::QA(BackupQA {
+                id: r.get(0),
+                q: r.get(1),
+                a: r.get(2),
+                deck_id: r.get(3),
+                tags: r.get(4),
+                max: r.get(5),
+                correct_count: r.get(6),
+                lapses: r.get(7),
+                leech: r.get(8),
+                suspended: r.get(9),
+                buried_until: r.get(10),
+                step: r.get(11),
+                due_at: r.get(12),
+                created_at: r.get(13),
+                last_shown_at: r.get(14),
+            }),
+            BackupPart::Sessions => BackupItem::Session(BackupSession {
+                id: r.get(0),
+                started_at: r.get(1),
+                ended_at: r.get(2),
+                reviewed: r.get(3),
+                correct: r.get(4),
+                failing: r.get(5),
+            }),
+            BackupPart::Reviews => BackupItem::Review(BackupReview {
+                id: r.get(0),
+                qa_id: r.get(1),
+                session_id: r.get(2),
+                correct: r.get(3),
+                kind: r.get(4),
+                reviewed_at: r.get(5),
+                response_ms: r.get(6),
+            }),
+        });
+        Ok(items.collect())
+    }
+
+    pub async fn account_is_empty(&self, customer_id: i64) -> anyhow::Result<bool> {
+        let row = self
+            .client
+            .query_one(&self.account_is_empty_stmt, &[&customer_id])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    // Restores a batch of backup items, recording the ids they're given. Returns how
+    // many were restored and skipped, and the decks QAs were restored to. The batch is
+    // restored in a transaction, and its ids are only recorded once it's committed, so
+    // that a failed batch can be sent again.
+    pub async fn restore(
+        &self,
+        customer_id: i64,
+        restore: &mut Restore,
+        items: &[BackupItem],
+    ) -> anyhow::Result<(u16, u16, Vec<i64>)> {
+        let mut conn = self.pool.get().await?;
+        let tx = conn.transaction().await?;
+        let mut batch = restore.clone();
+        let mut deck_ids = vec![];
+
+        let (mut restored, mut skipped) = (0, 0);
+        for item in items {
+            let done = match item {
+                BackupItem::Settings(_) if batch.mode == RestoreMode::Merge => false,
+                BackupItem::Settings(settings) => {
+                    upsert_settings(&tx, customer_id, settings).await?;
+                    true
+                }
+                // A deck whose name is taken by a deck that follows a shared one is
+                // restored under another name. If that's taken too, its QAs are restored
+                // without a deck.
+                BackupItem::Deck(deck) => {
+                    let restore_deck_stmt = tx.prepare_cached(RESTORE_DECK_QUERY).await?;
+                    let mut done = false;
+                    for name in [deck.name.clone(), format!("{} (restored)", deck.name)] {
+                        let row = tx
+                            .query_opt(&restore_deck_stmt, &[&customer_id, &name])
+                            .await?;
+                        if let Some(row) = row {
+                            batch.add_deck(deck.id, row.get(0));
+                            done = row.get("inserted");
+                            break;
+                        }
+                    }
+                    done
+                }
+                BackupItem::QA(qa) => {
+                    let done = restore_qa(&tx, customer_id, &mut batch, qa).await?;
+                    if done {
+                        deck_ids.extend(qa.deck_id.and_then(|id| batch.deck(id)));
+                    }
+                    done
+                }
+                BackupItem::Session(s) => {
+                    let restore_session_stmt = tx.prepare_cached(&restore_session_query()).await?;
+                    let row = tx
+                        .query_one(
+                            &restore_session_stmt,
+                            &[
+                                &customer_id,
+                                &s.started_at,
+                                &s.ended_at,
+                                &s.reviewed,
+                                &s.correct,
+                                &s.failing,
+                            ],
+                        )
+                        .await?;
+                    batch.add_session(s.id, row.get(0));
+                    true
+                }
+                BackupItem::Review(review) => match batch.map_review(review) {
+                    None => false,
+                    Some(r) => {
+                        let restore_review_stmt =
+                            tx.prepare_cached(&restore_review_query()).await?;
+                        tx.execute(
+                            &restore_review_stmt,
+                            &[
+                                &customer_id,
+                                &r.qa_id,
+                                &r.session_id,
+                                &r.correct,
+                                &r.kind,
+                                &r.reviewed_at,
+                                &r.response_ms,
+                            ],
+                        )
+                        .await?;
+                        true
+                    }
+                },
+            };
+            match done {
+                true => restored += 1,
+                false => skipped += 1,
+            }
+        }
+
+        tx.commit().await?;
+        *restore = batch;
+        deck_ids.sort_unstable();
+        deck_ids.dedup();
+        Ok((restored, skipped, deck_ids))
+    }
+
+    pub async fn today(&self, customer_id: i64, settings: &Settings) -> anyhow::Result<Today> {
+        let rollover_hour = settings.rollover_hour as i32;
+        let row = self
+            .client
+            .query_one(
+                &self.today_stmt,
+                &[&customer_id, &rollover_hour, &settings.timezone],
+            )
+            .await?;
+
+        Ok(Today {
+            start: row.get("start"),
+            new_done: row.get("new_done"),
+            reviews_done: row.get("reviews_done"),
+        })
+    }
+
+    // qas should have enough space for at least 20 QA because that is the limit
+    // that we're using in the query.
+    pub async fn get_quiz(
+        &self,
+        customer_id: i64,
+        settings: &Settings,
+        order: QuizOrder,
+        qas: &mut [QA],
+    ) -> anyhow::Result<usize> {
+        let today = self.today(customer_id, settings).await?;
+        let max = qas.len() as i64;
+        let reviews_left = (settings.reviews_per_day as i64 - today.reviews_done).clamp(0, max);
+        let new_left = (settings.new_per_day as i64 - today.new_done).clamp(0, max);
+        let (order_id, seed) = order_to_i32(order);
+
+        let row_iter = self
+            .client
+            .query_raw(
+                &self.get_quiz_stmt,
+                slice_iter(&[
+                    &customer_id,
+                    &today.start,
+                    &reviews_left,
+                    &new_left,
+                    &order_id,
+                    &seed,
+                ]),
+            )
+            .await?;
+
+        let mut row_iter = pin!(row_iter);
+        let mut candidates = Vec::new();
+        while let Some(r) = row_iter.next().await {
+            let r = r?;
+            let group = match r.get::<_, i32>("grp") {
+                0 => Group::Learning,
+                1 => Group::Review,
+                _ => Group::New,
+            };
+            candidates.push(Candidate {
+                qa: QA {
+                    id: r.get("id"),
+                    q: r.get("q"),
+                    a: r.get("a"),
+                },
+                group,
+            });
+        }
+
+        let quiz = quiz::arrange(candidates, order, settings.mix);
+        let n = quiz.len().min(qas.len());
+        for (slot, qa) in qas.iter_mut().zip(quiz) {
+            *slot = qa;
+        }
+        Ok(n)
+    }
+
+    pub async fn get_practice_quiz(
+        &self,
+        customer_id: i64,
+        settings: &Settings,
+        practice: &Practice<'_>,
+        qas: &mut [QA],
+    ) -> anyhow::Result<usize> {
+        let today = self.today(customer_id, settings).await?;
+        let ahead_days = practice.ahead_days as i32;
+        let (order_id, seed) = order_to_i32(practice.order);
+        let offset = practice.offset as i64;
+
+        let row_iter = self
+            .client
+            .query_raw(
+                &self.get_practice_quiz_stmt,
+                slice_iter(&[
+                    &customer_id,
+                    &practice.deck,
+                    &practice.tag,
+                    &ahead_days,
+                    &today.start,
+                    &order_id,
+                    &seed,
+                    &offset,
+                ]),
+            )
+            .await?;
+
+        read_qas(row_iter, qas).await
+    }
 
-        let n = self.client.execute(stmt, &[&id]).await?;
-        anyhow::ensure!(n == 1);
+    pub async fn get_choices(&self, customer_id: i64, id: i64) -> anyhow::Result<MultipleChoice> {
+        let Some(row) = self
+            .client
+            .query_opt(&self.get_qa_stmt, &[&id, &customer_id])
+            .await?
+        else {
+            anyhow::bail!("QA {id} not found");
+        };
+        let qa = QA {
+            id: row.get("id"),
+            q: row.get("q"),
+            a: row.get("a"),
+        };
+
+        let distractors = self
+            .client
+            .query(&self.distractors_stmt, &[&id, &customer_id, &DISTRACTORS])
+            .await?
+            .into_iter()
+            .map(|row| row.get("a"))
+            .collect();
+
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH)?.subsec_nanos();
+        Ok(quiz::multiple_choice(qa, distractors, seed as u64))
+    }
 
+    pub async fn log_practice(
+        &self,
+        customer_id: i64,
+        id: i64,
+        correct: bool,
+        response_ms: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let kind = Kind::Practice as i16;
+        let response_ms = response_ms.map(|ms| ms as i32);
+        let n = self
+            .client
+            .execute(
+                &self.log_practice_stmt,
+                &[&id, &customer_id, &correct, &kind, &response_ms],
+            )
+            .await?;
+        anyhow::ensure!(n == 1, "QA {id} not found");
         Ok(())
     }
+
+    // Returns whether the QA is a leech after this review and, if it's still being
+    // (re)learned, in how many seconds it should be shown again.
+    pub async fn review_qa(
+        &self,
+        customer_id: i64,
+        id: i64,
+        correct: bool,
+        response_ms: Option<u32>,
+        settings: &Settings,
+    ) -> anyhow::Result<(bool, Option<u32>)> {
+        let mut conn = self.pool.get().await?;
+        let tx = conn.transaction().await?;
+
+        let qa_state_stmt = tx.prepare_cached(QA_STATE_QUERY).await?;
+        let Some(row) = tx.query_opt(&qa_state_stmt, &[&id, &customer_id]).await? else {
+            anyhow::bail!("QA {id} not found");
+        };
+
+        let state = State {
+            step: row.get("step"),
+            graduated: row.get::<_, i32>("correct_count") > 0,
+            new: row.get("new"),
+        };
+
+        let answer = Answer::grade(correct, response_ms, settings.hard_after_secs);
+        let (step, due_in, correct_inc) = match Steps::from(settings).next(state, answer) {
+            Next::Step { step, due_in } => (Some(step), Some(due_in), 0),
+            Next::Graduate { learned } => (None, None, learned as i32),
+        };
+        let secs = due_in.map(f64::from);
+        let lapse_inc = !correct as i32;
+        let leech_threshold = settings.leech_threshold as i32;
+
+        let review_stmt = tx.prepare_cached(REVIEW_QUERY).await?;
+        let row = tx
+            .query_one(
+                &review_stmt,
+                &[
+                    &id,
+                    &customer_id,
+                    &step,
+                    &secs,
+                    &correct_inc,
+                    &lapse_inc,
+                    &leech_threshold,
+                    &settings.leech_suspend,
+                ],
+            )
+            .await?;
+
+        let kind = state.kind() as i16;
+        let response_ms = response_ms.map(|ms| ms as i32);
+        let log_review_stmt = tx.prepare_cached(&log_review_query()).await?;
+        tx.execute(
+            &log_review_stmt,
+            &[&id, &customer_id, &correct, &kind, &response_ms],
+        )
+        .await?;
+
+        tx.commit().await?;
+        Ok((row.get("leech"), due_in))
+    }
+
+    pub async fn stats(&self, customer_id: i64, settings: &Settings) -> anyhow::Result<Stats> {
+        let today = self.today(customer_id, settings).await?;
+        let days = STATS_DAYS as i32;
+        let past = days - 1;
+        let first = -past;
+
+        let rows = self
+            .client
+            .query(
+                &self.stats_reviews_stmt,
+                &[&customer_id, &today.start, &past, &settings.timezone],
+            )
+            .await?;
+        let reviews_per_day = stats::per_day(
+            rows.iter().map(|r| (r.get("day"), r.get("count"))),
+            first,
+            STATS_DAYS,
+        );
+
+        let row = self
+            .client
+            .query_one(
+                &self.stats_summary_stmt,
+                &[&customer_id, &today.start, &past, &settings.timezone],
+            )
+            .await?;
+        let reviews: i64 = row.get("reviews");
+        let correct: i64 = row.get("correct");
+        let retention = (reviews > 0).then(|| correct as f32 / reviews as f32);
+
+        let rows = self
+            .client
+            .query(
+                &self.stats_time_stmt,
+                &[&customer_id, &today.start, &past, &settings.timezone],
+            )
+            .await?;
+        let time_spent =
+            stats::time_spent(rows.iter().map(|r| (r.get("at"), r.get("response_ms"))));
+
+        let row = self
+            .client
+            .query_one(&self.stats_cards_stmt, &[&customer_id])
+            .await?;
+        let count = |name: &str| row.get::<_, i64>(name) as u32;
+        let cards = CardCounts {
+            new: count("new"),
+            learning: count("learning"),
+            young: count("young"),
+            mature: count("mature"),
+            suspended: count("suspended"),
+        };
+
+        let review_days: Vec<i32> = self
+            .client
+            .query(
+                &self.stats_days_stmt,
+                &[&customer_id, &today.start, &settings.timezone],
+            )
+            .await?
+            .iter()
+            .map(|r| r.get("day"))
+            .collect();
+        let (current_streak, longest_streak) = stats::streaks(&review_days);
+
+        let rows = self
+            .client
+            .query(
+                &self.stats_forecast_stmt,
+                &[&customer_id, &today.start, &settings.timezone],
+            )
+            .await?;
+        let forecast = stats::per_day(
+            rows.iter().map(|r| (r.get("day"), r.get("count"))),
+            0,
+            STATS_DAYS,
+        );
+
+        Ok(Stats {
+            reviews_per_day,
+            retention,
+            cards,
+            current_streak,
+            longest_streak,
+            time_spent,
+            forecast,
+        })
+    }
+
+    pub async fn start_session(&self, customer_id: i64) -> anyhow::Result<i64> {
+        let no_session: Option<i64> = None;
+        self.client
+            .execute(&self.end_session_stmt, &[&customer_id, &no_session])
+            .await?;
+
+        let row = self
+            .client
+            .query_one(&self.start_session_stmt, &[&customer_id])
+            .await?;
+        Ok(row.get("id"))
+    }
+
+    // Returns None if the customer has no session with that id.
+    pub async fn end_session(
+        &self,
+        customer_id: i64,
+        id: i64,
+        settings: &Settings,
+    ) -> anyhow::Result<Option<SessionSummary>> {
+        let Some(row) = self
+            .client
+            .query_opt(&self.end_session_stmt, &[&customer_id, &Some(id)])
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let today = self.today(customer_id, settings).await?;
+        let next_due_in = self
+            .client
+            .query_one(&self.next_due_stmt, &[&customer_id, &today.start])
+            .await?
+            .get::<_, Option<f64>>("due_in")
+            .map(|secs| secs.ceil() as u32);
+
+        Ok(Some(SessionSummary {
+            reviewed: row.get::<_, i32>("reviewed") as u32,
+            correct: row.get::<_, i32>("correct") as u32,
+            duration: row.get::<_, f64>("duration") as u32,
+            failing: row.get::<_, i32>("failing") as u32,
+            next_due_in,
+        }))
+    }
+
+    // Returns the decks the QA was in before and after the update.
+    pub async fn update_qa(
+        &self,
+        customer_id: i64,
+        id: i64,
+        q: &str,
+        a: &str,
+        deck: Option<&str>,
+        tags: Option<&[&str]>,
+    ) -> anyhow::Result<Vec<i64>> {
+        let row = self
+            .client
+            .query_opt(
+                &self.update_qa_stmt,
+                &[&q, &a, &customer_id, &deck, &tags, &id],
+            )
+            .await?;
+        let row = row.ok_or_else(|| anyhow::anyhow!("QA {id} not found"))?;
+        let decks: [Option<i64>; 2] = [row.get("old_deck_id"), row.get("deck_id")];
+        Ok(decks.into_iter().flatten().collect())
+    }
+
+    pub async fn set_suspended(
+        &self,
+        customer_id: i64,
+        id: i64,
+        suspended: bool,
+    ) -> anyhow::Result<()> {
+        let n = self
+            .client
+            .execute(&self.set_suspended_stmt, &[&id, &customer_id, &suspended])
+            .await?;
+        anyhow::ensure!(n == 1, "QA {id} not found");
+        Ok(())
+    }
+
+    // Buries the QA until the customer's next day starts.
+    pub async fn bury_qa(
+        &self,
+        customer_id: i64,
+        id: i64,
+        settings: &Settings,
+    ) -> anyhow::Result<()> {
+        let rollover_hour = settings.rollover_hour as i32;
+        let n = self
+            .client
+            .execute(
+                &self.bury_qa_stmt,
+                &[&id, &customer_id, &rollover_hour, &settings.timezone],
+            )
+            .await?;
+        anyhow::ensure!(n == 1, "QA {id} not found");
+        Ok(())
+    }
+
+    // qas should have enough space for at least 20 QA because that is the limit
+    // that we're using in the query.
+    pub async fn list_qas(
+        &self,
+        customer_id: i64,
+        filter: QAFilter,
+        after_id: i64,
+        qas: &mut [QA],
+    ) -> anyhow::Result<usize> {
+        let leeches = filter == QAFilter::Leeches;
+        let suspended = filter == QAFilter::Suspended;
+
+        let row_iter = self
+            .client
+            .query_raw(
+                &self.list_qas_stmt,
+                slice_iter(&[&customer_id, &after_id, &leeches, &suspended]),
+            )
+            .await?;
+
+        read_qas(row_iter, qas).await
+    }
+
+    // Returns the default settings for customers that haven't changed them.
+    pub async fn get_settings(&self, customer_id: i64) -> anyhow::Result<Settings> {
+        let Some(row) = self
+            .client
+            .query_opt(&self.get_settings_stmt, &[&customer_id])
+            .await?
+        else {
+            return Ok(Settings::default());
+        };
+
+        let steps = |col: &str| -> Vec<u32> {
+            row.get::<_, Vec<i32>>(col)
+                .into_iter()
+                .map(|step| step as u32)
+                .collect()
+        };
+
+        Ok(Settings {
+            new_per_day: row.get::<_, i32>("new_per_day") as u16,
+            reviews_per_day: row.get::<_, i32>("reviews_per_day") as u16,
+            mix: mix_from_i16(row.get("mix")),
+            learning_steps: steps("learning_steps"),
+            relearning_steps: steps("relearning_steps"),
+            timezone: row.get("timezone"),
+            rollover_hour: row.get::<_, i16>("rollover_hour") as u8,
+            leech_threshold: row.get::<_, i32>("leech_threshold") as u16,
+            leech_suspend: row.get("leech_suspend"),
+            hard_after_secs: row.get::<_, i32>("hard_after_secs") as u16,
+        })
+    }
+
+    pub async fn update_settings(
+        &self,
+        customer_id: i64,
+        settings: &Settings,
+    ) -> anyhow::Result<()> {
+        let conn = self.pool.get().await?;
+        upsert_settings(&conn, customer_id, settings).await
+    }
+
+    pub async fn timezone_exists(&self, timezone: &str) -> anyhow::Result<bool> {
+        let row = self
+            .client
+            .query_one(&self.timezone_exists_stmt, &[&timezone])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    // Whether the QA with the id, or one of the decks, is a copy the customer follows
+    // someone else's shared deck with.
+    pub async fn read_only(
+        &self,
+        customer_id: i64,
+        id: Option<i64>,
+        decks: &[&str],
+    ) -> anyhow::Result<bool> {
+        let row = self
+            .client
+            .query_one(&self.read_only_stmt, &[&customer_id, &id, &decks])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    // Returns the id of the deck, or None if the customer has no deck with the name that
+    // could be shared.
+    pub async fn share_deck(
+        &self,
+        customer_id: i64,
+        deck: &str,
+        shared: bool,
+    ) -> anyhow::Result<Option<i64>> {
+        let row = self
+            .client
+            .query_opt(&self.share_deck_stmt, &[&customer_id, &deck, &shared])
+            .await?;
+        Ok(row.map(|row| row.get("id")))
+    }
+
+    pub async fn list_shared_decks(&self, after_id: i64) -> anyhow::Result<Vec<SharedDeck>> {
+        let rows = self
+            .client
+            .query(&self.list_shared_decks_stmt, &[&after_id, &EXPORT_PAGE])
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| SharedDeck {
+                id: row.get("id"),
+                name: row.get("name"),
+                owner: row.get("username"),
+                cards: row.get::<_, i64>("cards") as u32,
+                subscribers: row.get::<_, i64>("subscribers") as u32,
+            })
+            .collect())
+    }
+
+    // Returns the owner and name of the shared deck, and whether the customer already
+    // follows it.
+    pub async fn shared_deck(
+        &self,
+        customer_id: i64,
+        id: i64,
+    ) -> anyhow::Result<Option<(i64, String, bool)>> {
+        let row = self
+            .client
+            .query_opt(&self.shared_deck_stmt, &[&id, &customer_id])
+            .await?;
+        Ok(row.map(|row| {
+            (
+                row.get("customer_id"),
+                row.get("name"),
+                row.get("subscribed"),
+            )
+        }))
+    }
+
+    // Adds a deck with the name that follows the shared deck and copies its cards.
+    // Returns the number of cards, or None if the customer has a deck with the name.
+    pub async fn subscribe(
+        &self,
+        customer_id: i64,
+        id: i64,
+        name: &str,
+    ) -> anyhow::Result<Option<u32>> {
+        let Some(row) = self
+            .client
+            .query_opt(&self.subscribe_stmt, &[&customer_id, &name, &id])
+            .await?
+        else {
+            return Ok(None);
+        };
+        let deck_id: i64 = row.get("id");
+        self.sync_shared_decks(&[deck_id]).await?;
+
+        let row = self
+            .client
+            .query_one(&self.deck_cards_stmt, &[&deck_id])
+            .await?;
+        Ok(Some(row.get::<_, i64>(0) as u32))
+    }
+
+    // Makes the copies in the deck the customer's own. Returns false if the customer has
+    // no deck with the name that follows a shared deck.
+    pub async fn fork_deck(&self, customer_id: i64, deck: &str) -> anyhow::Result<bool> {
+        let row = self
+            .client
+            .query_opt(&self.fork_deck_stmt, &[&customer_id, &deck])
+            .await?;
+        Ok(row.is_some())
+    }
+
+    // Passes the changes to the decks on to their subscribers if they're shared, and
+    // those of the decks they follow on to them. Returns the number of copies added,
+    // changed or detached.
+    pub async fn sync_shared_decks(&self, deck_ids: &[i64]) -> anyhow::Result<u64> {
+        if deck_ids.is_empty() {
+            return Ok(0);
+        }
+        let row = self
+            .client
+            .query_one(&self.sync_shared_stmt, &[&deck_ids])
+            .await?;
+        Ok(row.get::<_, i64>(0) as u64)
+    }
+
+    // Returns the id of the class, or None if the customer has one with the name.
+    pub async fn create_class(&self, customer_id: i64, name: &str) -> anyhow::Result<Option<i64>> {
+        let row = self
+            .client
+            .query_opt(&self.create_class_stmt, &[&customer_id, &name])
+            .await?;
+        Ok(row.map(|row| row.get("id")))
+    }
+
+    pub async fn teaches(&self, customer_id: i64, class_id: i64) -> anyhow::Result<bool> {
+        let row = self
+            .client
+            .query_one(&self.teaches_stmt, &[&class_id, &customer_id])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    // Invites the customer with the username to the class, who follows the decks
+    // assigned to it once they join. Returns false if there's no such customer, other
+    // than the teacher.
+    pub async fn enroll(&self, class_id: i64, username: &str) -> anyhow::Result<bool> {
+        let row = self
+            .client
+            .query_opt(&self.enroll_stmt, &[&class_id, &username])
+            .await?;
+        Ok(row.is_some())
+    }
+
+    // Has the customer, who was enrolled in the class, follow its decks. Returns false if
+    // they weren't enrolled.
+    pub async fn join_class(&self, customer_id: i64, class_id: i64) -> anyhow::Result<bool> {
+        let n = self
+            .client
+            .execute(&self.join_class_stmt, &[&class_id, &customer_id])
+            .await?;
+        if n == 0 {
+            return Ok(false);
+        }
+        self.subscribe_class(class_id).await?;
+        Ok(true)
+    }
+
+    // Returns false if no student of the class has the username. The student keeps the
+    // decks assigned to it as their own.
+    pub async fn remove_student(&self, class_id: i64, username: &str) -> anyhow::Result<bool> {
+        let row = self
+            .client
+            .query_opt(&self.remove_student_stmt, &[&class_id, &username])
+            .await?;
+        Ok(row.is_some())
+    }
+
+    // Returns false if the customer isn't a student of the class.
+    pub async fn leave_class(&self, customer_id: i64, class_id: i64) -> anyhow::Result<bool> {
+        let row = self
+            .client
+            .query_opt(&self.leave_class_stmt, &[&class_id, &customer_id])
+            .await?;
+        Ok(row.is_some())
+    }
+
+    // Assigns the teacher's deck to the class without sharing it. Returns the number of
+    // students who started following it, or None if the teacher has no deck with the
+    // name that could be assigned.
+    pub async fn assign_deck(
+        &self,
+        teacher_id: i64,
+        class_id: i64,
+        deck: &str,
+        due_at: Option<i64>,
+    ) -> anyhow::Result<Option<u32>> {
+        let due_at = due_at.map(|secs| secs as f64);
+        let row = self
+            .client
+            .query_opt(
+                &self.assign_deck_stmt,
+                &[&class_id, &teacher_id, &deck, &due_at],
+            )
+            .await?;
+        if row.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.subscribe_class(class_id).await?))
+    }
+
+    // Has the students of the class follow its decks, and copies the cards they're
+    // missing. Returns the number of decks added.
+    async fn subscribe_class(&self, class_id: i64) -> anyhow::Result<u32> {
+        let rows = self
+            .client
+            .query(&self.subscribe_class_stmt, &[&class_id])
+            .await?;
+        let deck_ids: Vec<i64> = rows.iter().map(|row| row.get("id")).collect();
+        self.sync_shared_decks(&deck_ids).await?;
+        Ok(deck_ids.len() as u32)
+    }
+
+    pub async fn list_classes(
+        &self,
+        customer_id: i64,
+        after_id: i64,
+    ) -> anyhow::Result<Vec<Class>> {
+        let rows = self
+            .client
+            .query(
+                &self.list_classes_stmt,
+                &[&customer_id, &after_id, &EXPORT_PAGE],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let decks: Vec<String> = row.get("decks");
+                let due: Vec<Option<i64>> = row.get("due");
+                Class {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    teacher: row.get("teacher"),
+                    teaching: row.get("teaching"),
+                    invited: row.get("invited"),
+                    students: row.get::<_, i64>("students") as u32,
+                    assignments: decks
+                        .into_iter()
+                        .zip(due)
+                        .map(|(deck, due_at)| Assignment { deck, due_at })
+                        .collect(),
+                }
+            })
+            .collect())
+    }
+
+    pub async fn class_progress(
+        &self,
+        class_id: i64,
+        after_id: i64,
+    ) -> anyhow::Result<Vec<StudentProgress>> {
+        let days = STATS_DAYS as i32;
+        let rows = self
+            .client
+            .query(
+                &self.class_progress_stmt,
+                &[&class_id, &after_id, &EXPORT_PAGE, &days],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| StudentProgress {
+                id: row.get("id"),
+                username: row.get("username"),
+                cards: row.get::<_, i64>("cards") as u32,
+                learned: row.get::<_, i64>("learned") as u32,
+                retention: row.get::<_, Option<f64>>("retention").map(|r| r as f32),
+                overdue: row.get::<_, i64>("overdue") as u32,
+                late: row.get::<_, i64>("late") as u32,
+            })
+            .collect())
+    }
+}
+
+async fn upsert_settings(
+    client: &impl GenericClient,
+    customer_id: i64,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    let steps = |steps: &[u32]| -> Vec<i32> { steps.iter().map(|&step| step as i32).collect() };
+
+    let upsert_settings_stmt = client.prepare_cached(UPSERT_SETTINGS_QUERY).await?;
+    client
+        .execute(
+            &upsert_settings_stmt,
+            &[
+                &customer_id,
+                &(settings.new_per_day as i32),
+                &(settings.reviews_per_day as i32),
+                &mix_to_i16(settings.mix),
+                &steps(&settings.learning_steps),
+                &steps(&settings.relearning_steps),
+                &settings.timezone,
+                &(settings.rollover_hour as i16),
+                &(settings.leech_threshold as i32),
+                &settings.leech_suspend,
+                &(settings.hard_after_secs as i32),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+// When merging, QAs whose question the customer already has are skipped.
+async fn restore_qa(
+    tx: &Transaction<'_>,
+    customer_id: i64,
+    restore: &mut Restore,
+    qa: &BackupQA,
+) -> anyhow::Result<bool> {
+    if restore.mode == RestoreMode::Merge {
+        let questions = [qa.q.as_str()];
+        let existing_qas_stmt = tx.prepare_cached(EXISTING_QAS_QUERY).await?;
+        let existing = tx
+            .query(&existing_qas_stmt, &[&customer_id, &&questions[..]])
+            .await?;
+        if !existing.is_empty() {
+            return Ok(false);
+        }
+    }
+
+    let qa = restore.map_qa(qa);
+    let restore_qa_stmt = tx.prepare_cached(&restore_qa_query()).await?;
+    let row = tx
+        .query_one(
+            &restore_qa_stmt,
+            &[
+                &customer_id,
+                &qa.q,
+                &qa.a,
+                &qa.deck_id,
+                &qa.tags,
+                &qa.max,
+                &qa.correct_count,
+                &qa.lapses,
+                &qa.leech,
+                &qa.suspended,
+                &qa.buried_until,
+                &qa.step,
+                &qa.due_at,
+                &qa.created_at,
+                &qa.last_shown_at,
+            ],
+        )
+        .await?;
+    restore.add_qa(qa.id, row.get(0));
+    Ok(true)
+}
+
+fn mix_to_i16(mix: MixOrder) -> i16 {
+    match mix {
+        MixOrder::Mixed => 0,
+        MixOrder::NewFirst => 1,
+        MixOrder::ReviewsFirst => 2,
+    }
+}
+
+fn mix_from_i16(mix: i16) -> MixOrder {
+    match mix {
+        1 => MixOrder::NewFirst,
+        2 => MixOrder::ReviewsFirst,
+        _ => MixOrder::Mixed,
+    }
+}
+
+// Builds the sort key of a QA in a quiz, lower keys coming first. order is the
+// parameter holding the id from order_to_i32 and seed the one holding the seed:
+//   1: when the QA was last shown, as it's been due since the day after.
+//   2: a hash of the id, so the same seed picks the same QAs.
+//   3: when the QA was added.
+//   5: retrievability, estimated with the power forgetting curve (1 + t / 9S)^-1
+//      where t is the days since it was last shown and the stability S doubles
+//      with every day it was answered correctly.
+//   otherwise: when the QA was added, newest first.
+fn order_key(order: &str, seed: &str) -> String {
+    format!(
+        "CASE {order}::int \
+            WHEN 1 THEN extract(epoch FROM coalesce(last_shown_at, created_at))::float8 \
+            WHEN 2 THEN hashtextextended(id::text, {seed})::float8 \
+            WHEN 3 THEN extract(epoch FROM created_at)::float8 \
+            WHEN 5 THEN (1 / (1 + extract(epoch FROM \
+                    CURRENT_TIMESTAMP - coalesce(last_shown_at, CURRENT_TIMESTAMP)) \
+                / 86400 / (9 * power(2, correct_count))))::float8 \
+            ELSE -extract(epoch FROM created_at)::float8 \
+        END"
+    )
+}
+
+// Number of the day, relative to the start of today in $2, that the timestamp in
+// column falls on. Days are counted on the calendar of time zone tz, where they don't
+// all last 24 hours, and start at the same time of day as today.
+fn day_of(column: &str, tz: &str) -> String {
+    let start = format!("($2::timestamptz AT TIME ZONE {tz})");
+    format!(
+        "((({column} AT TIME ZONE {tz}) - ({start}::time - time '00:00'))::date - {start}::date)"
+    )
+}
+
+// Start of the day the given number of days before today, see day_of.
+fn days_before_today(days: &str, tz: &str) -> String {
+    format!(
+        "((($2::timestamptz AT TIME ZONE {tz}) - make_interval(days => {days})) AT TIME ZONE {tz})"
+    )
+}
+
+// Splits imported QAs into the answer, deck and tags columns of IMPORT_INSERT_QUERY and
+// IMPORT_UPDATE_QUERY.
+fn import_columns<'a>(qas: &[&NewQA<'a>]) -> (Vec<&'a str>, Vec<Option<&'a str>>, Vec<String>) {
+    let answers = qas.iter().map(|qa| qa.a).collect();
+    let decks = qas.iter().map(|qa| qa.deck).collect();
+    let tags = qas.iter().map(|qa| qa.tags.join(IMPORT_TAG_SEP)).collect();
+    (answers, decks, tags)
+}
+
+// Returns the sort key id used by the quiz statements along with the seed for shuffling.
+fn order_to_i32(order: QuizOrder) -> (i32, i64) {
+    match order {
+        QuizOrder::Newest => (0, 0),
+        QuizOrder::MostOverdue => (1, 0),
+        QuizOrder::Random { seed } => (2, seed as i64),
+        QuizOrder::OldestFirst => (3, 0),
+        QuizOrder::Interleaved => (4, 0),
+        QuizOrder::LowestRetrievability => (5, 0),
+    }
+}
+
+fn slice_iter<'a>(
+    s: &'a [&'a (dyn ToSql + Sync)],
+) -> impl ExactSizeIterator<Item = &'a dyn ToSql> + 'a {
+    s.iter().map(|s| *s as _)
+}
+
+// read_qas copies (id, q, a) rows into qas, reusing the already allocated strings.
+async fn read_qas(row_iter: RowStream, qas: &mut [QA]) -> anyhow::Result<usize> {
+    let mut row_iter = pin!(row_iter);
+
+    let mut i = 0;
+    while let Some(r) = row_iter.next().await {
+        let r = r?;
+        qas[i].id = r.get(0);
+
+        qas[i].q.clear();
+        qas[i].q.push_str(r.get(1));
+
+        qas[i].a.clear();
+        qas[i].a.push_str(r.get(2));
+        i += 1;
+    }
+
+    Ok(i)
 }
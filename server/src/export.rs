@@ -0,0 +1,52 @@
+use serde::Serialize;
+
+// Number of rows fetched for an export reply, which is more than usually fit in it.
+pub const EXPORT_PAGE: i64 = 100;
+
+// Serializes as many of the fetched items as fit in buf, which is the size of an export
+// reply. Returns their bytes, how many there were and the after_id of the next request,
+// which is None once the last page has been sent. id gives the id of the item at an
+// index.
+pub fn batch<'a, T, F>(
+    items: &[T],
+    id: F,
+    buf: &'a mut [u8],
+) -> anyhow::Result<(&'a [u8], usize, Option<i64>)>
+where
+    T: Serialize,
+    F: Fn(usize) -> i64,
+{
+    let (bytes, count) = prot::ser_prefix(items, buf)?;
+    if count == 0 && !items.is_empty() {
+        anyhow::bail!("Item {} doesn't fit in an export reply", id(0));
+    }
+
+    let more = count < items.len() || items.len() as i64 == EXPORT_PAGE;
+    let next = (more && count > 0).then(|| id(count - 1));
+    Ok((bytes, count, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch() {
+        let items = vec![(2i64, "kissa"), (5, "koira"), (9, "talo")];
+        let id = |i: usize| items[i].0;
+
+        let mut buf = [0u8; 64];
+        let (_, count, next) = batch(&items, id, &mut buf).unwrap();
+        assert_eq!((count, next), (3, None));
+
+        let mut buf = [0u8; 15];
+        let (_, count, next) = batch(&items, id, &mut buf).unwrap();
+        assert_eq!((count, next), (2, Some(5)));
+
+        let (_, count, next) = batch(&[] as &[(i64, &str)], id, &mut buf).unwrap();
+        assert_eq!((count, next), (0, None));
+
+        let mut buf = [0u8; 4];
+        assert!(batch(&items, id, &mut buf).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::{HashMap, HashSet};
+
+use message::{DuplicateMode, ImportOutcome, NewQA};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Insert,
+    Update(i64),
+    Skip,
+    Invalid,
+}
+
+impl From<Action> for ImportOutcome {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Insert => ImportOutcome::Added,
+            Action::Update(_) => ImportOutcome::Updated,
+            Action::Skip => ImportOutcome::Skipped,
+            Action::Invalid => ImportOutcome::Invalid,
+        }
+    }
+}
+
+// Decides what's done with each QA of an import batch, given the ids of the customer's
+// existing QAs by question. A question repeated within the batch is a duplicate of its
+// first occurrence, unless both are kept.
+pub fn plan(qas: &[NewQA], existing: &HashMap<&str, i64>, mode: DuplicateMode) -> Vec<Action> {
+    let mut seen = HashSet::new();
+    qas.iter()
+        .map(|qa| {
+            if qa.q.trim().is_empty() || qa.a.trim().is_empty() {
+                return Action::Invalid;
+            }
+            if mode == DuplicateMode::KeepBoth {
+                return Action::Insert;
+            }
+            if !seen.insert(qa.q) {
+                return Action::Skip;
+            }
+            match (existing.get(qa.q), mode) {
+                (Some(&id), DuplicateMode::Update) => Action::Update(id),
+                (Some(_), _) => Action::Skip,
+                (None, _) => Action::Insert,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qa<'a>(q: &'a str, a: &'a str) -> NewQA<'a> {
+        NewQA {
+            q,
+            a,
+            deck: None,
+            tags: vec![],
+            schedule: None,
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let qas = vec![
+            qa("kissa", "cat"),
+            qa("koira", "dog"),
+            qa("kissa", "cat"),
+            qa(" ", "x"),
+        ];
+        let existing = HashMap::from([("koira", 7)]);
+
+        use Action::*;
+        assert_eq!(
+            plan(&qas, &existing, DuplicateMode::Skip),
+            vec![Insert, Skip, Skip, Invalid]
+        );
+        assert_eq!(
+            plan(&qas, &existing, DuplicateMode::Update),
+            vec![Insert, Update(7), Skip, Invalid]
+        );
+        assert_eq!(
+            plan(&qas, &existing, DuplicateMode::KeepBoth),
+            vec![Insert, Insert, Insert, Invalid]
+        );
+    }
+}
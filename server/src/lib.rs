@@ -1 +1,9 @@
+pub mod account;
+pub mod backup;
 pub mod db;
+pub mod export;
+pub mod import;
+pub mod quiz;
+pub mod sched;
+pub mod stats;
+pub mod token;
@@ -1,16 +1,24 @@
 use std::env;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 use anyhow::Context;
+use futures::{future, stream, StreamExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio_postgres::NoTls;
-use tracing::{debug, error, info, info_span, Instrument};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task;
+use tokio_postgres::{AsyncMessage, NoTls};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use tracing_subscriber::EnvFilter;
 
-use memryze::db::PgClient;
-use message::{Message, QA};
-use prot;
+use memryze::account;
+use memryze::backup::{self, Restore};
+use memryze::db::{self, PgClient};
+use memryze::export;
+use memryze::token::{self, TokenHasher};
+use message::{Message, NewQA, PastReview, RestoreMode, Role, EXPORT_BATCH_BYTES, QA};
+use prot::handshake;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -28,27 +36,68 @@ async fn main() -> anyhow::Result<()> {
         .with_line_number(true)
         .init();
 
-    let (pg_client, pg_conn) = tokio_postgres::connect(&pg_uri, NoTls).await?;
+    let (pg_client, mut pg_conn) = tokio_postgres::connect(&pg_uri, NoTls).await?;
 
+    // Ids of revoked tokens are passed on to the sessions, which close if they use one.
+    let (revoked_tx, _) = broadcast::channel(16);
+    let notify_tx = revoked_tx.clone();
     tokio::spawn(async move {
-        if let Err(e) = pg_conn.await {
-            error!("connection error: {}", e);
+        let mut messages = stream::poll_fn(|cx| pg_conn.poll_message(cx));
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(AsyncMessage::Notification(n)) if n.channel() == token::REVOKED_CHANNEL => {
+                    if let Ok(token_id) = n.payload().parse::<i64>() {
+                        let _ = notify_tx.send(token_id);
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    error!("connection error: {}", e);
+                    break;
+                }
+            }
         }
     });
 
-    let pg_client = PgClient::prepare(pg_client).await?;
+    let pool = db::pool(pg_uri.parse()?)?;
+    let pg_client = PgClient::prepare(pg_client, pool).await?;
+    pg_client.listen(token::REVOKED_CHANNEL).await?;
     let pg_client = Arc::new(pg_client);
 
+    let hasher = Arc::new(TokenHasher::from_env()?);
+    if hasher.is_dev() {
+        warn!("Tokens are hashed with the development key");
+    }
+
+    // Clients then have to prove they hold their token without sending it.
+    let require_challenge = env::var("REQUIRE_CHALLENGE").is_ok_and(|v| v == "1");
+    info!(require_challenge, "Handshake mode");
+
     let listener = TcpListener::bind(&server_addr).await?;
     info!(server_addr, "Server started");
 
+    let sessions = Arc::new(AtomicU32::new(0));
+
     loop {
         let (stream, addr) = listener.accept().await?;
 
         let pg_client = pg_client.clone();
+        let hasher = hasher.clone();
+        let revoked_rx = revoked_tx.subscribe();
+        let sessions = sessions.clone();
         tokio::spawn(
             async move {
-                match handle(stream, addr, pg_client).await {
+                match handle(
+                    stream,
+                    addr,
+                    pg_client,
+                    &hasher,
+                    require_challenge,
+                    revoked_rx,
+                    sessions,
+                )
+                .await
+                {
                     Ok(()) => (),
                     Err(err @ prot::Error::StreamClosed) => debug!(%err),
                     Err(err) => error!(%err),
@@ -63,45 +112,73 @@ async fn handle(
     mut stream: TcpStream,
     _addr: SocketAddr,
     pg_client: Arc<PgClient>,
+    hasher: &TokenHasher,
+    require_challenge: bool,
+    mut revoked_rx: broadcast::Receiver<i64>,
+    sessions: Arc<AtomicU32>,
 ) -> prot::Result<()> {
-    let mut in_buf = vec![0u8; 512];
+    // Large enough for a Restore message, see RESTORE_BATCH_BYTES.
+    let mut in_buf = vec![0u8; 2048];
     let mut prim_out_buf = vec![0u8; 2048];
     let mut sec_out_buf = vec![0u8; 2048];
 
-    let first_msg = prot::read_msg(&mut stream, &mut in_buf).await?;
-
-    let Message::Handshake { version, token } = first_msg else {
-        let err = anyhow::anyhow!("First message was not handshake");
-        return Err(prot::Error::Other(err));
-    };
-
-    let customer_id = pg_client
-        .customer_id_from_token(token)
+    let (customer_id, token_id) = handshake(
+        &mut stream,
+        &mut in_buf,
+        &mut prim_out_buf,
+        &pg_client,
+        hasher,
+        require_challenge,
+    )
+    .await?;
+    // Changing the role closes the sessions, so it's only fetched once.
+    let role = pg_client
+        .role(customer_id)
         .await
-        .context("Fetching customer id from token")?;
-
-    info!(version, customer_id, "Client handshake received");
+        .context("Fetching the role of the customer")?;
+    let _session = Session::open(sessions.clone());
 
     prot::write_msg(&mut stream, &mut prim_out_buf, &Message::HandshakeResp).await?;
 
     let mut qas: Vec<QA> = vec![QA::default(); 20];
+    // The restore in progress, which maps ids of the backup to those of restored rows.
+    let mut restore: Option<Restore> = None;
     loop {
-        let msg = prot::read_msg(&mut stream, &mut in_buf).await?;
+        let msg = tokio::select! {
+            msg = prot::read_msg(&mut stream, &mut in_buf) => msg?,
+            () = revoked(&mut revoked_rx, &pg_client, token_id) => {
+                info!(token_id, "Token revoked, closing the session");
+                return Ok(());
+            }
+        };
 
         match msg {
             // TODO: ensure q and a are not empty.
-            Message::AddQA { q, a } => match pg_client.insert_qa(customer_id, &q, &a).await {
-                Ok(_) => {
-                    prot::write_msg(&mut stream, &mut prim_out_buf, &Message::AddQAResp).await?
+            Message::AddQA { q, a, deck, tags } => {
+                let read_only = pg_client.read_only(customer_id, None, deck.as_slice());
+                if refuse_read_only(&mut stream, &mut prim_out_buf, read_only.await).await? {
+                    continue;
                 }
-                Err(err) => {
-                    error!(?err, "Error inserting QA");
-                    prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
-                        .await?;
+                match pg_client.insert_qa(customer_id, q, a, deck, &tags).await {
+                    Ok(deck_id) => {
+                        sync_shared(&pg_client, deck_id.as_slice()).await;
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::AddQAResp).await?
+                    }
+                    Err(err) => {
+                        error!(?err, "Error inserting QA");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
                 }
-            },
-            Message::GetQuiz => {
-                match pg_client.get_quiz(customer_id, &mut qas).await {
+            }
+            Message::GetQuiz { order } => {
+                let quiz = async {
+                    let settings = pg_client.get_settings(customer_id).await?;
+                    pg_client
+                        .get_quiz(customer_id, &settings, order, &mut qas)
+                        .await
+                };
+                match quiz.await {
                     Ok(n) => {
                         // If n = 0 the payload will be `[0x05, 0x00, 0x00]` and the client
                         // will receive qas as an empty slice of bytes.
@@ -124,20 +201,1400 @@ async fn handle(
                     }
                 };
             }
-            Message::ReviewQA { id, correct } => match pg_client.review_qa(id, correct).await {
+            Message::GetPracticeQuiz { practice } => {
+                let quiz = async {
+                    let settings = pg_client.get_settings(customer_id).await?;
+                    pg_client
+                        .get_practice_quiz(customer_id, &settings, &practice, &mut qas)
+                        .await
+                };
+                match quiz.await {
+                    Ok(n) => {
+                        let qas_bytes = prot::ser_slice(&qas[0..n], &mut sec_out_buf)?;
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::Quiz {
+                                count: n as u16,
+                                qas_bytes,
+                            },
+                        )
+                        .await?;
+                    }
+                    Err(err) => {
+                        error!(?err, "Error fetching a practice quiz");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                }
+            }
+            Message::GetChoices { id } => match pg_client.get_choices(customer_id, id).await {
                 Err(err) => {
-                    error!(?err, "Error reviewing QA");
+                    error!(?err, "Error building multiple-choice question");
                     prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
                         .await?;
                 }
-                Ok(()) => {
-                    prot::write_msg(&mut stream, &mut prim_out_buf, &Message::ReviewQAResp).await?;
+                Ok(choices) => {
+                    prot::write_msg(
+                        &mut stream,
+                        &mut prim_out_buf,
+                        &Message::Choices { choices },
+                    )
+                    .await?;
                 }
             },
-            msg => {
-                let err = anyhow::anyhow!("Client sent wrong message: {:?}", msg);
-                return Err(prot::Error::Other(err));
+            Message::PracticeReviewQA {
+                id,
+                correct,
+                response_ms,
+            } => {
+                match pg_client
+                    .log_practice(customer_id, id, correct, response_ms)
+                    .await
+                {
+                    Err(err) => {
+                        error!(?err, "Error logging practice review");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(()) => {
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::PracticeReviewQAResp,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::ReviewQA {
+                id,
+                correct,
+                response_ms,
+            } => {
+                let review = async {
+                    let settings = pg_client.get_settings(customer_id).await?;
+                    pg_client
+                        .review_qa(customer_id, id, correct, response_ms, &settings)
+                        .await
+                };
+                match review.await {
+                    Err(err) => {
+                        error!(?err, "Error reviewing QA");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok((is_leech, due_in)) => {
+                        if is_leech && !correct {
+                            info!(id, "QA is a leech");
+                        }
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::ReviewQAResp { due_in },
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::UpdateQA {
+                id,
+                q,
+                a,
+                deck,
+                tags,
+            } => {
+                let read_only = pg_client.read_only(customer_id, Some(id), deck.as_slice());
+                if refuse_read_only(&mut stream, &mut prim_out_buf, read_only.await).await? {
+                    continue;
+                }
+                match pg_client
+                    .update_qa(customer_id, id, q, a, deck, tags.as_deref())
+                    .await
+                {
+                    Err(err) => {
+                        error!(?err, "Error updating QA");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(deck_ids) => {
+                        sync_shared(&pg_client, &deck_ids).await;
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::UpdateQAResp)
+                            .await?;
+                    }
+                }
+            }
+            Message::SuspendQA { id } | Message::UnsuspendQA { id } => {
+                let suspend = matches!(msg, Message::SuspendQA { .. });
+                match pg_client.set_suspended(customer_id, id, suspend).await {
+                    Err(err) => {
+                        error!(?err, suspend, "Error (un)suspending QA");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(()) => {
+                        let resp = if suspend {
+                            Message::SuspendQAResp
+                        } else {
+                            Message::UnsuspendQAResp
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                }
+            }
+            Message::BuryQA { id } => {
+                let bury = async {
+                    let settings = pg_client.get_settings(customer_id).await?;
+                    pg_client.bury_qa(customer_id, id, &settings).await
+                };
+                match bury.await {
+                    Err(err) => {
+                        error!(?err, "Error burying QA");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(()) => {
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::BuryQAResp)
+                            .await?;
+                    }
+                }
+            }
+            Message::ListQAs { filter, after_id } => {
+                match pg_client
+                    .list_qas(customer_id, filter, after_id, &mut qas)
+                    .await
+                {
+                    Ok(n) => {
+                        let qas_bytes = prot::ser_slice(&qas[0..n], &mut sec_out_buf)?;
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::QAs {
+                                count: n as u16,
+                                qas_bytes,
+                            },
+                        )
+                        .await?;
+                    }
+                    Err(err) => {
+                        error!(?err, "Error listing QAs");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                }
+            }
+            Message::GetSettings => match pg_client.get_settings(customer_id).await {
+                Err(err) => {
+                    error!(?err, "Error fetching settings");
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                        .await?;
+                }
+                Ok(settings) => {
+                    prot::write_msg(
+                        &mut stream,
+                        &mut prim_out_buf,
+                        &Message::Settings { settings },
+                    )
+                    .await?;
+                }
+            },
+            Message::UpdateSettings { settings } => {
+                if let Err(reason) = settings.validate() {
+                    let resp = Message::BadRequest { reason: &reason };
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    continue;
+                }
+
+                let update = async {
+                    if !pg_client.timezone_exists(&settings.timezone).await? {
+                        return Ok(false);
+                    }
+                    pg_client.update_settings(customer_id, &settings).await?;
+                    Ok::<_, anyhow::Error>(true)
+                };
+                match update.await {
+                    Ok(false) => {
+                        let resp = Message::BadRequest {
+                            reason: "Unknown time zone",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Err(err) => {
+                        error!(?err, "Error updating settings");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(true) => {
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::UpdateSettingsResp,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::GetStats => {
+                let stats = async {
+                    let settings = pg_client.get_settings(customer_id).await?;
+                    pg_client.stats(customer_id, &settings).await
+                };
+                match stats.await {
+                    Err(err) => {
+                        error!(?err, "Error computing stats");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(stats) => {
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::Stats { stats })
+                            .await?;
+                    }
+                }
+            }
+            Message::StartSession => match pg_client.start_session(customer_id).await {
+                Err(err) => {
+                    error!(?err, "Error starting session");
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                        .await?;
+                }
+                Ok(id) => {
+                    prot::write_msg(
+                        &mut stream,
+                        &mut prim_out_buf,
+                        &Message::StartSessionResp { id },
+                    )
+                    .await?;
+                }
+            },
+            Message::EndSession { id } => {
+                let summary = async {
+                    let settings = pg_client.get_settings(customer_id).await?;
+                    pg_client.end_session(customer_id, id, &settings).await
+                };
+                match summary.await {
+                    Ok(None) => {
+                        let resp = Message::BadRequest {
+                            reason: "Session not found",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Err(err) => {
+                        error!(?err, "Error ending session");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(Some(summary)) => {
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::EndSessionResp { summary },
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::ImportQAs {
+                duplicates,
+                dry_run,
+                count,
+                qas_bytes,
+            } => {
+                let mut new_qas: Vec<NewQA> = Vec::with_capacity(count as usize);
+                if let Err(err) = prot::deser_from_bytes(qas_bytes, count, &mut new_qas) {
+                    error!(?err, "Error importing QAs");
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                        .await?;
+                    continue;
+                }
+                let decks: Vec<&str> = new_qas.iter().filter_map(|qa| qa.deck).collect();
+                let read_only = pg_client.read_only(customer_id, None, &decks);
+                if refuse_read_only(&mut stream, &mut prim_out_buf, read_only.await).await? {
+                    continue;
+                }
+                match pg_client
+                    .import_qas(customer_id, &new_qas, duplicates, dry_run)
+                    .await
+                {
+                    Err(err) => {
+                        error!(?err, "Error importing QAs");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok((outcomes, ids, deck_ids)) => {
+                        // Updates of copies are undone here too.
+                        sync_shared(&pg_client, &deck_ids).await;
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::ImportQAsResp { outcomes, ids },
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::ImportReviews {
+                count,
+                reviews_bytes,
+            } => {
+                let import = async {
+                    let mut reviews = Vec::with_capacity(count as usize);
+                    prot::deser_from_bytes(reviews_bytes, count, &mut reviews)?;
+                    pg_client.import_reviews(customer_id, &reviews).await
+                };
+                match import.await {
+                    Err(err) => {
+                        error!(?err, "Error importing reviews");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(()) => {
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::ImportReviewsResp,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::ExportQAs { after_id } => {
+                let qas = match pg_client.export_qas(customer_id, after_id).await {
+                    Ok(qas) => qas,
+                    Err(err) => {
+                        error!(?err, "Error exporting QAs");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                        continue;
+                    }
+                };
+                let batch_buf = &mut sec_out_buf[..EXPORT_BATCH_BYTES];
+                let (qas_bytes, count, next) = match export::batch(&qas, |i| qas[i].id, batch_buf) {
+                    Ok(batch) => batch,
+                    Err(err) => {
+                        error!(?err, "Error batching exported QAs");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                        continue;
+                    }
+                };
+                prot::write_msg(
+                    &mut stream,
+                    &mut prim_out_buf,
+                    &Message::ExportedQAs {
+                        count: count as u16,
+                        qas_bytes,
+                        next,
+                    },
+                )
+                .await?;
+            }
+            Message::ExportReviews { after_id } => {
+                let (ids, reviews): (Vec<i64>, Vec<PastReview>) = match pg_client
+                    .export_reviews(customer_id, after_id)
+                    .await
+                {
+                    Ok(reviews) => reviews.into_iter().unzip(),
+                    Err(err) => {
+                        error!(?err, "Error exporting reviews");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                        continue;
+                    }
+                };
+                let batch_buf = &mut sec_out_buf[..EXPORT_BATCH_BYTES];
+                let (reviews_bytes, count, next) =
+                    match export::batch(&reviews, |i| ids[i], batch_buf) {
+                        Ok(batch) => batch,
+                        Err(err) => {
+                            error!(?err, "Error batching exported reviews");
+                            prot::write_msg(
+                                &mut stream,
+                                &mut prim_out_buf,
+                                &Message::InternalError,
+                            )
+                            .await?;
+                            continue;
+                        }
+                    };
+                prot::write_msg(
+                    &mut stream,
+                    &mut prim_out_buf,
+                    &Message::ExportedReviews {
+                        count: count as u16,
+                        reviews_bytes,
+                        next,
+                    },
+                )
+                .await?;
+            }
+            Message::Backup { after } => {
+                let (part, items) = match pg_client.backup(customer_id, after).await {
+                    Ok(backup) => backup,
+                    Err(err) => {
+                        error!(?err, "Error backing up");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                        continue;
+                    }
+                };
+                let batch_buf = &mut sec_out_buf[..EXPORT_BATCH_BYTES];
+                let (items_bytes, count, next_id) =
+                    match export::batch(&items, |i| items[i].id(), batch_buf) {
+                        Ok(batch) => batch,
+                        Err(err) => {
+                            error!(?err, "Error batching the backup");
+                            prot::write_msg(
+                                &mut stream,
+                                &mut prim_out_buf,
+                                &Message::InternalError,
+                            )
+                            .await?;
+                            continue;
+                        }
+                    };
+                let next = backup::next_cursor(part, next_id);
+                prot::write_msg(
+                    &mut stream,
+                    &mut prim_out_buf,
+                    &Message::BackupItems {
+                        count: count as u16,
+                        items_bytes,
+                        next,
+                    },
+                )
+                .await?;
+            }
+            Message::StartRestore { mode } => {
+                let empty = match mode {
+                    RestoreMode::Merge => Ok(true),
+                    RestoreMode::Empty => pg_client.account_is_empty(customer_id).await,
+                };
+                match empty {
+                    Err(err) => {
+                        error!(?err, "Error checking if the account is empty");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(false) => {
+                        let resp = Message::BadRequest {
+                            reason: "The account isn't empty, restore with merging instead",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(true) => {
+                        restore = Some(Restore::new(mode));
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::StartRestoreResp)
+                            .await?;
+                    }
+                }
+            }
+            Message::Restore { count, items_bytes } => {
+                let Some(restore) = restore.as_mut() else {
+                    let resp = Message::BadRequest {
+                        reason: "No restore has been started",
+                    };
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    continue;
+                };
+                let restored = async {
+                    let mut items = Vec::with_capacity(count as usize);
+                    prot::deser_from_bytes(items_bytes, count, &mut items)?;
+                    pg_client.restore(customer_id, restore, &items).await
+                };
+                match restored.await {
+                    Err(err) => {
+                        error!(?err, "Error restoring");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok((restored, skipped, deck_ids)) => {
+                        sync_shared(&pg_client, &deck_ids).await;
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::RestoreResp { restored, skipped },
+                        )
+                        .await?;
+                    }
+                }
             }
+            Message::DeleteAccount {
+                password,
+                nonce: client_nonce,
+            } => {
+                let password = password.map(str::to_owned);
+                let secrets = match pg_client.reauth_secrets(customer_id, token_id).await {
+                    Err(err) => {
+                        error!(?err, "Error fetching the secrets to re-authenticate with");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                        continue;
+                    }
+                    Ok(secrets) => secrets,
+                };
+                // Tokens are never sent in place of a password, so accounts without one
+                // are challenged again instead.
+                let verified = match (secrets, password) {
+                    ((Some(password_hash), _, _), Some(password)) => {
+                        task::spawn_blocking(move || {
+                            account::verify_password(&password, &password_hash)
+                        })
+                        .await
+                        .context("Verifying the password")?
+                    }
+                    ((None, prefix, Some(stored_key)), None) => {
+                        let server_nonce = handshake::nonce();
+                        let challenge = Message::Challenge {
+                            nonce: server_nonce,
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &challenge).await?;
+                        match prot::read_msg(&mut stream, &mut in_buf).await? {
+                            Message::Proof { proof } => handshake::verify(
+                                &stored_key,
+                                &proof,
+                                handshake::PROTOCOL_VERSION,
+                                &prefix,
+                                &client_nonce,
+                                &server_nonce,
+                            ),
+                            _ => false,
+                        }
+                    }
+                    ((None, _, None), None) => {
+                        let resp = Message::BadRequest {
+                            reason: "The token has to be rotated to be used with challenges",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                        continue;
+                    }
+                    ((Some(_), _, _), None) => {
+                        let resp = Message::BadRequest {
+                            reason: "The account is confirmed with its password",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                        continue;
+                    }
+                    ((None, _, _), Some(_)) => {
+                        let resp = Message::BadRequest {
+                            reason: "The account has no password and is confirmed with a challenge",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                        continue;
+                    }
+                };
+                // Like a failed login, this closes the connection.
+                if !verified {
+                    let resp = Message::BadRequest {
+                        reason: "Wrong password",
+                    };
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    let err = anyhow::anyhow!("Client failed to re-authenticate");
+                    return Err(prot::Error::Other(err));
+                }
+
+                match pg_client.delete_customer(customer_id).await {
+                    Err(err) => {
+                        error!(?err, "Error deleting the account");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(()) => {
+                        info!(customer_id, "Account deleted");
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::DeleteAccountResp,
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            }
+            Message::ShareDeck { deck, shared } => {
+                match pg_client.share_deck(customer_id, deck, shared).await {
+                    Err(err) => {
+                        error!(?err, "Error sharing the deck");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(None) => {
+                        let resp = Message::BadRequest {
+                            reason: "You have no deck with the name, other than one you follow",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(Some(id)) => {
+                        info!(customer_id, id, shared, "Deck sharing changed");
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::ShareDeckResp { id },
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::ListSharedDecks { after_id } => {
+                let decks = match pg_client.list_shared_decks(after_id).await {
+                    Ok(decks) => decks,
+                    Err(err) => {
+                        error!(?err, "Error listing shared decks");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                        continue;
+                    }
+                };
+                let batch_buf = &mut sec_out_buf[..EXPORT_BATCH_BYTES];
+                let (decks_bytes, count, next) =
+                    match export::batch(&decks, |i| decks[i].id, batch_buf) {
+                        Ok(batch) => batch,
+                        Err(err) => {
+                            error!(?err, "Error batching shared decks");
+                            prot::write_msg(
+                                &mut stream,
+                                &mut prim_out_buf,
+                                &Message::InternalError,
+                            )
+                            .await?;
+                            continue;
+                        }
+                    };
+                prot::write_msg(
+                    &mut stream,
+                    &mut prim_out_buf,
+                    &Message::SharedDecks {
+                        count: count as u16,
+                        decks_bytes,
+                        next,
+                    },
+                )
+                .await?;
+            }
+            Message::SubscribeDeck { id, name } => {
+                let subscribed = async {
+                    let Some((owner_id, deck, subscribed)) =
+                        pg_client.shared_deck(customer_id, id).await?
+                    else {
+                        return Ok(Err("No deck with the id is shared"));
+                    };
+                    if owner_id == customer_id {
+                        return Ok(Err("The deck is your own"));
+                    }
+                    if subscribed {
+                        return Ok(Err("You already follow the deck"));
+                    }
+                    let name = name.unwrap_or(&deck);
+                    match pg_client.subscribe(customer_id, id, name).await? {
+                        Some(cards) => anyhow::Ok(Ok(cards)),
+                        None => Ok(Err("You already have a deck with the name")),
+                    }
+                };
+                match subscribed.await {
+                    Err(err) => {
+                        error!(?err, "Error subscribing to the deck");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(Err(reason)) => {
+                        let resp = Message::BadRequest { reason };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(Ok(cards)) => {
+                        info!(customer_id, id, cards, "Subscribed to a shared deck");
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::SubscribeDeckResp { cards },
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::ForkDeck { deck } => match pg_client.fork_deck(customer_id, deck).await {
+                Err(err) => {
+                    error!(?err, "Error forking the deck");
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                        .await?;
+                }
+                Ok(false) => {
+                    let resp = Message::BadRequest {
+                        reason: "You follow no shared deck with a deck of the name",
+                    };
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                }
+                Ok(true) => {
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &Message::ForkDeckResp).await?;
+                }
+            },
+            Message::CreateClass { name } => {
+                if refuse_role(&mut stream, &mut prim_out_buf, role, Role::Teacher).await? {
+                    continue;
+                }
+                if name.trim().is_empty() {
+                    let resp = Message::BadRequest {
+                        reason: "The class needs a name",
+                    };
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    continue;
+                }
+                match pg_client.create_class(customer_id, name).await {
+                    Err(err) => {
+                        error!(?err, "Error creating the class");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(None) => {
+                        let resp = Message::BadRequest {
+                            reason: "You already have a class with the name",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(Some(id)) => {
+                        info!(customer_id, id, "Class created");
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::CreateClassResp { id },
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::EnrollStudent { class_id, username } => {
+                let teaches = pg_client.teaches(customer_id, class_id);
+                if refuse_unless_teacher(&mut stream, &mut prim_out_buf, role, teaches.await)
+                    .await?
+                {
+                    continue;
+                }
+                match pg_client.enroll(class_id, username).await {
+                    Err(err) => {
+                        error!(?err, "Error enrolling the student");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(false) => {
+                        let resp = Message::BadRequest {
+                            reason: "No one else has the username",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(true) => {
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::EnrollStudentResp,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::JoinClass { class_id } => {
+                match pg_client.join_class(customer_id, class_id).await {
+                    Err(err) => {
+                        error!(?err, "Error joining the class");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(false) => {
+                        let resp = Message::BadRequest {
+                            reason: "You haven't been enrolled in the class",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(true) => {
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::JoinClassResp)
+                            .await?;
+                    }
+                }
+            }
+            Message::RemoveStudent { class_id, username } => {
+                let teaches = pg_client.teaches(customer_id, class_id);
+                if refuse_unless_teacher(&mut stream, &mut prim_out_buf, role, teaches.await)
+                    .await?
+                {
+                    continue;
+                }
+                match pg_client.remove_student(class_id, username).await {
+                    Err(err) => {
+                        error!(?err, "Error removing the student");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(false) => {
+                        let resp = Message::BadRequest {
+                            reason: "No student of the class has the username",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(true) => {
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::RemoveStudentResp,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::LeaveClass { class_id } => {
+                match pg_client.leave_class(customer_id, class_id).await {
+                    Err(err) => {
+                        error!(?err, "Error leaving the class");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(false) => {
+                        let resp = Message::BadRequest {
+                            reason: "You aren't a student of the class",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(true) => {
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::LeaveClassResp)
+                            .await?;
+                    }
+                }
+            }
+            Message::AssignDeck {
+                class_id,
+                deck,
+                due_at,
+            } => {
+                let teaches = pg_client.teaches(customer_id, class_id);
+                if refuse_unless_teacher(&mut stream, &mut prim_out_buf, role, teaches.await)
+                    .await?
+                {
+                    continue;
+                }
+                match pg_client
+                    .assign_deck(customer_id, class_id, deck, due_at)
+                    .await
+                {
+                    Err(err) => {
+                        error!(?err, "Error assigning the deck");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(None) => {
+                        let resp = Message::BadRequest {
+                            reason: "You have no deck with the name, other than one you follow",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(Some(subscribed)) => {
+                        info!(customer_id, class_id, subscribed, "Deck assigned");
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::AssignDeckResp { subscribed },
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::ListClasses { after_id } => {
+                let classes = match pg_client.list_classes(customer_id, after_id).await {
+                    Ok(classes) => classes,
+                    Err(err) => {
+                        error!(?err, "Error listing classes");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                        continue;
+                    }
+                };
+                let batch_buf = &mut sec_out_buf[..EXPORT_BATCH_BYTES];
+                let (classes_bytes, count, next) =
+                    match export::batch(&classes, |i| classes[i].id, batch_buf) {
+                        Ok(batch) => batch,
+                        Err(err) => {
+                            error!(?err, "Error batching classes");
+                            prot::write_msg(
+                                &mut stream,
+                                &mut prim_out_buf,
+                                &Message::InternalError,
+                            )
+                            .await?;
+                            continue;
+                        }
+                    };
+                prot::write_msg(
+                    &mut stream,
+                    &mut prim_out_buf,
+                    &Message::Classes {
+                        count: count as u16,
+                        classes_bytes,
+                        next,
+                    },
+                )
+                .await?;
+            }
+            Message::GetClassProgress { class_id, after_id } => {
+                let teaches = pg_client.teaches(customer_id, class_id);
+                if refuse_unless_teacher(&mut stream, &mut prim_out_buf, role, teaches.await)
+                    .await?
+                {
+                    continue;
+                }
+                let students = match pg_client.class_progress(class_id, after_id).await {
+                    Ok(students) => students,
+                    Err(err) => {
+                        error!(?err, "Error fetching the progress of the class");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                        continue;
+                    }
+                };
+                let batch_buf = &mut sec_out_buf[..EXPORT_BATCH_BYTES];
+                let (students_bytes, count, next) =
+                    match export::batch(&students, |i| students[i].id, batch_buf) {
+                        Ok(batch) => batch,
+                        Err(err) => {
+                            error!(?err, "Error batching students");
+                            prot::write_msg(
+                                &mut stream,
+                                &mut prim_out_buf,
+                                &Message::InternalError,
+                            )
+                            .await?;
+                            continue;
+                        }
+                    };
+                prot::write_msg(
+                    &mut stream,
+                    &mut prim_out_buf,
+                    &Message::ClassProgress {
+                        count: count as u16,
+                        students_bytes,
+                        next,
+                    },
+                )
+                .await?;
+            }
+            Message::CreateCustomer {
+                username,
+                role: new_role,
+                label,
+            } => {
+                if refuse_role(&mut stream, &mut prim_out_buf, role, Role::Admin).await? {
+                    continue;
+                }
+                let valid = username
+                    .map_or(Ok(()), account::validate_username)
+                    .and(account::validate_device(label));
+                if let Err(reason) = valid {
+                    prot::write_msg(
+                        &mut stream,
+                        &mut prim_out_buf,
+                        &Message::BadRequest { reason },
+                    )
+                    .await?;
+                    continue;
+                }
+                match pg_client
+                    .create_customer(hasher, username, None, new_role, label)
+                    .await
+                {
+                    Err(err) => {
+                        error!(?err, "Error creating the customer");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(None) => {
+                        let resp = Message::BadRequest {
+                            reason: "The username is taken",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(Some((id, _, token))) => {
+                        info!(
+                            customer_id,
+                            id,
+                            role = new_role.as_str(),
+                            "Customer created"
+                        );
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::CreatedCustomer { id, token: &token },
+                        )
+                        .await?;
+                    }
+                }
+            }
+            Message::SetDisabled {
+                customer_id: id,
+                disabled,
+            } => {
+                if refuse_role(&mut stream, &mut prim_out_buf, role, Role::Admin).await? {
+                    continue;
+                }
+                // So that admins don't lock themselves out.
+                if id == customer_id {
+                    let resp = Message::BadRequest {
+                        reason: "You can't disable yourself",
+                    };
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    continue;
+                }
+                match pg_client.set_disabled(id, disabled).await {
+                    Err(err) => {
+                        error!(?err, "Error disabling the customer");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(false) => {
+                        let resp = Message::BadRequest {
+                            reason: "No customer has the id",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(true) => {
+                        info!(customer_id, id, disabled, "Customer disabled changed");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::SetDisabledResp)
+                            .await?;
+                    }
+                }
+            }
+            Message::SetRole {
+                customer_id: id,
+                role: new_role,
+            } => {
+                if refuse_role(&mut stream, &mut prim_out_buf, role, Role::Admin).await? {
+                    continue;
+                }
+                if id == customer_id {
+                    let resp = Message::BadRequest {
+                        reason: "You can't change your own role",
+                    };
+                    prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    continue;
+                }
+                match pg_client.set_role(id, new_role).await {
+                    Err(err) => {
+                        error!(?err, "Error changing the role");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(false) => {
+                        let resp = Message::BadRequest {
+                            reason: "No customer has the id",
+                        };
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &resp).await?;
+                    }
+                    Ok(true) => {
+                        info!(customer_id, id, role = new_role.as_str(), "Role changed");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::SetRoleResp)
+                            .await?;
+                    }
+                }
+            }
+            Message::GetServerStats => {
+                if refuse_role(&mut stream, &mut prim_out_buf, role, Role::Admin).await? {
+                    continue;
+                }
+                match pg_client.server_stats().await {
+                    Err(err) => {
+                        error!(?err, "Error fetching the server's stats");
+                        prot::write_msg(&mut stream, &mut prim_out_buf, &Message::InternalError)
+                            .await?;
+                    }
+                    Ok(mut stats) => {
+                        stats.sessions = sessions.load(Ordering::Relaxed);
+                        prot::write_msg(
+                            &mut stream,
+                            &mut prim_out_buf,
+                            &Message::ServerStats { stats },
+                        )
+                        .await?;
+                    }
+                }
+            }
+            msg => {
+                let err = anyhow::anyhow!("Client sent wrong message: {:?}", msg);
+                return Err(prot::Error::Other(err));
+            }
+        }
+    }
+}
+
+// Authenticates the client and returns the ids of the customer and the token. The client
+// either sends the token or answers a challenge, see prot::handshake. A token the server
+// has no stored key for has to be sent once, which is refused if challenges are required.
+// Clients without a token get one by registering or logging in first.
+async fn handshake(
+    stream: &mut TcpStream,
+    in_buf: &mut [u8],
+    out_buf: &mut [u8],
+    pg_client: &PgClient,
+    hasher: &TokenHasher,
+    require_challenge: bool,
+) -> prot::Result<(i64, i64)> {
+    loop {
+        match prot::read_msg(stream, in_buf).await? {
+            Message::Handshake { version, token } => {
+                if require_challenge {
+                    let reason = "The server requires a challenge handshake";
+                    prot::write_msg(stream, out_buf, &Message::BadRequest { reason }).await?;
+                    let err = anyhow::anyhow!("Client sent its token, which isn't allowed");
+                    return Err(prot::Error::Other(err));
+                }
+
+                let (customer_id, token_id) = pg_client
+                    .authenticate(hasher, token)
+                    .await
+                    .context("Fetching customer id from token")?;
+                info!(version, customer_id, token_id, "Client handshake received");
+                return Ok((customer_id, token_id));
+            }
+            Message::StartHandshake {
+                version,
+                prefix,
+                nonce: client_nonce,
+            } => {
+                if version != handshake::PROTOCOL_VERSION {
+                    let reason = "The protocol version isn't supported";
+                    prot::write_msg(stream, out_buf, &Message::BadRequest { reason }).await?;
+                    let err = anyhow::anyhow!("Client sent protocol version {version}");
+                    return Err(prot::Error::Other(err));
+                }
+
+                let prefix = prefix.to_owned();
+                let server_nonce = handshake::nonce();
+                let challenge = Message::Challenge {
+                    nonce: server_nonce,
+                };
+                prot::write_msg(stream, out_buf, &challenge).await?;
+
+                let Message::Proof { proof } = prot::read_msg(stream, in_buf).await? else {
+                    let err = anyhow::anyhow!("Client didn't answer the challenge");
+                    return Err(prot::Error::Other(err));
+                };
+                let verify = |stored_key: &[u8]| {
+                    handshake::verify(
+                        stored_key,
+                        &proof,
+                        version,
+                        &prefix,
+                        &client_nonce,
+                        &server_nonce,
+                    )
+                };
+                let ids = pg_client
+                    .authenticate_proof(&prefix, verify)
+                    .await
+                    .context("Checking the proof of the challenge")?;
+
+                match ids {
+                    Some((customer_id, token_id)) => {
+                        info!(
+                            version,
+                            customer_id, token_id, "Client answered the challenge"
+                        );
+                        return Ok((customer_id, token_id));
+                    }
+                    None if require_challenge => {
+                        let reason = "The token has to be rotated to be used with challenges";
+                        prot::write_msg(stream, out_buf, &Message::BadRequest { reason }).await?;
+                        let err = anyhow::anyhow!("Token has no stored key");
+                        return Err(prot::Error::Other(err));
+                    }
+                    None => {
+                        let reason = handshake::NO_STORED_KEY;
+                        prot::write_msg(stream, out_buf, &Message::BadRequest { reason }).await?;
+                    }
+                }
+            }
+            Message::Register {
+                username,
+                password,
+                device,
+            } => {
+                let valid = account::validate_username(username)
+                    .and(account::validate_password(password))
+                    .and(account::validate_device(device));
+                if let Err(reason) = valid {
+                    prot::write_msg(stream, out_buf, &Message::BadRequest { reason }).await?;
+                    continue;
+                }
+
+                let password = password.to_owned();
+                let password_hash = task::spawn_blocking(move || account::hash_password(&password))
+                    .await
+                    .context("Hashing the password")??;
+                let token = pg_client
+                    .register(hasher, username, &password_hash, device)
+                    .await
+                    .context("Registering the customer")?;
+
+                let Some(token) = token else {
+                    let reason = "The username is taken";
+                    prot::write_msg(stream, out_buf, &Message::BadRequest { reason }).await?;
+                    continue;
+                };
+                info!(username, device, "Customer registered");
+                prot::write_msg(stream, out_buf, &Message::IssuedToken { token: &token }).await?;
+            }
+            Message::Login {
+                username,
+                password,
+                device,
+            } => {
+                if let Err(reason) = account::validate_device(device) {
+                    prot::write_msg(stream, out_buf, &Message::BadRequest { reason }).await?;
+                    continue;
+                }
+
+                let login = pg_client
+                    .find_login(username)
+                    .await
+                    .context("Fetching the login of the customer")?;
+                let (customer_id, password_hash) = match login {
+                    Some((customer_id, password_hash)) => (Some(customer_id), password_hash),
+                    None => (None, account::DUMMY_HASH.to_owned()),
+                };
+                let password = password.to_owned();
+                let verified = task::spawn_blocking(move || {
+                    account::verify_password(&password, &password_hash)
+                })
+                .await
+                .context("Verifying the password")?;
+                // Whether the username exists isn't given away, neither by the reply nor
+                // by how long it takes, and the connection is closed so that passwords
+                // can't be guessed on it.
+                let Some(customer_id) = customer_id.filter(|_| verified) else {
+                    let reason = "Wrong username or password";
+                    prot::write_msg(stream, out_buf, &Message::BadRequest { reason }).await?;
+                    let err = anyhow::anyhow!("Client failed to log in as {username}");
+                    return Err(prot::Error::Other(err));
+                };
+
+                let (_, token) = pg_client
+                    .issue_token(hasher, customer_id, device, None)
+                    .await
+                    .context("Issuing a token")?;
+                info!(customer_id, device, "Customer logged in");
+                prot::write_msg(stream, out_buf, &Message::IssuedToken { token: &token }).await?;
+            }
+            _ => {
+                let err = anyhow::anyhow!("First message was not handshake");
+                return Err(prot::Error::Other(err));
+            }
+        }
+    }
+}
+
+// Replies to a request that would edit a copy of a shared deck, or whose check failed,
+// and returns whether it was refused.
+async fn refuse_read_only(
+    stream: &mut TcpStream,
+    out_buf: &mut [u8],
+    read_only: anyhow::Result<bool>,
+) -> prot::Result<bool> {
+    match read_only {
+        Ok(false) => Ok(false),
+        Ok(true) => {
+            let resp = Message::BadRequest {
+                reason: "The deck follows a shared deck and can't be edited, fork it first",
+            };
+            prot::write_msg(stream, out_buf, &resp).await?;
+            Ok(true)
+        }
+        Err(err) => {
+            error!(?err, "Error checking whether the QAs can be edited");
+            prot::write_msg(stream, out_buf, &Message::InternalError).await?;
+            Ok(true)
+        }
+    }
+}
+
+// Replies to a request the customer's role doesn't allow, and returns whether it was
+// refused.
+async fn refuse_role(
+    stream: &mut TcpStream,
+    out_buf: &mut [u8],
+    role: Role,
+    required: Role,
+) -> prot::Result<bool> {
+    if role >= required {
+        return Ok(false);
+    }
+    let reason = match required {
+        Role::Admin => "Only admins can do that",
+        _ => "Only teachers can do that",
+    };
+    prot::write_msg(stream, out_buf, &Message::BadRequest { reason }).await?;
+    Ok(true)
+}
+
+// Replies to a request only the teacher of the class can make, if the customer isn't
+// them or the check failed, and returns whether it was refused.
+async fn refuse_unless_teacher(
+    stream: &mut TcpStream,
+    out_buf: &mut [u8],
+    role: Role,
+    teaches: anyhow::Result<bool>,
+) -> prot::Result<bool> {
+    if refuse_role(stream, out_buf, role, Role::Teacher).await? {
+        return Ok(true);
+    }
+    match teaches {
+        Ok(true) => Ok(false),
+        Ok(false) => {
+            let resp = Message::BadRequest {
+                reason: "Only the teacher of the class can do that",
+            };
+            prot::write_msg(stream, out_buf, &resp).await?;
+            Ok(true)
+        }
+        Err(err) => {
+            error!(
+                ?err,
+                "Error checking whether the customer teaches the class"
+            );
+            prot::write_msg(stream, out_buf, &Message::InternalError).await?;
+            Ok(true)
+        }
+    }
+}
+
+// Passes the edits to the decks on to their subscribers, if they're shared. The edits
+// have been made either way, so errors are only logged.
+async fn sync_shared(pg_client: &PgClient, deck_ids: &[i64]) {
+    if let Err(err) = pg_client.sync_shared_decks(deck_ids).await {
+        error!(?err, "Error syncing shared decks");
+    }
+}
+
+// Counts an open session for the server's stats until it's dropped.
+struct Session(Arc<AtomicU32>);
+
+impl Session {
+    fn open(sessions: Arc<AtomicU32>) -> Self {
+        sessions.fetch_add(1, Ordering::Relaxed);
+        Self(sessions)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Completes once the token is revoked. If revocations were missed because the session
+// fell behind, whether the token was among them is looked up.
+async fn revoked(revoked_rx: &mut broadcast::Receiver<i64>, pg_client: &PgClient, token_id: i64) {
+    loop {
+        match revoked_rx.recv().await {
+            Ok(id) if id == token_id => return,
+            Ok(_) => (),
+            Err(RecvError::Lagged(_)) => match pg_client.token_revoked(token_id).await {
+                Ok(true) => return,
+                Ok(false) => (),
+                Err(err) => error!(?err, "Error checking whether the token was revoked"),
+            },
+            Err(RecvError::Closed) => return future::pending().await,
         }
     }
 }
@@ -0,0 +1,194 @@
+use message::{MixOrder, MultipleChoice, QuizOrder, QA};
+
+// Group a quiz candidate was picked from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Group {
+    // Due (re)learning steps, always shown first.
+    Learning,
+    Review,
+    New,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub qa: QA,
+    pub group: Group,
+}
+
+// Arranges the candidates, each group already sorted according to the order, into
+// the final quiz. Learning steps come first, the rest are mixed as the settings say
+// unless the order asks for interleaving or shuffling.
+pub fn arrange(candidates: Vec<Candidate>, order: QuizOrder, mix: MixOrder) -> Vec<QA> {
+    let mut learning = Vec::new();
+    let mut reviews = Vec::new();
+    let mut new = Vec::new();
+    for c in candidates {
+        match c.group {
+            Group::Learning => learning.push(c.qa),
+            Group::Review => reviews.push(c.qa),
+            Group::New => new.push(c.qa),
+        }
+    }
+
+    let mix = match order {
+        QuizOrder::Interleaved => MixOrder::Mixed,
+        _ => mix,
+    };
+    let mut rest = match mix {
+        MixOrder::Mixed => interleave(reviews, new),
+        MixOrder::NewFirst => new.into_iter().chain(reviews).collect(),
+        MixOrder::ReviewsFirst => reviews.into_iter().chain(new).collect(),
+    };
+
+    if let QuizOrder::Random { seed } = order {
+        shuffle(&mut rest, seed);
+    }
+
+    learning.append(&mut rest);
+    learning
+}
+
+fn interleave(a: Vec<QA>, b: Vec<QA>) -> Vec<QA> {
+    let mut res = Vec::with_capacity(a.len() + b.len());
+    let mut a = a.into_iter();
+    let mut b = b.into_iter();
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return res,
+            (x, y) => res.extend(x.into_iter().chain(y)),
+        }
+    }
+}
+
+// Puts the answer among the distractors at a position picked by the seed.
+pub fn multiple_choice(qa: QA, distractors: Vec<String>, seed: u64) -> MultipleChoice {
+    let mut options = distractors;
+    options.push(qa.a);
+    let last = options.len() - 1;
+    let answer = (seed % options.len() as u64) as usize;
+    options.swap(answer, last);
+
+    MultipleChoice {
+        id: qa.id,
+        q: qa.q,
+        options,
+        answer: answer as u8,
+    }
+}
+
+// Fisher-Yates shuffle driven by SplitMix64, so that the same seed always gives the
+// same order regardless of platform or dependency versions.
+pub fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    for i in (1..items.len()).rev() {
+        let j = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: i64, group: Group) -> Candidate {
+        Candidate {
+            qa: QA {
+                id,
+                ..Default::default()
+            },
+            group,
+        }
+    }
+
+    fn ids(qas: &[QA]) -> Vec<i64> {
+        qas.iter().map(|qa| qa.id).collect()
+    }
+
+    fn candidates() -> Vec<Candidate> {
+        vec![
+            candidate(1, Group::Review),
+            candidate(2, Group::Review),
+            candidate(3, Group::Review),
+            candidate(4, Group::New),
+            candidate(5, Group::New),
+            candidate(6, Group::Learning),
+        ]
+    }
+
+    #[test]
+    fn test_arrange_mix() {
+        let quiz = arrange(candidates(), QuizOrder::Newest, MixOrder::Mixed);
+        assert_eq!(ids(&quiz), vec![6, 1, 4, 2, 5, 3]);
+
+        let quiz = arrange(candidates(), QuizOrder::Newest, MixOrder::NewFirst);
+        assert_eq!(ids(&quiz), vec![6, 4, 5, 1, 2, 3]);
+
+        let quiz = arrange(candidates(), QuizOrder::OldestFirst, MixOrder::ReviewsFirst);
+        assert_eq!(ids(&quiz), vec![6, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_arrange_interleaved_ignores_mix() {
+        let quiz = arrange(candidates(), QuizOrder::Interleaved, MixOrder::NewFirst);
+        assert_eq!(ids(&quiz), vec![6, 1, 4, 2, 5, 3]);
+    }
+
+    #[test]
+    fn test_arrange_random() {
+        let order = QuizOrder::Random { seed: 42 };
+        let quiz = arrange(candidates(), order, MixOrder::Mixed);
+
+        // Learning steps stay first, the rest are shuffled the same way every time.
+        assert_eq!(quiz[0].id, 6);
+        assert_eq!(quiz, arrange(candidates(), order, MixOrder::Mixed));
+
+        let mut shuffled = ids(&quiz[1..]);
+        shuffled.sort();
+        assert_eq!(shuffled, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_multiple_choice() {
+        let qa = QA {
+            id: 1,
+            q: "hund".to_owned(),
+            a: "dog".to_owned(),
+        };
+        let distractors = vec!["cat".to_owned(), "cow".to_owned(), "pig".to_owned()];
+
+        for seed in 0..8 {
+            let choices = multiple_choice(qa.clone(), distractors.clone(), seed);
+            assert_eq!(choices.id, 1);
+            assert_eq!(choices.options.len(), 4);
+            assert_eq!(choices.answer as u64, seed % 4);
+            assert_eq!(choices.options[choices.answer as usize], "dog");
+        }
+
+        let choices = multiple_choice(qa, vec![], 5);
+        assert_eq!(choices.options, vec!["dog"]);
+        assert_eq!(choices.answer, 0);
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let mut items: Vec<u32> = (0..10).collect();
+        shuffle(&mut items, 7);
+        assert_eq!(items, vec![8, 1, 5, 9, 0, 4, 3, 2, 6, 7]);
+
+        let mut other: Vec<u32> = (0..10).collect();
+        shuffle(&mut other, 8);
+        assert_ne!(items, other);
+
+        let mut empty: Vec<u32> = vec![];
+        shuffle(&mut empty, 7);
+        assert!(empty.is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+use message::Settings;
+
+// Steps are the delays (in seconds) after which a QA that's being (re)learned
+// is shown again within the same day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Steps<'a> {
+    pub learning: &'a [u32],
+    pub relearning: &'a [u32],
+}
+
+impl<'a> From<&'a Settings> for Steps<'a> {
+    fn from(settings: &'a Settings) -> Self {
+        Self {
+            learning: &settings.learning_steps,
+            relearning: &settings.relearning_steps,
+        }
+    }
+}
+
+// Where a QA is in its schedule before being reviewed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct State {
+    // Index into the learning or relearning steps, None when the QA isn't being learned.
+    pub step: Option<i16>,
+    // A QA that has graduated at least once goes through the relearning steps when
+    // it fails, otherwise through the learning steps.
+    pub graduated: bool,
+    // Whether the QA has never been reviewed.
+    pub new: bool,
+}
+
+// Kind of a review, as stored in review_log.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    New = 0,
+    Learning = 1,
+    Review = 2,
+    Relearning = 3,
+    // Reviews in practice quizzes, which don't affect scheduling.
+    Practice = 4,
+}
+
+impl State {
+    pub fn kind(&self) -> Kind {
+        match (self.step, self.graduated) {
+            (None, _) if self.new => Kind::New,
+            (None, _) => Kind::Review,
+            (Some(_), false) => Kind::Learning,
+            (Some(_), true) => Kind::Relearning,
+        }
+    }
+}
+
+// How well a QA was recalled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Answer {
+    Wrong,
+    // Correct, but slow enough to suggest the QA isn't learned yet.
+    Hard,
+    Good,
+}
+
+impl Answer {
+    // Correct answers that took longer than hard_after_secs are Hard. A zero
+    // threshold or an unknown response time never makes an answer Hard.
+    pub fn grade(correct: bool, response_ms: Option<u32>, hard_after_secs: u16) -> Self {
+        match response_ms {
+            _ if !correct => Answer::Wrong,
+            Some(ms) if hard_after_secs > 0 && ms > hard_after_secs as u32 * 1000 => Answer::Hard,
+            _ => Answer::Good,
+        }
+    }
+}
+
+// Where a QA is in its schedule after being reviewed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Next {
+    // Show the QA again in `due_in` seconds.
+    Step { step: i16, due_in: u32 },
+    // The QA leaves the (re)learning steps and goes back to daily reviews. `learned`
+    // is true when it counts as a correct review.
+    Graduate { learned: bool },
+}
+
+impl Steps<'_> {
+    pub fn next(&self, state: State, answer: Answer) -> Next {
+        let steps = if state.graduated {
+            self.relearning
+        } else {
+            self.learning
+        };
+
+        match (state.step, answer) {
+            // A failed QA starts over from the first step. Without any steps it waits
+            // until the next day like before.
+            (_, Answer::Wrong) => match steps.first() {
+                Some(&due_in) => Next::Step { step: 0, due_in },
+                None => Next::Graduate { learned: false },
+            },
+            // A hard review doesn't count as learned. A graduated QA is left for the
+            // next day, a new one starts at the first step and one in the steps
+            // repeats its current step.
+            (None, Answer::Hard) if state.graduated => Next::Graduate { learned: false },
+            (None, Answer::Hard) => repeat(steps, 0),
+            (Some(step), Answer::Hard) => repeat(steps, step),
+            // A new QA answered correctly skips the first step, the one it would've
+            // been put in had it failed. A graduated one is just a regular review.
+            (None, Answer::Good) if state.graduated => Next::Graduate { learned: true },
+            (None, Answer::Good) => advance(steps, 0, true),
+            (Some(step), Answer::Good) => advance(steps, step, !state.graduated),
+        }
+    }
+}
+
+fn repeat(steps: &[u32], step: i16) -> Next {
+    match steps.get(step as usize) {
+        Some(&due_in) => Next::Step { step, due_in },
+        None => Next::Graduate { learned: false },
+    }
+}
+
+fn advance(steps: &[u32], step: i16, learned: bool) -> Next {
+    let next = step + 1;
+    match steps.get(next as usize) {
+        Some(&due_in) => Next::Step { step: next, due_in },
+        None => Next::Graduate { learned },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEPS: Steps = Steps {
+        learning: &[60, 600],
+        relearning: &[600],
+    };
+    const NEW: State = State {
+        step: None,
+        graduated: false,
+        new: true,
+    };
+    const REVIEW: State = State {
+        step: None,
+        graduated: true,
+        new: false,
+    };
+
+    #[test]
+    fn test_learning() {
+        assert_eq!(
+            STEPS.next(NEW, Answer::Wrong),
+            Next::Step {
+                step: 0,
+                due_in: 60
+            }
+        );
+        assert_eq!(
+            STEPS.next(NEW, Answer::Good),
+            Next::Step {
+                step: 1,
+                due_in: 600
+            }
+        );
+
+        let first = State {
+            step: Some(0),
+            graduated: false,
+            new: false,
+        };
+        assert_eq!(
+            STEPS.next(first, Answer::Good),
+            Next::Step {
+                step: 1,
+                due_in: 600
+            }
+        );
+
+        let last = State {
+            step: Some(1),
+            graduated: false,
+            new: false,
+        };
+        assert_eq!(
+            STEPS.next(last, Answer::Good),
+            Next::Graduate { learned: true }
+        );
+        assert_eq!(
+            STEPS.next(last, Answer::Wrong),
+            Next::Step {
+                step: 0,
+                due_in: 60
+            }
+        );
+    }
+
+    #[test]
+    fn test_relearning() {
+        assert_eq!(
+            STEPS.next(REVIEW, Answer::Good),
+            Next::Graduate { learned: true }
+        );
+        assert_eq!(
+            STEPS.next(REVIEW, Answer::Wrong),
+            Next::Step {
+                step: 0,
+                due_in: 600
+            }
+        );
+
+        let relearning = State {
+            step: Some(0),
+            graduated: true,
+            new: false,
+        };
+        assert_eq!(
+            STEPS.next(relearning, Answer::Good),
+            Next::Graduate { learned: false }
+        );
+    }
+
+    #[test]
+    fn test_no_steps() {
+        let steps = Steps {
+            learning: &[],
+            relearning: &[],
+        };
+
+        assert_eq!(
+            steps.next(NEW, Answer::Good),
+            Next::Graduate { learned: true }
+        );
+        assert_eq!(
+            steps.next(NEW, Answer::Wrong),
+            Next::Graduate { learned: false }
+        );
+        assert_eq!(
+            steps.next(REVIEW, Answer::Wrong),
+            Next::Graduate { learned: false }
+        );
+    }
+
+    #[test]
+    fn test_hard() {
+        assert_eq!(
+            STEPS.next(NEW, Answer::Hard),
+            Next::Step {
+                step: 0,
+                due_in: 60
+            }
+        );
+        assert_eq!(
+            STEPS.next(REVIEW, Answer::Hard),
+            Next::Graduate { learned: false }
+        );
+
+        let second = State {
+            step: Some(1),
+            graduated: false,
+            new: false,
+        };
+        assert_eq!(
+            STEPS.next(second, Answer::Hard),
+            Next::Step {
+                step: 1,
+                due_in: 600
+            }
+        );
+
+        let steps = Steps {
+            learning: &[],
+            relearning: &[],
+        };
+        assert_eq!(
+            steps.next(NEW, Answer::Hard),
+            Next::Graduate { learned: false }
+        );
+    }
+
+    #[test]
+    fn test_grade() {
+        assert_eq!(Answer::grade(false, Some(1000), 10), Answer::Wrong);
+        assert_eq!(Answer::grade(true, Some(10_000), 10), Answer::Good);
+        assert_eq!(Answer::grade(true, Some(10_001), 10), Answer::Hard);
+        assert_eq!(Answer::grade(true, Some(60_000), 0), Answer::Good);
+        assert_eq!(Answer::grade(true, None, 10), Answer::Good);
+    }
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(NEW.kind(), Kind::New);
+        assert_eq!(REVIEW.kind(), Kind::Review);
+
+        let learning = State {
+            step: Some(1),
+            graduated: false,
+            new: false,
+        };
+        assert_eq!(learning.kind(), Kind::Learning);
+
+        let relearning = State {
+            step: Some(0),
+            graduated: true,
+            new: false,
+        };
+        assert_eq!(relearning.kind(), Kind::Relearning);
+    }
+}
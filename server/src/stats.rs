@@ -0,0 +1,106 @@
+// Days are numbered relative to the customer's current day: 0 is today, -1 yesterday
+// and 1 tomorrow.
+
+// Spreads (day, count) rows over `len` days starting at day `first`. Rows outside of
+// that range are dropped.
+pub fn per_day(rows: impl IntoIterator<Item = (i32, i64)>, first: i32, len: usize) -> Vec<u32> {
+    let mut counts = vec![0; len];
+    for (day, count) in rows {
+        let Ok(idx) = usize::try_from(day - first) else {
+            continue;
+        };
+        if let Some(c) = counts.get_mut(idx) {
+            *c += count as u32;
+        }
+    }
+    counts
+}
+
+// Returns the current and longest streak given the distinct days with reviews, most
+// recent first. A streak that reached yesterday is still current since there's time
+// left to review today.
+pub fn streaks(days: &[i32]) -> (u32, u32) {
+    let mut first_run = None;
+    let mut longest = 0;
+    let mut run = 0;
+    let mut prev = None;
+
+    for &day in days {
+        if prev == Some(day + 1) {
+            run += 1;
+        } else {
+            if prev.is_some() && first_run.is_none() {
+                first_run = Some(run);
+            }
+            run = 1;
+        }
+        longest = longest.max(run);
+        prev = Some(day);
+    }
+
+    let current = match days.first() {
+        Some(&day) if day >= -1 => first_run.unwrap_or(run),
+        _ => 0,
+    };
+    (current, longest)
+}
+
+// Reviews whose response time wasn't measured are counted for the gap since the
+// previous review, capped so that breaks aren't counted.
+const MAX_GAP_SECS: f64 = 60.0;
+
+// Returns the number of seconds spent on the (time, response time) reviews, ordered by
+// time in seconds. Reviews are counted for their response time, or else for the gap
+// since the previous one. The first review has no gap, so it only counts if its response
+// time was measured.
+pub fn time_spent(reviews: impl IntoIterator<Item = (f64, Option<i32>)>) -> u32 {
+    let mut secs = 0.0;
+    let mut prev: Option<f64> = None;
+    for (at, response_ms) in reviews {
+        secs += match (response_ms, prev) {
+            (Some(ms), _) => ms as f64 / 1000.0,
+            (None, Some(prev)) => (at - prev).min(MAX_GAP_SECS),
+            (None, None) => 0.0,
+        };
+        prev = Some(at);
+    }
+    secs as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_day() {
+        let rows = vec![(-2, 3), (0, 1), (-5, 7), (1, 4)];
+        assert_eq!(per_day(rows, -2, 3), vec![3, 0, 1]);
+        assert_eq!(per_day(vec![], 0, 2), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_streaks() {
+        assert_eq!(streaks(&[]), (0, 0));
+        assert_eq!(streaks(&[0]), (1, 1));
+        assert_eq!(streaks(&[-1, -2]), (2, 2));
+        assert_eq!(streaks(&[0, -1, -2, -5, -6, -7, -8]), (3, 4));
+        assert_eq!(streaks(&[-2, -3]), (0, 2));
+        assert_eq!(streaks(&[0, -2, -3, -4]), (1, 3));
+    }
+
+    #[test]
+    fn test_time_spent() {
+        assert_eq!(time_spent(vec![]), 0);
+        // A single quick review isn't counted for the cap.
+        assert_eq!(time_spent(vec![(100.0, None)]), 0);
+        assert_eq!(time_spent(vec![(100.0, Some(2500))]), 2);
+        // The gap after a break is capped, measured response times aren't.
+        let reviews = vec![
+            (100.0, None),
+            (110.0, None),
+            (1000.0, None),
+            (1005.0, Some(90_000)),
+        ];
+        assert_eq!(time_spent(reviews), 10 + 60 + 90);
+    }
+}
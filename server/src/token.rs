@@ -0,0 +1,103 @@
+use std::env;
+
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::Rng;
+use sha2::Sha256;
+
+pub use prot::handshake::{prefix, stored_key, PREFIX_LEN};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Channel the id of a revoked token, or a token of a disabled customer, is sent on so
+// that the sessions using it are closed.
+pub const REVOKED_CHANNEL: &str = "token_revoked";
+
+// Key used when TOKEN_KEY isn't set and DEV_TOKEN_KEY=1, which is only fit for
+// development.
+const DEV_KEY: &str = "memryze-dev-token-key";
+
+// Hashes tokens with a key that's kept out of the database, so that the hashes can't be
+// brute-forced by whoever gets hold of them alone.
+#[derive(Clone)]
+pub struct TokenHasher {
+    key: Vec<u8>,
+}
+
+impl TokenHasher {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+
+    // Reads the key from TOKEN_KEY. The server, the db migration and the admin tool have
+    // to be given the same one. Without it they fail, unless DEV_TOKEN_KEY=1 asks for the
+    // development key, since tokens hashed with the wrong key can't be used again.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match env::var("TOKEN_KEY") {
+            Ok(key) if !key.is_empty() => Ok(Self::new(key.as_bytes())),
+            _ if env::var("DEV_TOKEN_KEY").is_ok_and(|v| v == "1") => {
+                Ok(Self::new(DEV_KEY.as_bytes()))
+            }
+            _ => {
+                anyhow::bail!("TOKEN_KEY isn't set, set DEV_TOKEN_KEY=1 to use the development key")
+            }
+        }
+    }
+
+    pub fn is_dev(&self) -> bool {
+        self.key == DEV_KEY.as_bytes()
+    }
+
+    pub fn hash(&self, token: &str) -> Vec<u8> {
+        self.mac(token).finalize().into_bytes().to_vec()
+    }
+
+    // Compares the hash of the token with the stored one in constant time.
+    pub fn verify(&self, token: &str, hash: &[u8]) -> bool {
+        self.mac(token).verify_slice(hash).is_ok()
+    }
+
+    fn mac(&self, token: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(token.as_bytes());
+        mac
+    }
+}
+
+// A new random token, as 64 hex characters.
+pub fn generate() -> String {
+    let token: [u8; 32] = OsRng.gen();
+    hex_encode(&token)
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    const HEX_CHARS: &[u8] = b"0123456789ABCDEF";
+    let mut hex_string = String::with_capacity(bytes.len() * 2);
+
+    for &byte in bytes {
+        hex_string.push(HEX_CHARS[(byte >> 4) as usize] as char);
+        hex_string.push(HEX_CHARS[(byte & 0x0f) as usize] as char);
+    }
+
+    hex_string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        let hasher = TokenHasher::new(b"key");
+        let hash = hasher.hash("17bd4593ade7");
+
+        assert_eq!(hash.len(), 32);
+        assert!(hasher.verify("17bd4593ade7", &hash));
+        assert!(!hasher.verify("17bd4593ade8", &hash));
+        assert!(!hasher.verify("17bd4593ade7", &hash[..16]));
+        assert!(!TokenHasher::new(b"other").verify("17bd4593ade7", &hash));
+
+        assert_eq!(prefix("17bd4593ade7"), "17bd4593");
+        assert_eq!(prefix("17bd"), "17bd");
+    }
+}